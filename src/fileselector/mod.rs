@@ -1,46 +1,107 @@
+use std::env::current_dir;
+use std::ffi::OsStr;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-use slint::ModelRc;
+use slint::{ComponentHandle, ModelRc, SharedString};
 
-/// Retrieve all image files from folder given as String
-/// Must also retrieve size of images and mime type
+use crate::io::geojson::GEOJSON_EXTENSION;
+use crate::io::import::{GPX_EXTENSION, KML_EXTENSION};
+use crate::io::BUNDLE_EXTENSION;
+use crate::FileSelector;
+
+/// Extension of project files
+pub const PROJECT_EXTENSION: &str = "mrs";
+
+/// Kind of files listed by a file selector
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFilter {
+    /// Image files, used for backgrounds and layers
+    Images,
+    /// Project files and project bundles
+    Projects,
+    /// Geographic data files
+    GeoData,
+}
+
+impl FileFilter {
+    /// Get the type description of a file if it passes the filter
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file
+    ///
+    /// # Returns
+    ///
+    /// The type of the file to be displayed, or `None` if the file is filtered out
+    fn file_type(&self, path: &Path) -> Option<String> {
+        match self {
+            FileFilter::Images => {
+                let mime = mime_guess::from_path(path).first_raw()?;
+                // Filter mime for image types
+                if mime.starts_with("image") {
+                    Some(mime.to_string())
+                } else {
+                    None
+                }
+            }
+            FileFilter::Projects => match path.extension().and_then(|ext| ext.to_str()) {
+                Some(PROJECT_EXTENSION) => Some(String::from("Project")),
+                Some(BUNDLE_EXTENSION) => Some(String::from("Project bundle")),
+                _ => None,
+            },
+            FileFilter::GeoData => match path.extension().and_then(|ext| ext.to_str()) {
+                Some(GEOJSON_EXTENSION) => Some(String::from("GeoJSON")),
+                Some(GPX_EXTENSION) => Some(String::from("GPX")),
+                Some(KML_EXTENSION) => Some(String::from("KML")),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Retrieve all files passing a filter from folder given as String
+/// Must also retrieve size of files and their type
 ///
 /// # Arguments
 ///
 /// * `folder` - A string slice that holds the path to the folder
+/// * `filter` - The filter files must pass
 ///
 /// # Returns
 ///
-/// A vector of tuples containing the image file path, file_size and mime type
-fn get_images_from_folder(folder: &str) -> Vec<(String, u64, String)> {
-    let mut images = Vec::new();
-    let paths = fs::read_dir(folder).unwrap();
-    for path in paths {
-        let path = path.unwrap().path();
+/// A vector of tuples containing the file name, file_size and file type
+fn get_files_from_folder(folder: &str, filter: FileFilter) -> Vec<(String, u64, String)> {
+    let mut files = Vec::new();
+    let paths = match fs::read_dir(folder) {
+        Ok(paths) => paths,
+        Err(e) => {
+            log::warn!("Cannot read folder {}: {:?}", folder, e);
+            return files;
+        }
+    };
+    for path in paths.flatten() {
+        let path = path.path();
 
         // Filter files
         if !path.is_file() {
             continue;
         }
 
-        let path_str = path.to_str().unwrap().to_string();
-        let file_size = fs::metadata(&path_str).unwrap().len();
-        let mime = mime_guess::from_path(&path_str).first_raw();
-        if mime.is_none() {
+        let Some(file_type) = filter.file_type(&path) else {
             continue;
-        }
-        let mime = mime.unwrap().to_string();
-
-        // Filter mime for image types
-        if !mime.starts_with("image") {
-            continue;
-        }
+        };
+        let file_size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
 
         // Extract filename from path
-        let path_str = String::from(path_str.split(std::path::MAIN_SEPARATOR).last().unwrap());
-        images.push((path_str, file_size, mime));
+        let Some(filename) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        files.push((String::from(filename), file_size, file_type));
     }
-    images
+    files.sort();
+    files
 }
 
 /// Retrieve all folders from folder given as String
@@ -54,16 +115,23 @@ fn get_images_from_folder(folder: &str) -> Vec<(String, u64, String)> {
 /// A vector of string slices containing the folder names
 fn get_folders_from_folder(folder: &str) -> Vec<String> {
     let mut folders = Vec::new();
-    let paths = fs::read_dir(folder).unwrap();
-    for path in paths {
-        let path = path.unwrap().path();
-        let path_str = path.to_str().unwrap().to_string();
+    let paths = match fs::read_dir(folder) {
+        Ok(paths) => paths,
+        Err(e) => {
+            log::warn!("Cannot read folder {}: {:?}", folder, e);
+            return folders;
+        }
+    };
+    for path in paths.flatten() {
+        let path = path.path();
         if path.is_dir() {
             // Extract folder name from path
-            let path_str = String::from(path_str.split(std::path::MAIN_SEPARATOR).last().unwrap());
-            folders.push(path_str);
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                folders.push(String::from(name));
+            }
         }
     }
+    folders.sort();
     folders
 }
 
@@ -93,17 +161,18 @@ fn format_size(size: u64) -> String {
 
 pub fn get_slint_files_from_folder(
     folder: &str,
+    filter: FileFilter,
 ) -> slint::ModelRc<ModelRc<slint::StandardListViewItem>> {
-    let images = get_images_from_folder(folder);
+    let images = get_files_from_folder(folder, filter);
     let files = slint::VecModel::from(
         images
             .iter()
             .map(|image| {
-                let filename = format!("{}", image.0);
+                let filename = image.0.to_string();
                 let filename = slint::SharedString::from(filename.as_str());
                 let size = format_size(image.1);
                 let size = slint::SharedString::from(size.as_str());
-                let mime = format!("{}", image.2);
+                let mime = image.2.to_string();
                 let mime = slint::SharedString::from(mime.as_str());
 
                 slint::ModelRc::new(slint::VecModel::from(vec![
@@ -115,14 +184,13 @@ pub fn get_slint_files_from_folder(
             .collect::<Vec<slint::ModelRc<slint::StandardListViewItem>>>(),
     );
 
-    let files = slint::VecModel::from(files);
-    let files = slint::ModelRc::new(files);
-
-    files
+    slint::ModelRc::new(files)
 }
 
 pub fn get_slint_folders_from_folder(path: &str) -> slint::ModelRc<slint::StandardListViewItem> {
-    let mut folders = vec![slint::StandardListViewItem::from(slint::SharedString::from(".."))];
+    let mut folders = vec![slint::StandardListViewItem::from(
+        slint::SharedString::from(".."),
+    )];
     let folder_lists = get_folders_from_folder(path);
     let folder_lists = folder_lists
         .iter()
@@ -130,7 +198,209 @@ pub fn get_slint_folders_from_folder(path: &str) -> slint::ModelRc<slint::Standa
         .collect::<Vec<slint::StandardListViewItem>>();
     folders.extend(folder_lists);
     let folders = slint::VecModel::from(folders);
-    let folders = slint::ModelRc::new(folders);
+    slint::ModelRc::new(folders)
+}
 
-    folders
+/// Retrieve the path of the file selected in a file selector
+///
+/// # Arguments
+///
+/// * `file_selector` - The file selector
+///
+/// # Returns
+///
+/// The full path of the selected file, or `None` if no file name was entered
+pub fn selected_path(file_selector: &FileSelector) -> Option<PathBuf> {
+    let filename = file_selector.get_filename().to_string();
+    let filename = filename.trim();
+    if filename.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(file_selector.get_path().as_str()).join(filename))
+}
+
+/// Add an extension to a path if it has not the expected one already
+///
+/// # Arguments
+///
+/// * `path` - The path of the file
+/// * `extension` - The expected extension, without the leading dot
+pub fn with_extension(path: PathBuf, extension: &str) -> PathBuf {
+    if path.extension().and_then(|ext| ext.to_str()) == Some(extension) {
+        path
+    } else {
+        let mut path = path.into_os_string();
+        path.push(".");
+        path.push(extension);
+        PathBuf::from(path)
+    }
+}
+
+/// Create and show a file selector browsing the current directory
+///
+/// Folder navigation, cancellation and image previews are handled here, the caller
+/// only has to handle the `send_ok` callback.
+///
+/// # Arguments
+///
+/// * `title` - The title of the dialog
+/// * `filter` - The kind of files to be listed
+/// * `save_mode` - Whether the dialog is used to pick a file to write to
+pub fn show_file_selector(
+    title: &str,
+    filter: FileFilter,
+    save_mode: bool,
+) -> Result<FileSelector, slint::PlatformError> {
+    let file_selector = FileSelector::new()?;
+    file_selector.set_dialog_title(SharedString::from(title));
+    file_selector.set_save_mode(save_mode);
+    file_selector.set_show_preview(filter == FileFilter::Images);
+    file_selector.show()?;
+    let file_selector_weak = file_selector.as_weak();
+
+    if file_selector.get_path().is_empty() {
+        let path = current_dir().unwrap_or_default();
+        file_selector.set_path(SharedString::from(path.to_string_lossy().as_ref()));
+    }
+
+    let path = file_selector.get_path().to_string();
+
+    let folders = get_slint_folders_from_folder(&path);
+    file_selector.set_folders(folders);
+
+    let files = get_slint_files_from_folder(&path, filter);
+    file_selector.set_files(files);
+
+    file_selector.on_send_cancel({
+        let ui_fs = file_selector_weak.clone();
+        move || {
+            if let Some(ui_fs) = ui_fs.upgrade() {
+                let _ = ui_fs.hide();
+            }
+        }
+    });
+
+    file_selector.on_set_folder({
+        let ui_fs = file_selector_weak.clone();
+        move || {
+            let Some(ui_fs) = ui_fs.upgrade() else {
+                return;
+            };
+            let parent_path = ui_fs.get_path().to_string();
+            let parent_path = PathBuf::from(&parent_path);
+            let child_path = ui_fs.get_current_folder().to_string();
+
+            let parent_path = parent_path.join(child_path);
+            let parent_path = match parent_path.canonicalize() {
+                Ok(path) => path,
+                Err(e) => {
+                    log::warn!("Cannot open folder {:?}: {:?}", parent_path, e);
+                    return;
+                }
+            };
+
+            let parent_path = parent_path.to_string_lossy();
+
+            ui_fs.set_path(SharedString::from(parent_path.as_ref()));
+            ui_fs.set_overwrite_prompt(false);
+
+            let files = get_slint_files_from_folder(&parent_path, filter);
+            ui_fs.set_files(files);
+
+            let folders = get_slint_folders_from_folder(&parent_path);
+            ui_fs.set_folders(folders);
+        }
+    });
+
+    file_selector.on_load_preview({
+        let ui_fs = file_selector_weak.clone();
+        move || {
+            let Some(ui) = ui_fs.upgrade() else {
+                return;
+            };
+            ui.set_overwrite_prompt(false);
+            if filter != FileFilter::Images {
+                return;
+            }
+            let Some(image_path) = selected_path(&ui) else {
+                return;
+            };
+            log::debug!("Entering clossure");
+            let ui_fs = ui_fs.clone();
+            let _thread = std::thread::spawn(move || {
+                log::debug!("Entering thread");
+                let _ = slint::invoke_from_event_loop(move || {
+                    log::debug!("Entering invoke_from_event_loop");
+                    let Some(ui) = ui_fs.upgrade() else {
+                        return;
+                    };
+                    log::debug!("Doing it");
+                    match slint::Image::load_from_path(image_path.as_path()) {
+                        Ok(image) => {
+                            ui.set_preview(image);
+                            log::debug!("Loading preview image")
+                        }
+                        Err(e) => {
+                            log::warn!("Error loading image: {:?}", e);
+                        }
+                    }
+                });
+            });
+        }
+    });
+
+    Ok(file_selector)
+}
+
+/// Create and show a file selector to choose the file a document is written to
+///
+/// The expected extension is appended to the entered file name if missing, and the user
+/// is asked for confirmation before an existing file is replaced.
+///
+/// # Arguments
+///
+/// * `title` - The title of the dialog
+/// * `filter` - The kind of files to be listed
+/// * `extension` - The extension of the written file, without the leading dot
+/// * `default_name` - The file name suggested to the user, if any
+/// * `save` - Writes the file to the given path, returning whether it succeeded. The
+///   dialog is closed once the file is written.
+pub fn show_save_selector(
+    title: &str,
+    filter: FileFilter,
+    extension: &'static str,
+    default_name: Option<&OsStr>,
+    save: impl Fn(&Path) -> bool + 'static,
+) -> Result<(), slint::PlatformError> {
+    let file_selector = show_file_selector(title, filter, true)?;
+    if let Some(name) = default_name {
+        let name = with_extension(PathBuf::from(name), extension);
+        file_selector.set_filename(SharedString::from(name.to_string_lossy().as_ref()));
+    }
+
+    let file_selector_weak = file_selector.as_weak();
+    let save = Rc::new(move |check_overwrite: bool| {
+        let Some(ui_fs) = file_selector_weak.upgrade() else {
+            return;
+        };
+        let Some(path) = selected_path(&ui_fs) else {
+            return;
+        };
+        let path = with_extension(path, extension);
+        if check_overwrite && path.exists() {
+            ui_fs.set_overwrite_prompt(true);
+            return;
+        }
+        ui_fs.set_overwrite_prompt(false);
+        if save(&path) {
+            let _ = ui_fs.hide();
+        }
+    });
+
+    file_selector.on_send_ok({
+        let save = save.clone();
+        move || save(true)
+    });
+    file_selector.on_confirm_overwrite(move || save(false));
+    Ok(())
 }
@@ -0,0 +1,368 @@
+//! Ground control points and transform solving
+//!
+//! A georeference is computed from ground control points, pixels of the background whose
+//! real-world coordinates are known. The transform is solved by least squares, and the
+//! residual of each control point is reported so that misplaced points can be spotted.
+
+use std::fmt;
+
+use super::{AffineTransform, Coordinate};
+use crate::math::Point;
+
+/// A pixel of the background with known real-world coordinates
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct GroundControlPoint {
+    pub pixel: Point,
+    pub world: Coordinate,
+}
+
+/// Kind of transform solved from ground control points
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum TransformKind {
+    /// General affine transform, handling different scales along both axes and shearing,
+    /// which needs at least three control points
+    #[default]
+    Affine,
+    /// Similarity transform (translation, rotation and uniform scale), which needs at
+    /// least two control points
+    Helmert,
+}
+
+impl TransformKind {
+    /// Minimum number of control points needed to solve the transform
+    pub fn minimum_points(&self) -> usize {
+        match self {
+            TransformKind::Affine => 3,
+            TransformKind::Helmert => 2,
+        }
+    }
+}
+
+/// Errors raised while solving a transform
+#[derive(Debug, PartialEq)]
+pub enum SolveError {
+    /// Not enough control points for the transform kind
+    NotEnoughPoints { found: usize, needed: usize },
+    /// Control points are aligned or duplicated, the transform cannot be determined
+    Degenerate,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::NotEnoughPoints { found, needed } => write!(
+                f,
+                "{} control points are needed, only {} defined",
+                needed, found
+            ),
+            SolveError::Degenerate => write!(f, "control points are aligned or duplicated"),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+/// A solved transform and how well it fits the control points
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    pub transform: AffineTransform,
+    /// Distance between the transformed pixel and the expected coordinates of each
+    /// control point, in real-world units
+    pub residuals: Vec<f64>,
+    /// Root mean square of the residuals
+    pub rms: f64,
+}
+
+/// Solve a square linear system by Gaussian elimination with partial pivoting
+///
+/// # Returns
+///
+/// The solution, or `None` if the system is singular
+fn solve_linear<const N: usize>(mut matrix: [[f64; N]; N], mut rhs: [f64; N]) -> Option<[f64; N]> {
+    for column in 0..N {
+        let pivot = (column..N).max_by(|&i, &j| {
+            matrix[i][column]
+                .abs()
+                .total_cmp(&matrix[j][column].abs())
+        })?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+        for row in column + 1..N {
+            let factor = matrix[row][column] / matrix[column][column];
+            let pivot_row = matrix[column];
+            for (value, pivot_value) in matrix[row].iter_mut().zip(pivot_row).skip(column) {
+                *value -= factor * pivot_value;
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+    let mut solution = [0.; N];
+    for row in (0..N).rev() {
+        let sum: f64 = (row + 1..N).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - sum) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+/// Solve the transform best fitting the control points, in the least squares sense
+///
+/// Coordinates are centered on their mean before solving, which keeps the system well
+/// conditioned with projected coordinates in the millions of meters.
+///
+/// # Arguments
+///
+/// * `points` - The ground control points
+/// * `kind` - The kind of transform to be solved
+pub fn solve(points: &[GroundControlPoint], kind: TransformKind) -> Result<Solution, SolveError> {
+    if points.len() < kind.minimum_points() {
+        return Err(SolveError::NotEnoughPoints {
+            found: points.len(),
+            needed: kind.minimum_points(),
+        });
+    }
+
+    let count = points.len() as f64;
+    let pixel_x = points.iter().map(|p| p.pixel.x as f64).sum::<f64>() / count;
+    let pixel_y = points.iter().map(|p| p.pixel.y as f64).sum::<f64>() / count;
+    let world_x = points.iter().map(|p| p.world.x).sum::<f64>() / count;
+    let world_y = points.iter().map(|p| p.world.y).sum::<f64>() / count;
+    let centered = points
+        .iter()
+        .map(|p| {
+            (
+                p.pixel.x as f64 - pixel_x,
+                p.pixel.y as f64 - pixel_y,
+                p.world.x - world_x,
+                p.world.y - world_y,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let (a, b, d, e) = match kind {
+        TransformKind::Affine => {
+            // Both output axes share the same normal matrix
+            let mut normal = [[0.; 2]; 2];
+            let mut rhs_x = [0.; 2];
+            let mut rhs_y = [0.; 2];
+            for &(x, y, wx, wy) in centered.iter() {
+                normal[0][0] += x * x;
+                normal[0][1] += x * y;
+                normal[1][0] += x * y;
+                normal[1][1] += y * y;
+                rhs_x[0] += x * wx;
+                rhs_x[1] += y * wx;
+                rhs_y[0] += x * wy;
+                rhs_y[1] += y * wy;
+            }
+            let [a, b] = solve_linear(normal, rhs_x).ok_or(SolveError::Degenerate)?;
+            let [d, e] = solve_linear(normal, rhs_y).ok_or(SolveError::Degenerate)?;
+            (a, b, d, e)
+        }
+        TransformKind::Helmert => {
+            // The image y axis points down while northing points up, so the similarity is
+            // solved on the mirrored image: x' = p * x + q * y and y' = q * x - p * y
+            let mut norm = 0.;
+            let mut p = 0.;
+            let mut q = 0.;
+            for &(x, y, wx, wy) in centered.iter() {
+                norm += x * x + y * y;
+                p += x * wx - y * wy;
+                q += y * wx + x * wy;
+            }
+            if norm < 1e-12 {
+                return Err(SolveError::Degenerate);
+            }
+            let (p, q) = (p / norm, q / norm);
+            (p, q, q, -p)
+        }
+    };
+
+    let transform = AffineTransform {
+        a,
+        b,
+        c: world_x - a * pixel_x - b * pixel_y,
+        d,
+        e,
+        f: world_y - d * pixel_x - e * pixel_y,
+    };
+    if !(transform.a * transform.e - transform.b * transform.d).is_normal() {
+        return Err(SolveError::Degenerate);
+    }
+
+    let residuals = points
+        .iter()
+        .map(|p| {
+            let world = transform.apply(p.pixel);
+            ((world.x - p.world.x).powi(2) + (world.y - p.world.y).powi(2)).sqrt()
+        })
+        .collect::<Vec<f64>>();
+    let rms = (residuals.iter().map(|r| r * r).sum::<f64>() / count).sqrt();
+
+    Ok(Solution {
+        transform,
+        residuals,
+        rms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} differs from {} by more than {}",
+            actual,
+            expected,
+            tolerance
+        );
+    }
+
+    /// Control points on the corners of a square of side 100 pixels, mapped by a transform
+    /// and moved by an offset
+    fn corners(transform: &AffineTransform, offsets: [(f64, f64); 4]) -> Vec<GroundControlPoint> {
+        [(0., 0.), (100., 0.), (0., 100.), (100., 100.)]
+            .into_iter()
+            .zip(offsets)
+            .map(|((x, y), (dx, dy))| {
+                let pixel = Point { x, y };
+                let world = transform.apply(pixel);
+                GroundControlPoint {
+                    pixel,
+                    world: Coordinate {
+                        x: world.x + dx,
+                        y: world.y + dy,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn solve_linear_system() {
+        let matrix = [[2., 1., -1.], [-3., -1., 2.], [-2., 1., 2.]];
+        let solution = solve_linear(matrix, [8., -11., -3.]).unwrap();
+        assert_close(solution[0], 2., 1e-12);
+        assert_close(solution[1], 3., 1e-12);
+        assert_close(solution[2], -1., 1e-12);
+        assert!(solve_linear([[1., 2.], [2., 4.]], [1., 2.]).is_none());
+    }
+
+    #[test]
+    fn affine_fits_exact_points() {
+        let expected = AffineTransform {
+            a: 2.,
+            b: 0.5,
+            c: 700_000.,
+            d: 0.1,
+            e: -3.,
+            f: 6_600_000.,
+        };
+        let solution = solve(&corners(&expected, [(0., 0.); 4]), TransformKind::Affine).unwrap();
+        assert_close(solution.transform.a, expected.a, 1e-9);
+        assert_close(solution.transform.b, expected.b, 1e-9);
+        assert_close(solution.transform.c, expected.c, 1e-6);
+        assert_close(solution.transform.d, expected.d, 1e-9);
+        assert_close(solution.transform.e, expected.e, 1e-9);
+        assert_close(solution.transform.f, expected.f, 1e-6);
+        assert_close(solution.rms, 0., 1e-6);
+    }
+
+    #[test]
+    fn affine_residuals() {
+        let expected = AffineTransform {
+            a: 2.,
+            b: 0.,
+            c: 1000.,
+            d: 0.,
+            e: -2.,
+            f: 5000.,
+        };
+        // Offsets of alternate signs no affine transform can absorb: the fit is unchanged
+        // and every point is 1 away from it
+        let offsets = [(1., 0.), (-1., 0.), (-1., 0.), (1., 0.)];
+        let solution = solve(&corners(&expected, offsets), TransformKind::Affine).unwrap();
+        assert_close(solution.transform.a, expected.a, 1e-9);
+        assert_close(solution.transform.c, expected.c, 1e-9);
+        for residual in solution.residuals {
+            assert_close(residual, 1., 1e-9);
+        }
+        assert_close(solution.rms, 1., 1e-9);
+    }
+
+    #[test]
+    fn helmert_fits_exact_points() {
+        // Rotation and uniform scale, the image y axis pointing down
+        let expected = AffineTransform {
+            a: 2.,
+            b: 1.,
+            c: 100.,
+            d: 1.,
+            e: -2.,
+            f: 200.,
+        };
+        let solution = solve(&corners(&expected, [(0., 0.); 4]), TransformKind::Helmert).unwrap();
+        assert_close(solution.transform.a, expected.a, 1e-9);
+        assert_close(solution.transform.b, expected.b, 1e-9);
+        assert_close(solution.transform.c, expected.c, 1e-9);
+        assert_close(solution.transform.d, expected.d, 1e-9);
+        assert_close(solution.transform.e, expected.e, 1e-9);
+        assert_close(solution.transform.f, expected.f, 1e-9);
+        assert_close(solution.rms, 0., 1e-9);
+    }
+
+    #[test]
+    fn helmert_residuals() {
+        // Scales of 2 and 1 along the axes are averaged into a uniform scale of 1.5, which
+        // leaves every corner 25 pixels off along both axes, 50 pixels from the center
+        let stretched = AffineTransform {
+            a: 2.,
+            b: 0.,
+            c: 0.,
+            d: 0.,
+            e: -1.,
+            f: 0.,
+        };
+        let solution = solve(&corners(&stretched, [(0., 0.); 4]), TransformKind::Helmert).unwrap();
+        assert_close(solution.transform.a, 1.5, 1e-9);
+        assert_close(solution.transform.e, -1.5, 1e-9);
+        let expected = 25. * 2_f64.sqrt();
+        for residual in solution.residuals {
+            assert_close(residual, expected, 1e-9);
+        }
+        assert_close(solution.rms, expected, 1e-9);
+    }
+
+    #[test]
+    fn solve_errors() {
+        let transform = AffineTransform {
+            a: 1.,
+            b: 0.,
+            c: 0.,
+            d: 0.,
+            e: -1.,
+            f: 0.,
+        };
+        let points = corners(&transform, [(0., 0.); 4]);
+        assert_eq!(
+            solve(&points[..2], TransformKind::Affine),
+            Err(SolveError::NotEnoughPoints {
+                found: 2,
+                needed: 3
+            })
+        );
+        let aligned = [points[0], points[3], points[0]];
+        assert_eq!(
+            solve(&aligned, TransformKind::Affine),
+            Err(SolveError::Degenerate)
+        );
+        assert_eq!(
+            solve(&[points[0], points[0]], TransformKind::Helmert),
+            Err(SolveError::Degenerate)
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Georeferencing of the background
+//!
+//! A georeference maps pixel coordinates of the background onto real-world coordinates
+//! expressed in a coordinate reference system (CRS), identified by its EPSG code.
+
+pub mod gcp;
+pub mod projection;
+
+use crate::math::Point;
+use gcp::{GroundControlPoint, TransformKind};
+
+/// EPSG code of WGS84 longitude/latitude coordinates
+pub const WGS84: &str = "EPSG:4326";
+
+/// Mean radius of the earth, in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Real-world coordinates, easting and northing (or longitude and latitude) in the CRS units
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Coordinate {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Affine transform from pixel to real-world coordinates
+///
+/// `x' = a * x + b * y + c` and `y' = d * x + e * y + f`
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AffineTransform {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+impl AffineTransform {
+    /// Transform a pixel position into real-world coordinates
+    pub fn apply(&self, point: Point) -> Coordinate {
+        let x = point.x as f64;
+        let y = point.y as f64;
+        Coordinate {
+            x: self.a * x + self.b * y + self.c,
+            y: self.d * x + self.e * y + self.f,
+        }
+    }
+
+    /// Get the inverse transform, from real-world to pixel coordinates
+    ///
+    /// # Returns
+    ///
+    /// The inverse transform, or `None` if the transform is degenerate
+    pub fn inverse(&self) -> Option<AffineTransform> {
+        let determinant = self.a * self.e - self.b * self.d;
+        if determinant == 0. || !determinant.is_finite() {
+            return None;
+        }
+        let a = self.e / determinant;
+        let b = -self.b / determinant;
+        let d = -self.d / determinant;
+        let e = self.a / determinant;
+        Some(AffineTransform {
+            a,
+            b,
+            c: -(a * self.c + b * self.f),
+            d,
+            e,
+            f: -(d * self.c + e * self.f),
+        })
+    }
+}
+
+/// Georeference of a background image
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Georeference {
+    /// EPSG code of the coordinate reference system, e.g. `EPSG:2154`
+    pub crs: String,
+    /// Transform from background pixels to coordinates in `crs`
+    pub transform: AffineTransform,
+    /// Kind of the transform solved from the control points
+    pub kind: TransformKind,
+    /// Control points the transform was solved from
+    pub control_points: Vec<GroundControlPoint>,
+}
+
+impl Georeference {
+    /// Transform a pixel position of the background into real-world coordinates
+    pub fn to_world(&self, point: Point) -> Coordinate {
+        self.transform.apply(point)
+    }
+
+    /// Transform real-world coordinates into a pixel position of the background
+    ///
+    /// # Returns
+    ///
+    /// The pixel position, or `None` if the transform cannot be inverted
+    pub fn to_pixel(&self, coordinate: Coordinate) -> Option<Point> {
+        let inverse = self.transform.inverse()?;
+        Some(Point {
+            x: (inverse.a * coordinate.x + inverse.b * coordinate.y + inverse.c) as f32,
+            y: (inverse.d * coordinate.x + inverse.e * coordinate.y + inverse.f) as f32,
+        })
+    }
+
+    /// Distance in meters between two pixels of the background
+    ///
+    /// Distances are computed on the sphere for WGS84 coordinates, and in the plane for
+    /// projected coordinates, which are expected to be in meters.
+    pub fn distance(&self, point1: Point, point2: Point) -> f64 {
+        let world1 = self.to_world(point1);
+        let world2 = self.to_world(point2);
+        if self.crs == WGS84 {
+            // Haversine formula
+            let lat1 = world1.y.to_radians();
+            let lat2 = world2.y.to_radians();
+            let half_dlat = (lat2 - lat1) / 2.;
+            let half_dlon = (world2.x - world1.x).to_radians() / 2.;
+            let h = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlon.sin().powi(2);
+            2. * EARTH_RADIUS * h.sqrt().min(1.).asin()
+        } else {
+            ((world1.x - world2.x).powi(2) + (world1.y - world2.y).powi(2)).sqrt()
+        }
+    }
+
+    /// Bearing from a pixel of the background to another one, relative to true north
+    ///
+    /// Positions are converted into WGS84 and the initial bearing of the great circle is
+    /// computed, so that the convergence of the meridians of projected CRS is taken into
+    /// account. Grid north is used for the CRS which cannot be converted.
+    ///
+    /// # Returns
+    ///
+    /// The bearing in degrees clockwise from north, in `[0, 360)`
+    pub fn bearing(&self, from: Point, to: Point) -> f64 {
+        let world1 = self.to_world(from);
+        let world2 = self.to_world(to);
+        let bearing = match (
+            projection::to_wgs84(&self.crs, world1),
+            projection::to_wgs84(&self.crs, world2),
+        ) {
+            (Some(wgs1), Some(wgs2)) => {
+                let lat1 = wgs1.y.to_radians();
+                let lat2 = wgs2.y.to_radians();
+                let dlon = (wgs2.x - wgs1.x).to_radians();
+                (dlon.sin() * lat2.cos())
+                    .atan2(lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos())
+            }
+            _ => (world2.x - world1.x).atan2(world2.y - world1.y),
+        };
+        bearing.to_degrees().rem_euclid(360.)
+    }
+
+    /// Average size in meters of a pixel around a position of the background
+    pub fn m_per_px(&self, at: Point) -> f64 {
+        let along_x = Point {
+            x: at.x + 1.,
+            y: at.y,
+        };
+        let along_y = Point {
+            x: at.x,
+            y: at.y + 1.,
+        };
+        (self.distance(at, along_x) + self.distance(at, along_y)) / 2.
+    }
+}
@@ -0,0 +1,458 @@
+//! Conversion between the coordinate reference systems of French maps
+//!
+//! IGN maps are drawn in Lambert 93 (RGF93 datum, GRS80 ellipsoid), older ones in
+//! Lambert II étendu (NTF datum, Clarke 1880 IGN ellipsoid, Paris meridian). Both are
+//! Lambert conformal conic projections, converted here to and from WGS84 longitude and
+//! latitude. RGF93 is considered identical to WGS84, while NTF is shifted by the
+//! three-parameter geocentric translation published by IGN, which is accurate to a few
+//! meters.
+
+use super::{Coordinate, WGS84};
+
+/// EPSG code of Lambert 93 coordinates
+pub const LAMBERT_93: &str = "EPSG:2154";
+
+/// EPSG code of Lambert II étendu coordinates
+pub const LAMBERT_2_ETENDU: &str = "EPSG:27572";
+
+/// Maximum number of iterations when computing a latitude
+const MAX_ITERATIONS: usize = 20;
+
+/// Precision of iteratively computed latitudes, in radians (a few micrometers)
+const LATITUDE_EPSILON: f64 = 1e-12;
+
+/// Reference ellipsoid, given by its semi-major axis and first eccentricity
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ellipsoid {
+    a: f64,
+    e: f64,
+}
+
+impl Ellipsoid {
+    fn from_flattening(a: f64, inverse_flattening: f64) -> Ellipsoid {
+        let f = 1. / inverse_flattening;
+        Ellipsoid {
+            a,
+            e: (f * (2. - f)).sqrt(),
+        }
+    }
+
+    fn from_axes(a: f64, b: f64) -> Ellipsoid {
+        Ellipsoid {
+            a,
+            e: (1. - (b * b) / (a * a)).sqrt(),
+        }
+    }
+
+    /// Isometric latitude factor `t` of the Lambert projection formulas
+    fn t(&self, latitude: f64) -> f64 {
+        let e_sin = self.e * latitude.sin();
+        (std::f64::consts::FRAC_PI_4 - latitude / 2.).tan()
+            / ((1. - e_sin) / (1. + e_sin)).powf(self.e / 2.)
+    }
+
+    /// Ratio between the radius of the parallel and the semi-major axis
+    fn m(&self, latitude: f64) -> f64 {
+        latitude.cos() / (1. - (self.e * latitude.sin()).powi(2)).sqrt()
+    }
+
+    /// Latitude of a given `t` factor, computed by fixed-point iteration
+    fn latitude(&self, t: f64) -> f64 {
+        let mut latitude = std::f64::consts::FRAC_PI_2 - 2. * t.atan();
+        for _ in 0..MAX_ITERATIONS {
+            let e_sin = self.e * latitude.sin();
+            let next = std::f64::consts::FRAC_PI_2
+                - 2. * (t * ((1. - e_sin) / (1. + e_sin)).powf(self.e / 2.)).atan();
+            let converged = (next - latitude).abs() < LATITUDE_EPSILON;
+            latitude = next;
+            if converged {
+                break;
+            }
+        }
+        latitude
+    }
+
+    /// Convert longitude and latitude in radians into geocentric cartesian coordinates,
+    /// on the surface of the ellipsoid
+    fn geocentric(&self, longitude: f64, latitude: f64) -> [f64; 3] {
+        let e2 = self.e * self.e;
+        let n = self.a / (1. - e2 * latitude.sin().powi(2)).sqrt();
+        [
+            n * latitude.cos() * longitude.cos(),
+            n * latitude.cos() * longitude.sin(),
+            n * (1. - e2) * latitude.sin(),
+        ]
+    }
+
+    /// Convert geocentric cartesian coordinates into longitude and latitude in radians
+    fn geographic(&self, [x, y, z]: [f64; 3]) -> (f64, f64) {
+        let e2 = self.e * self.e;
+        let p = (x * x + y * y).sqrt();
+        let mut latitude = z.atan2(p * (1. - e2));
+        for _ in 0..MAX_ITERATIONS {
+            let n = self.a / (1. - e2 * latitude.sin().powi(2)).sqrt();
+            let height = p / latitude.cos() - n;
+            let next = z.atan2(p * (1. - e2 * n / (n + height)));
+            let converged = (next - latitude).abs() < LATITUDE_EPSILON;
+            latitude = next;
+            if converged {
+                break;
+            }
+        }
+        (y.atan2(x), latitude)
+    }
+}
+
+/// GRS80 ellipsoid, used by RGF93 and, up to a negligible difference, by WGS84
+fn grs80() -> Ellipsoid {
+    Ellipsoid::from_flattening(6_378_137., 298.257_222_101)
+}
+
+/// Clarke 1880 IGN ellipsoid, used by NTF
+fn clarke_1880_ign() -> Ellipsoid {
+    Ellipsoid::from_axes(6_378_249.2, 6_356_515.)
+}
+
+/// Longitude of the Paris meridian east of Greenwich, in degrees (2°20'14.025")
+const PARIS_MERIDIAN: f64 = 2.337_229_166_667;
+
+/// Geocentric translation from NTF to WGS84, in meters
+const NTF_TO_WGS84: [f64; 3] = [-168., -60., 320.];
+
+/// Lambert conformal conic projection
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Lambert {
+    ellipsoid: Ellipsoid,
+    /// Longitude of the central meridian, in radians
+    longitude_origin: f64,
+    /// Exponent of the projection
+    n: f64,
+    /// Radius of the parallel of latitude 0, i.e. `a * k0 * F` in the usual notation
+    c: f64,
+    /// Easting of the pole
+    x_pole: f64,
+    /// Northing of the pole
+    y_pole: f64,
+}
+
+impl Lambert {
+    /// Build a secant projection from its two standard parallels, in degrees
+    fn secant(
+        ellipsoid: Ellipsoid,
+        origin: Coordinate,
+        parallels: (f64, f64),
+        false_origin: Coordinate,
+    ) -> Lambert {
+        let (latitude1, latitude2) = (parallels.0.to_radians(), parallels.1.to_radians());
+        let (m1, m2) = (ellipsoid.m(latitude1), ellipsoid.m(latitude2));
+        let (t1, t2) = (ellipsoid.t(latitude1), ellipsoid.t(latitude2));
+        let n = (m1.ln() - m2.ln()) / (t1.ln() - t2.ln());
+        let c = ellipsoid.a * m1 / (n * t1.powf(n));
+        Lambert::with_constants(ellipsoid, origin, n, c, false_origin)
+    }
+
+    /// Build a tangent projection from its scale factor on the origin parallel
+    fn tangent(
+        ellipsoid: Ellipsoid,
+        origin: Coordinate,
+        scale_factor: f64,
+        false_origin: Coordinate,
+    ) -> Lambert {
+        let latitude = origin.y.to_radians();
+        let n = latitude.sin();
+        let c = ellipsoid.a * scale_factor * ellipsoid.m(latitude)
+            / (n * ellipsoid.t(latitude).powf(n));
+        Lambert::with_constants(ellipsoid, origin, n, c, false_origin)
+    }
+
+    fn with_constants(
+        ellipsoid: Ellipsoid,
+        origin: Coordinate,
+        n: f64,
+        c: f64,
+        false_origin: Coordinate,
+    ) -> Lambert {
+        let radius_origin = c * ellipsoid.t(origin.y.to_radians()).powf(n);
+        Lambert {
+            ellipsoid,
+            longitude_origin: origin.x.to_radians(),
+            n,
+            c,
+            x_pole: false_origin.x,
+            y_pole: false_origin.y + radius_origin,
+        }
+    }
+
+    /// Project longitude and latitude in radians
+    fn forward(&self, longitude: f64, latitude: f64) -> Coordinate {
+        let radius = self.c * self.ellipsoid.t(latitude).powf(self.n);
+        let angle = self.n * (longitude - self.longitude_origin);
+        Coordinate {
+            x: self.x_pole + radius * angle.sin(),
+            y: self.y_pole - radius * angle.cos(),
+        }
+    }
+
+    /// Get the longitude and latitude in radians of projected coordinates
+    fn inverse(&self, coordinate: Coordinate) -> (f64, f64) {
+        let dx = coordinate.x - self.x_pole;
+        let dy = self.y_pole - coordinate.y;
+        let radius = (dx * dx + dy * dy).sqrt();
+        let angle = dx.atan2(dy);
+        let t = (radius / self.c).powf(1. / self.n);
+        (
+            self.longitude_origin + angle / self.n,
+            self.ellipsoid.latitude(t),
+        )
+    }
+}
+
+/// Lambert 93 projection, secant to the 44°N and 49°N parallels
+fn lambert_93() -> Lambert {
+    Lambert::secant(
+        grs80(),
+        Coordinate { x: 3., y: 46.5 },
+        (44., 49.),
+        Coordinate {
+            x: 700_000.,
+            y: 6_600_000.,
+        },
+    )
+}
+
+/// Lambert II étendu projection, tangent to the 52 grad parallel, from the Paris meridian
+fn lambert_2_etendu() -> Lambert {
+    Lambert::tangent(
+        clarke_1880_ign(),
+        Coordinate {
+            x: PARIS_MERIDIAN,
+            y: 46.8,
+        },
+        0.999_877_42,
+        Coordinate {
+            x: 600_000.,
+            y: 2_200_000.,
+        },
+    )
+}
+
+/// Shift longitude and latitude in radians from NTF to WGS84, or back if `inverse` is set
+fn shift_ntf(longitude: f64, latitude: f64, inverse: bool) -> (f64, f64) {
+    let (from, to, sign) = match inverse {
+        false => (clarke_1880_ign(), grs80(), 1.),
+        true => (grs80(), clarke_1880_ign(), -1.),
+    };
+    let [x, y, z] = from.geocentric(longitude, latitude);
+    to.geographic([
+        x + sign * NTF_TO_WGS84[0],
+        y + sign * NTF_TO_WGS84[1],
+        z + sign * NTF_TO_WGS84[2],
+    ])
+}
+
+fn degrees((longitude, latitude): (f64, f64)) -> Coordinate {
+    Coordinate {
+        x: longitude.to_degrees(),
+        y: latitude.to_degrees(),
+    }
+}
+
+/// Convert coordinates into WGS84 longitude and latitude
+///
+/// # Arguments
+///
+/// * `crs` - The EPSG code of the coordinates
+/// * `coordinate` - The coordinates to be converted
+///
+/// # Returns
+///
+/// Longitude and latitude in degrees, or `None` if the CRS is not supported
+pub fn to_wgs84(crs: &str, coordinate: Coordinate) -> Option<Coordinate> {
+    match crs {
+        WGS84 => Some(coordinate),
+        LAMBERT_93 => Some(degrees(lambert_93().inverse(coordinate))),
+        LAMBERT_2_ETENDU => {
+            let (longitude, latitude) = lambert_2_etendu().inverse(coordinate);
+            Some(degrees(shift_ntf(longitude, latitude, false)))
+        }
+        _ => None,
+    }
+}
+
+/// Convert WGS84 longitude and latitude into another CRS
+///
+/// # Arguments
+///
+/// * `crs` - The EPSG code of the CRS to convert into
+/// * `coordinate` - Longitude and latitude in degrees
+///
+/// # Returns
+///
+/// The converted coordinates, or `None` if the CRS is not supported
+pub fn from_wgs84(crs: &str, coordinate: Coordinate) -> Option<Coordinate> {
+    let longitude = coordinate.x.to_radians();
+    let latitude = coordinate.y.to_radians();
+    match crs {
+        WGS84 => Some(coordinate),
+        LAMBERT_93 => Some(lambert_93().forward(longitude, latitude)),
+        LAMBERT_2_ETENDU => {
+            let (longitude, latitude) = shift_ntf(longitude, latitude, true);
+            Some(lambert_2_etendu().forward(longitude, latitude))
+        }
+        _ => None,
+    }
+}
+
+/// Convert coordinates between two CRS, through WGS84
+///
+/// # Returns
+///
+/// The converted coordinates, or `None` if one of the CRS is not supported
+pub fn convert(from: &str, to: &str, coordinate: Coordinate) -> Option<Coordinate> {
+    if from == to {
+        return Some(coordinate);
+    }
+    from_wgs84(to, to_wgs84(from, coordinate)?)
+}
+
+/// Human readable name of a CRS
+pub fn crs_name(crs: &str) -> &str {
+    match crs {
+        WGS84 => "WGS84",
+        LAMBERT_93 => "Lambert 93",
+        LAMBERT_2_ETENDU => "Lambert II étendu",
+        _ => crs,
+    }
+}
+
+/// Format coordinates for display, as latitude and longitude for WGS84 and as easting and
+/// northing for projected CRS
+pub fn format_coordinate(crs: &str, coordinate: Coordinate) -> String {
+    match crs {
+        WGS84 => format!(
+            "{}: {:.6}°{} {:.6}°{}",
+            crs_name(crs),
+            coordinate.y.abs(),
+            if coordinate.y < 0. { "S" } else { "N" },
+            coordinate.x.abs(),
+            if coordinate.x < 0. { "W" } else { "E" }
+        ),
+        _ => format!(
+            "{}: X {:.1} Y {:.1}",
+            crs_name(crs),
+            coordinate.x,
+            coordinate.y
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} differs from {} by more than {}",
+            actual,
+            expected,
+            tolerance
+        );
+    }
+
+    /// Lambert I projection with the rounded constants of the IGN test cases (NTG_71)
+    fn ign_test_projection() -> Lambert {
+        Lambert {
+            ellipsoid: Ellipsoid {
+                a: 6_378_249.2,
+                e: 0.082_483_256_8,
+            },
+            longitude_origin: 0.040_792_344_33,
+            n: 0.760_405_966,
+            c: 11_603_796.976_7,
+            x_pole: 600_000.,
+            y_pole: 5_657_616.674,
+        }
+    }
+
+    #[test]
+    fn forward_matches_ign_alg0003() {
+        let projected = ign_test_projection().forward(0.145_512_099, 0.872_664_626);
+        assert_close(projected.x, 1_029_705.081_8, 1e-3);
+        assert_close(projected.y, 272_723.851_0, 1e-3);
+    }
+
+    #[test]
+    fn inverse_matches_ign_alg0004() {
+        let (longitude, latitude) = ign_test_projection().inverse(Coordinate {
+            x: 1_029_705.083,
+            y: 272_723.849,
+        });
+        assert_close(longitude, 0.145_512_099, 1e-9);
+        assert_close(latitude, 0.872_664_626, 1e-9);
+    }
+
+    #[test]
+    fn constants_match_ign() {
+        let lambert = lambert_93();
+        assert_close(lambert.n, 0.725_607_765_0, 1e-10);
+        assert_close(lambert.c, 11_754_255.426, 1e-3);
+        assert_close(lambert.y_pole, 12_655_612.050, 1e-3);
+
+        let lambert = lambert_2_etendu();
+        assert_close(lambert.n, 0.728_968_627_4, 1e-10);
+        assert_close(lambert.c, 11_745_793.39, 1e-2);
+        assert_close(lambert.y_pole, 8_199_695.768, 1e-3);
+    }
+
+    #[test]
+    fn origins_project_on_false_origins() {
+        let origin = from_wgs84(LAMBERT_93, Coordinate { x: 3., y: 46.5 }).unwrap();
+        assert_close(origin.x, 700_000., 1e-6);
+        assert_close(origin.y, 6_600_000., 1e-6);
+
+        let origin = lambert_2_etendu().forward(PARIS_MERIDIAN.to_radians(), 46.8_f64.to_radians());
+        assert_close(origin.x, 600_000., 1e-6);
+        assert_close(origin.y, 2_200_000., 1e-6);
+    }
+
+    #[test]
+    fn round_trip_through_wgs84() {
+        let points = [
+            Coordinate {
+                x: 2.3522,
+                y: 48.8566,
+            },
+            Coordinate {
+                x: -4.4861,
+                y: 48.3904,
+            },
+            Coordinate {
+                x: 7.2620,
+                y: 43.7102,
+            },
+        ];
+        for crs in [LAMBERT_93, LAMBERT_2_ETENDU] {
+            for point in points {
+                let projected = from_wgs84(crs, point).unwrap();
+                let back = to_wgs84(crs, projected).unwrap();
+                // The NTF shift drops the ellipsoidal height, hence a looser tolerance
+                assert_close(back.x, point.x, 1e-7);
+                assert_close(back.y, point.y, 1e-7);
+            }
+        }
+    }
+
+    #[test]
+    fn convert_between_lambert_projections() {
+        let lambert_93 = Coordinate {
+            x: 652_469.,
+            y: 6_862_035.,
+        };
+        let lambert_2 = convert(LAMBERT_93, LAMBERT_2_ETENDU, lambert_93).unwrap();
+        let back = convert(LAMBERT_2_ETENDU, LAMBERT_93, lambert_2).unwrap();
+        assert_close(back.x, lambert_93.x, 1e-2);
+        assert_close(back.y, lambert_93.y, 1e-2);
+        assert!(convert("EPSG:3857", LAMBERT_93, lambert_93).is_none());
+    }
+}
@@ -0,0 +1,485 @@
+//! Undo/redo history module
+//!
+//! Every modification of the overlay or of the layers is recorded as a `Command`
+//! holding enough data to be reverted and replayed. Commands are stored in a
+//! bounded `History`, the oldest ones being dropped once the capacity is reached.
+
+use std::collections::VecDeque;
+
+use crate::geo::Georeference;
+use crate::rendering::{
+    group::Group,
+    layer::LayerRenderer,
+    overlay::{Color, Drawable, OverlayRenderer},
+    style::Style,
+};
+use crate::LayerDrawable;
+
+/// Default number of commands kept in the history
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100;
+
+/// State of a whole project, used to revert a project load or a background change
+#[derive(Clone, Debug, Default)]
+pub struct ProjectSnapshot {
+    pub background: String,
+    pub georeference: Option<Georeference>,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub layers: Vec<LayerDrawable>,
+    pub drawables: Vec<Drawable>,
+    pub groups: Vec<Group>,
+}
+
+impl ProjectSnapshot {
+    /// Capture the current state of the renderers
+    ///
+    /// # Arguments
+    ///
+    /// * `background` - The background file path
+    /// * `georeference` - The georeference of the background, if any
+    /// * `overlay` - The overlay renderer
+    /// * `layers` - The layer renderer
+    pub fn capture(
+        background: &str,
+        georeference: Option<&Georeference>,
+        overlay: &OverlayRenderer,
+        layers: &LayerRenderer,
+    ) -> ProjectSnapshot {
+        let (image_width, image_height) = overlay.image_size();
+        ProjectSnapshot {
+            background: String::from(background),
+            georeference: georeference.cloned(),
+            image_width,
+            image_height,
+            layers: layers.layers.clone(),
+            drawables: overlay.drawables.clone(),
+            groups: overlay.groups.clone(),
+        }
+    }
+
+    /// Put the renderers back in the captured state
+    fn restore(&self, overlay: &mut OverlayRenderer, layers: &mut LayerRenderer) {
+        overlay.reset(self.image_width, self.image_height);
+        overlay.replace_groups(&self.groups);
+        overlay.replace_drawables(&self.drawables);
+        layers.reset();
+        layers.replace_layers(&self.layers);
+    }
+}
+
+/// A reversible modification of the project
+#[derive(Clone, Debug)]
+pub enum Command {
+    /// Drawables added in a single action, e.g. the two lines of a tangent
+    AddDrawables(Vec<Drawable>),
+    /// Drawables deleted in a single action
+    DeleteDrawables(Vec<Drawable>),
+    /// A drawable moved or edited, along with the drawables built from it
+    EditDrawables {
+        before: Vec<Drawable>,
+        after: Vec<Drawable>,
+    },
+    /// A layer added on top of the map
+    AddLayer(LayerDrawable),
+    /// Group created, drawables being added to it afterwards
+    AddGroup(Group),
+    /// Group renamed, hidden or shown, locked or unlocked
+    EditGroup { before: Group, after: Group },
+    /// Group deleted along with its drawables and the drawables built from them
+    DeleteGroup {
+        group: Group,
+        drawables: Vec<Drawable>,
+    },
+    /// Change of color, width and style of a drawable
+    ChangeStyle {
+        id: i32,
+        before: (Color, f32, Style),
+        after: (Color, f32, Style),
+    },
+    /// Georeference of the background solved or removed
+    SetGeoreference {
+        before: Option<Georeference>,
+        after: Option<Georeference>,
+    },
+    /// Position, transparency or scale of a layer edited
+    EditLayer {
+        before: LayerDrawable,
+        after: LayerDrawable,
+    },
+    /// Scale calibrated, for the background or for a layer
+    SetScale {
+        layer: Option<i32>,
+        before: f32,
+        after: f32,
+    },
+    /// Whole project replaced, e.g. by loading a file or a new background
+    RestoreProject {
+        before: Box<ProjectSnapshot>,
+        after: Box<ProjectSnapshot>,
+    },
+}
+
+impl Command {
+    /// Revert the effects of the command
+    pub fn undo(&self, overlay: &mut OverlayRenderer, layers: &mut LayerRenderer) {
+        match self {
+            Command::AddDrawables(drawables) => {
+                for d in drawables {
+                    overlay.remove_drawable(d.id);
+                }
+            }
+            Command::DeleteDrawables(drawables) => {
+                for d in drawables {
+                    overlay.insert_drawable(d.clone());
+                }
+            }
+            Command::EditDrawables { before, .. } => {
+                for d in before {
+                    overlay.replace_drawable(d.clone());
+                }
+            }
+            Command::AddLayer(layer) => {
+                layers.remove_layer(layer.id);
+            }
+            Command::AddGroup(group) => {
+                overlay.remove_group(group.id);
+            }
+            Command::EditGroup { before, .. } => overlay.replace_group(before.clone()),
+            Command::DeleteGroup { group, drawables } => {
+                overlay.insert_group(group.clone());
+                for d in drawables {
+                    overlay.insert_drawable(d.clone());
+                }
+            }
+            Command::ChangeStyle { id, before, .. } => {
+                overlay.set_drawable_style(*id, before.0, before.1, before.2);
+            }
+            Command::SetGeoreference { .. } => {}
+            Command::EditLayer { before, .. } => layers.update_layer(before),
+            Command::SetScale { layer, before, .. } => {
+                if let Some(id) = layer {
+                    layers.set_scale(*id, *before);
+                }
+            }
+            Command::RestoreProject { before, .. } => before.restore(overlay, layers),
+        }
+    }
+
+    /// Apply again the effects of the command
+    pub fn redo(&self, overlay: &mut OverlayRenderer, layers: &mut LayerRenderer) {
+        match self {
+            Command::AddDrawables(drawables) => {
+                for d in drawables {
+                    overlay.insert_drawable(d.clone());
+                }
+            }
+            Command::DeleteDrawables(drawables) => {
+                for d in drawables {
+                    overlay.remove_drawable(d.id);
+                }
+            }
+            Command::EditDrawables { after, .. } => {
+                for d in after {
+                    overlay.replace_drawable(d.clone());
+                }
+            }
+            Command::AddLayer(layer) => layers.insert_layer(layer.clone()),
+            Command::AddGroup(group) => overlay.insert_group(group.clone()),
+            Command::EditGroup { after, .. } => overlay.replace_group(after.clone()),
+            Command::DeleteGroup { group, drawables } => {
+                for d in drawables {
+                    overlay.remove_drawable(d.id);
+                }
+                overlay.remove_group(group.id);
+            }
+            Command::ChangeStyle { id, after, .. } => {
+                overlay.set_drawable_style(*id, after.0, after.1, after.2);
+            }
+            Command::SetGeoreference { .. } => {}
+            Command::EditLayer { after, .. } => layers.update_layer(after),
+            Command::SetScale { layer, after, .. } => {
+                if let Some(id) = layer {
+                    layers.set_scale(*id, *after);
+                }
+            }
+            Command::RestoreProject { after, .. } => after.restore(overlay, layers),
+        }
+    }
+
+    /// Project state once the command is undone or redone
+    ///
+    /// Only project restoration can change the background and its georeference, other
+    /// commands return `None`.
+    pub fn project(&self, undo: bool) -> Option<&ProjectSnapshot> {
+        match self {
+            Command::RestoreProject { before, after } => {
+                if undo {
+                    Some(before)
+                } else {
+                    Some(after)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Georeference of the background once the command is undone or redone
+    ///
+    /// # Returns
+    ///
+    /// `None` if the command does not change the georeference, otherwise the new
+    /// georeference, which may be empty
+    pub fn georeference(&self, undo: bool) -> Option<Option<&Georeference>> {
+        match self {
+            Command::SetGeoreference { before, after } => {
+                Some(if undo { before.as_ref() } else { after.as_ref() })
+            }
+            _ => self
+                .project(undo)
+                .map(|project| project.georeference.as_ref()),
+        }
+    }
+
+    /// Meters per pixel of the background once the command is undone or redone
+    ///
+    /// # Returns
+    ///
+    /// `None` if the command does not change the scale of the background
+    pub fn background_scale(&self, undo: bool) -> Option<f32> {
+        match self {
+            Command::SetScale {
+                layer: None,
+                before,
+                after,
+            } => Some(if undo { *before } else { *after }),
+            _ => None,
+        }
+    }
+
+    /// Short description of the command, displayed to the user
+    pub fn description(&self) -> &'static str {
+        match self {
+            Command::AddDrawables(_) => "add object",
+            Command::DeleteDrawables(_) => "delete object",
+            Command::EditDrawables { .. } => "edit object",
+            Command::AddLayer(_) => "add layer",
+            Command::AddGroup(_) => "add group",
+            Command::EditGroup { .. } => "edit group",
+            Command::DeleteGroup { .. } => "delete group",
+            Command::ChangeStyle { .. } => "change style",
+            Command::SetGeoreference { .. } => "georeference",
+            Command::EditLayer { .. } => "edit layer",
+            Command::SetScale { .. } => "calibrate scale",
+            Command::RestoreProject { .. } => "load project",
+        }
+    }
+}
+
+/// Bounded undo/redo stacks of commands
+///
+/// Each recorded command gets a sequence number, used to know whether the
+/// project has been modified since it was last loaded or saved.
+#[derive(Clone, Debug)]
+pub struct History {
+    undo_stack: VecDeque<(u64, Command)>,
+    redo_stack: Vec<(u64, Command)>,
+    capacity: usize,
+    next_sequence: u64,
+    /// Sequence number of the oldest command dropped from the undo stack
+    base_sequence: u64,
+    /// Sequence number of the last applied command when the project was saved,
+    /// `None` if the current state does not match any saved file
+    saved_sequence: Option<u64>,
+}
+
+impl History {
+    /// Creates an empty history keeping at most `capacity` commands
+    pub fn new(capacity: usize) -> History {
+        History {
+            undo_stack: VecDeque::with_capacity(capacity),
+            redo_stack: Vec::new(),
+            capacity,
+            next_sequence: 1,
+            base_sequence: 0,
+            saved_sequence: Some(0),
+        }
+    }
+
+    /// Record a command that has just been applied
+    ///
+    /// The redo stack is cleared, and the oldest command is dropped if the history is full.
+    pub fn push(&mut self, command: Command) {
+        self.redo_stack.clear();
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        if self.capacity == 0 {
+            self.base_sequence = sequence;
+            return;
+        }
+        if self.undo_stack.len() == self.capacity {
+            if let Some((dropped, _)) = self.undo_stack.pop_front() {
+                self.base_sequence = dropped;
+            }
+        }
+        self.undo_stack.push_back((sequence, command));
+    }
+
+    /// Undo the last command on the given renderers
+    ///
+    /// # Returns
+    ///
+    /// The undone command, or `None` if there is nothing to undo
+    pub fn undo(
+        &mut self,
+        overlay: &mut OverlayRenderer,
+        layers: &mut LayerRenderer,
+    ) -> Option<Command> {
+        let (sequence, command) = self.undo_stack.pop_back()?;
+        command.undo(overlay, layers);
+        self.redo_stack.push((sequence, command.clone()));
+        Some(command)
+    }
+
+    /// Redo the last undone command on the given renderers
+    ///
+    /// # Returns
+    ///
+    /// The redone command, or `None` if there is nothing to redo
+    pub fn redo(
+        &mut self,
+        overlay: &mut OverlayRenderer,
+        layers: &mut LayerRenderer,
+    ) -> Option<Command> {
+        let (sequence, command) = self.redo_stack.pop()?;
+        command.redo(overlay, layers);
+        self.undo_stack.push_back((sequence, command.clone()));
+        Some(command)
+    }
+
+    /// Sequence number of the last applied command
+    fn current_sequence(&self) -> u64 {
+        self.undo_stack
+            .back()
+            .map(|(sequence, _)| *sequence)
+            .unwrap_or(self.base_sequence)
+    }
+
+    /// Last applied command, to be amended by a continuous edit such as a slider drag
+    ///
+    /// # Returns
+    ///
+    /// The last command, or `None` if there is none, if some commands can be redone, or if
+    /// it matches the saved state, in which case amending it would hide the modification
+    pub fn last_unsaved_mut(&mut self) -> Option<&mut Command> {
+        if !self.is_modified() || !self.redo_stack.is_empty() {
+            return None;
+        }
+        self.undo_stack.back_mut().map(|(_, command)| command)
+    }
+
+    /// Remember the current state as the saved one
+    pub fn mark_saved(&mut self) {
+        self.saved_sequence = Some(self.current_sequence());
+    }
+
+    /// Forget the saved state, e.g. after restoring an autosaved project
+    pub fn mark_unsaved(&mut self) {
+        self.saved_sequence = None;
+    }
+
+    /// Check whether the project has been modified since it was last saved
+    pub fn is_modified(&self) -> bool {
+        Some(self.current_sequence()) != self.saved_sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Background scale change, which leaves the renderers untouched
+    fn scale(after: f32) -> Command {
+        Command::SetScale {
+            layer: None,
+            before: after - 1.,
+            after,
+        }
+    }
+
+    fn renderers() -> (OverlayRenderer, LayerRenderer) {
+        (OverlayRenderer::new(10, 10), LayerRenderer::new())
+    }
+
+    #[test]
+    fn capacity_drops_oldest_command() {
+        let (mut overlay, mut layers) = renderers();
+        let mut history = History::new(2);
+        for after in [1., 2., 3.] {
+            history.push(scale(after));
+        }
+        let undone = std::iter::from_fn(|| history.undo(&mut overlay, &mut layers))
+            .filter_map(|command| command.background_scale(false))
+            .collect::<Vec<f32>>();
+        assert_eq!(undone, vec![3., 2.]);
+        // The dropped command cannot be undone, the project stays modified
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn push_clears_redo_stack() {
+        let (mut overlay, mut layers) = renderers();
+        let mut history = History::new(10);
+        history.push(scale(1.));
+        history.push(scale(2.));
+        history.undo(&mut overlay, &mut layers);
+        history.push(scale(3.));
+        assert!(history.redo(&mut overlay, &mut layers).is_none());
+        let undone = history.undo(&mut overlay, &mut layers).unwrap();
+        assert_eq!(undone.background_scale(false), Some(3.));
+    }
+
+    #[test]
+    fn modified_across_undo_and_redo() {
+        let (mut overlay, mut layers) = renderers();
+        let mut history = History::new(10);
+        assert!(!history.is_modified());
+        history.push(scale(1.));
+        assert!(history.is_modified());
+        history.undo(&mut overlay, &mut layers);
+        assert!(!history.is_modified());
+        history.redo(&mut overlay, &mut layers);
+        assert!(history.is_modified());
+
+        history.push(scale(2.));
+        history.mark_saved();
+        assert!(!history.is_modified());
+        history.undo(&mut overlay, &mut layers);
+        assert!(history.is_modified());
+        history.redo(&mut overlay, &mut layers);
+        assert!(!history.is_modified());
+
+        history.mark_unsaved();
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn last_unsaved_command() {
+        let (mut overlay, mut layers) = renderers();
+        let mut history = History::new(10);
+        assert!(history.last_unsaved_mut().is_none());
+        history.push(scale(1.));
+        history.push(scale(2.));
+        if let Some(Command::SetScale { after, .. }) = history.last_unsaved_mut() {
+            *after = 5.;
+        }
+        let undone = history.undo(&mut overlay, &mut layers).unwrap();
+        assert_eq!(undone.background_scale(false), Some(5.));
+        // Commands can be redone
+        assert!(history.last_unsaved_mut().is_none());
+        history.redo(&mut overlay, &mut layers);
+        assert!(history.last_unsaved_mut().is_some());
+        // Saved state
+        history.mark_saved();
+        assert!(history.last_unsaved_mut().is_none());
+    }
+}
@@ -0,0 +1,448 @@
+//! GeoJSON export of the annotations
+//!
+//! Drawables are exported in the coordinate reference system of the background
+//! georeference. Coordinates are written as `[x, y]`, i.e. `[longitude, latitude]` for
+//! WGS84, and a legacy `crs` member names the CRS when it is not WGS84 so that GIS tools
+//! such as QGIS pick the right projection.
+
+use std::fs::File;
+
+use serde_json::{json, Map, Value};
+
+use super::{Project, ProjectDrawable, ProjectError};
+use crate::geo::{Coordinate, Georeference, WGS84};
+use crate::math::{arc_points, bearing, distance, ellipse_points, point_at_bearing, sweep, Point};
+use crate::rendering::group::Group;
+use crate::rendering::overlay::{Color, DrawableType};
+use crate::rendering::style::DashPattern;
+
+/// Extension of GeoJSON files
+pub const GEOJSON_EXTENSION: &str = "geojson";
+
+/// Number of vertices of the polygon approximating a circle or an ellipse
+const CIRCLE_VERTICES: usize = 64;
+
+/// Build the GeoJSON position of a pixel of the background
+fn position(georeference: &Georeference, point: Point) -> Value {
+    let Coordinate { x, y } = georeference.to_world(point);
+    json!([x, y])
+}
+
+/// Format a color as an HTML hexadecimal color
+fn hex_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Build a closed linear ring of GeoJSON positions
+fn ring(georeference: &Georeference, points: impl IntoIterator<Item = Point>) -> Vec<Value> {
+    let mut ring = points
+        .into_iter()
+        .map(|point| position(georeference, point))
+        .collect::<Vec<Value>>();
+    if let Some(first) = ring.first().cloned() {
+        ring.push(first);
+    }
+    ring
+}
+
+/// Build the geometry of a drawable
+///
+/// Lines and half-lines are already clipped to the background when they are drawn, so
+/// their two points are exported as a line string. Circles are approximated by a polygon.
+/// Rings of polygons are closed by repeating their first vertex. Labels are exported as
+/// the point their text is centered on. Arcs are approximated by a line string, sectors
+/// and annuli by a polygon, with a hole for a full annulus, and ellipses like circles.
+fn geometry(georeference: &Georeference, drawable: &ProjectDrawable) -> Value {
+    match drawable.object_type {
+        DrawableType::Point | DrawableType::Label => json!({
+            "type": "Point",
+            "coordinates": position(georeference, drawable.point1),
+        }),
+        DrawableType::Segment | DrawableType::HalfLine | DrawableType::Line => json!({
+            "type": "LineString",
+            "coordinates": [
+                position(georeference, drawable.point1),
+                position(georeference, drawable.point2),
+            ],
+        }),
+        DrawableType::Polyline => json!({
+            "type": "LineString",
+            "coordinates": drawable
+                .points
+                .iter()
+                .map(|point| position(georeference, *point))
+                .collect::<Vec<Value>>(),
+        }),
+        DrawableType::Polygon => json!({
+            "type": "Polygon",
+            "coordinates": [ring(georeference, drawable.points.iter().copied())],
+        }),
+        DrawableType::Arc | DrawableType::Sector | DrawableType::Annulus => {
+            let center = drawable.point1;
+            let radius = distance(drawable.point1, drawable.point2);
+            let sweep = sweep(drawable.start_angle, drawable.end_angle);
+            let outer = arc_points(center, radius, drawable.start_angle, sweep);
+            let mut inner = arc_points(center, drawable.inner_radius, drawable.start_angle, sweep);
+            inner.reverse();
+            let full_turn = sweep >= 360.;
+            let rings = match drawable.object_type {
+                DrawableType::Arc => {
+                    return json!({
+                        "type": "LineString",
+                        "coordinates": outer
+                            .into_iter()
+                            .map(|point| position(georeference, point))
+                            .collect::<Vec<Value>>(),
+                    })
+                }
+                DrawableType::Sector if full_turn => vec![ring(georeference, outer)],
+                DrawableType::Sector => {
+                    vec![ring(georeference, std::iter::once(center).chain(outer))]
+                }
+                _ if full_turn => vec![ring(georeference, outer), ring(georeference, inner)],
+                _ => vec![ring(georeference, outer.into_iter().chain(inner))],
+            };
+            json!({
+                "type": "Polygon",
+                "coordinates": rings,
+            })
+        }
+        DrawableType::Ellipse => {
+            let points = ellipse_points(
+                drawable.point1,
+                drawable.point2,
+                drawable.semi_axis,
+                CIRCLE_VERTICES,
+            );
+            json!({
+                "type": "Polygon",
+                "coordinates": [ring(georeference, points)],
+            })
+        }
+        DrawableType::Circle => {
+            let center = drawable.point1;
+            let radius = distance(drawable.point1, drawable.point2);
+            let points = (0..CIRCLE_VERTICES).map(|i| {
+                let angle = 2. * std::f32::consts::PI * i as f32 / CIRCLE_VERTICES as f32;
+                Point {
+                    x: center.x + radius * angle.cos(),
+                    y: center.y + radius * angle.sin(),
+                }
+            });
+            json!({
+                "type": "Polygon",
+                "coordinates": [ring(georeference, points)],
+            })
+        }
+    }
+}
+
+/// Build the GeoJSON feature of a drawable, with its style and group as properties
+fn feature(
+    georeference: &Georeference,
+    drawable: &ProjectDrawable,
+    group: Option<&Group>,
+) -> Value {
+    let mut properties = Map::new();
+    properties.insert(String::from("id"), json!(drawable.id));
+    properties.insert(
+        String::from("type"),
+        json!(format!("{:?}", drawable.object_type)),
+    );
+    properties.insert(String::from("color"), json!(hex_color(drawable.color)));
+    properties.insert(String::from("width"), json!(drawable.width));
+    let style = &drawable.style;
+    if style.alpha < 255 {
+        properties.insert(String::from("opacity"), json!(style.alpha as f32 / 255.));
+    }
+    if style.dash != DashPattern::Solid {
+        properties.insert(String::from("dash"), json!(format!("{:?}", style.dash)));
+    }
+    if drawable.fill {
+        let fill = style.fill_color.unwrap_or(drawable.color);
+        properties.insert(String::from("fill"), json!(hex_color(fill)));
+    }
+    if style.start_arrow {
+        properties.insert(String::from("start_arrow"), json!(true));
+    }
+    if style.end_arrow {
+        properties.insert(String::from("end_arrow"), json!(true));
+    }
+    if !drawable.name.is_empty() {
+        properties.insert(String::from("name"), json!(drawable.name));
+    }
+    if !drawable.note.is_empty() {
+        properties.insert(String::from("note"), json!(drawable.note));
+    }
+    if !drawable.tags.is_empty() {
+        properties.insert(String::from("tags"), json!(drawable.tags));
+    }
+    if let Some(group) = group {
+        properties.insert(String::from("group"), json!(group.name));
+    }
+    if drawable.object_type == DrawableType::Label {
+        properties.insert(String::from("text"), json!(drawable.text));
+        properties.insert(String::from("font_size"), json!(drawable.font_size));
+        if drawable.point1 != drawable.point2 {
+            let target = georeference.to_world(drawable.point2);
+            properties.insert(String::from("target"), json!([target.x, target.y]));
+        }
+    }
+    // Lengths are given in meters whatever the CRS, degrees of longitude and latitude
+    // having different lengths
+    if let DrawableType::Arc | DrawableType::Sector | DrawableType::Annulus = drawable.object_type {
+        let center = drawable.point1;
+        let radius = georeference.distance(center, drawable.point2);
+        properties.insert(String::from("radius_m"), json!(radius));
+        if drawable.object_type == DrawableType::Annulus {
+            let inner = point_at_bearing(
+                center,
+                drawable.inner_radius,
+                bearing(center, drawable.point2),
+            );
+            properties.insert(
+                String::from("inner_radius_m"),
+                json!(georeference.distance(center, inner)),
+            );
+        }
+        // Bearings are drawn from the top of the image, they are exported from true north
+        let pixel_radius = distance(center, drawable.point2);
+        let edge = |angle: f32| point_at_bearing(center, pixel_radius, angle);
+        properties.insert(
+            String::from("start_bearing"),
+            json!(georeference.bearing(center, edge(drawable.start_angle))),
+        );
+        properties.insert(
+            String::from("end_bearing"),
+            json!(georeference.bearing(center, edge(drawable.end_angle))),
+        );
+    }
+    if drawable.object_type == DrawableType::Ellipse {
+        let center = drawable.point1;
+        // The second semi-axis is perpendicular to the first one
+        let second_axis_end = point_at_bearing(
+            center,
+            drawable.semi_axis,
+            bearing(center, drawable.point2) + 90.,
+        );
+        properties.insert(
+            String::from("semi_axes_m"),
+            json!([
+                georeference.distance(center, drawable.point2),
+                georeference.distance(center, second_axis_end),
+            ]),
+        );
+    }
+    if drawable.object_type == DrawableType::Circle {
+        let center = georeference.to_world(drawable.point1);
+        properties.insert(String::from("center"), json!([center.x, center.y]));
+        properties.insert(
+            String::from("radius_m"),
+            json!(georeference.distance(drawable.point1, drawable.point2)),
+        );
+    }
+    json!({
+        "type": "Feature",
+        "geometry": geometry(georeference, drawable),
+        "properties": properties,
+    })
+}
+
+/// Convert the drawables of a project into a GeoJSON feature collection
+///
+/// Drawables of hidden groups are left out, so that only the annotations shown on the
+/// map are exported.
+///
+/// # Returns
+///
+/// The feature collection, or an error if the project is not georeferenced
+pub fn to_geojson(project: &Project) -> Result<Value, ProjectError> {
+    let georeference = project
+        .georeference
+        .as_ref()
+        .ok_or(ProjectError::NotGeoreferenced)?;
+
+    let features = project
+        .drawables
+        .iter()
+        .filter_map(|drawable| {
+            let group = drawable
+                .group
+                .and_then(|id| project.groups.iter().find(|g| g.id == id));
+            match group {
+                Some(group) if !group.visible => None,
+                _ => Some(feature(georeference, drawable, group)),
+            }
+        })
+        .collect::<Vec<Value>>();
+
+    let mut collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    if georeference.crs != WGS84 {
+        let code = georeference.crs.trim_start_matches("EPSG:");
+        collection["crs"] = json!({
+            "type": "name",
+            "properties": { "name": format!("urn:ogc:def:crs:EPSG::{}", code) },
+        });
+    }
+    Ok(collection)
+}
+
+/// Export the drawables of a project to a GeoJSON file
+///
+/// # Arguments
+///
+/// * `project` - The georeferenced project
+/// * `file` - The path of the GeoJSON file to be written
+pub fn export_geojson(project: &Project, file: &str) -> Result<(), ProjectError> {
+    let collection = to_geojson(project)?;
+    let file = File::create(file)?;
+    let file = std::io::BufWriter::new(file);
+    serde_json::to_writer_pretty(file, &collection)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::AffineTransform;
+    use crate::rendering::overlay::Drawable;
+
+    /// Georeference of 10 meters per pixel in Lambert-93, north up
+    fn lambert93() -> Georeference {
+        Georeference {
+            crs: String::from("EPSG:2154"),
+            transform: AffineTransform {
+                a: 10.,
+                b: 0.,
+                c: 700_000.,
+                d: 0.,
+                e: -10.,
+                f: 6_600_000.,
+            },
+            kind: Default::default(),
+            control_points: vec![],
+        }
+    }
+
+    fn project(drawables: &[Drawable], groups: &[Group], crs: &str) -> Project {
+        let georeference = Georeference {
+            crs: String::from(crs),
+            ..lambert93()
+        };
+        Project::new("", &[], drawables, groups, Some(&georeference), 10.)
+    }
+
+    /// Check that every ring of a polygon ends with its first position
+    fn assert_closed(rings: &Value) {
+        for ring in rings.as_array().unwrap() {
+            let ring = ring.as_array().unwrap();
+            assert!(ring.len() > 3);
+            assert_eq!(ring.first(), ring.last());
+        }
+    }
+
+    #[test]
+    fn not_georeferenced() {
+        let project = Project::new("", &[], &[], &[], None, 1.);
+        assert!(matches!(
+            to_geojson(&project),
+            Err(ProjectError::NotGeoreferenced)
+        ));
+    }
+
+    #[test]
+    fn hidden_groups_skipped() {
+        let groups = [
+            Group {
+                id: 1,
+                name: String::from("shown"),
+                visible: true,
+                locked: false,
+            },
+            Group {
+                id: 2,
+                name: String::from("hidden"),
+                visible: false,
+                locked: false,
+            },
+        ];
+        let point = |id: i32, group: Option<i32>| Drawable {
+            id,
+            object_type: DrawableType::Point,
+            group,
+            ..Default::default()
+        };
+        let drawables = [point(1, None), point(2, Some(1)), point(3, Some(2))];
+        let collection = to_geojson(&project(&drawables, &groups, "EPSG:2154")).unwrap();
+        let features = collection["features"].as_array().unwrap();
+        let ids = features
+            .iter()
+            .map(|f| f["properties"]["id"].as_i64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(features[1]["properties"]["group"], "shown");
+    }
+
+    #[test]
+    fn crs_member_named() {
+        let collection = to_geojson(&project(&[], &[], "EPSG:2154")).unwrap();
+        assert_eq!(
+            collection["crs"]["properties"]["name"],
+            "urn:ogc:def:crs:EPSG::2154"
+        );
+        let collection = to_geojson(&project(&[], &[], WGS84)).unwrap();
+        assert!(collection.get("crs").is_none());
+    }
+
+    #[test]
+    fn circle_ring_closed() {
+        let circle = Drawable {
+            id: 1,
+            object_type: DrawableType::Circle,
+            point1: Point { x: 100., y: 100. },
+            point2: Point { x: 150., y: 100. },
+            ..Default::default()
+        };
+        let collection = to_geojson(&project(&[circle], &[], "EPSG:2154")).unwrap();
+        let feature = &collection["features"][0];
+        assert_eq!(feature["geometry"]["type"], "Polygon");
+        let rings = &feature["geometry"]["coordinates"];
+        assert_eq!(rings.as_array().unwrap().len(), 1);
+        assert_eq!(rings[0].as_array().unwrap().len(), CIRCLE_VERTICES + 1);
+        assert_closed(rings);
+        assert_eq!(
+            feature["properties"]["center"],
+            json!([701_000., 6_599_000.])
+        );
+        let radius = feature["properties"]["radius_m"].as_f64().unwrap();
+        assert!((radius - 500.).abs() < 1., "{}", radius);
+    }
+
+    #[test]
+    fn annulus_has_hole() {
+        let annulus = |end_angle: f32| Drawable {
+            id: 1,
+            object_type: DrawableType::Annulus,
+            point1: Point { x: 100., y: 100. },
+            point2: Point { x: 150., y: 100. },
+            inner_radius: 20.,
+            start_angle: 0.,
+            end_angle,
+            ..Default::default()
+        };
+        // A full annulus is a ring with a hole
+        let collection = to_geojson(&project(&[annulus(0.)], &[], "EPSG:2154")).unwrap();
+        let rings = &collection["features"][0]["geometry"]["coordinates"];
+        assert_eq!(rings.as_array().unwrap().len(), 2);
+        assert_closed(rings);
+
+        // A partial annulus is a single ring around its outline
+        let collection = to_geojson(&project(&[annulus(90.)], &[], "EPSG:2154")).unwrap();
+        let rings = &collection["features"][0]["geometry"]["coordinates"];
+        assert_eq!(rings.as_array().unwrap().len(), 1);
+        assert_closed(rings);
+    }
+}
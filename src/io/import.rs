@@ -0,0 +1,555 @@
+//! Import of geographic data files
+//!
+//! GPX waypoints, routes and tracks, KML placemarks and GeoJSON geometries are read as
+//! named points and paths in real-world coordinates. GPX and KML are always expressed
+//! in WGS84, GeoJSON may name another reference system through its legacy `crs` member.
+
+use std::fs;
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+use serde_json::Value;
+
+use super::geojson::GEOJSON_EXTENSION;
+use super::ProjectError;
+use crate::geo::{projection, Coordinate, WGS84};
+
+/// Extension of GPX files
+pub const GPX_EXTENSION: &str = "gpx";
+
+/// Extension of KML files
+pub const KML_EXTENSION: &str = "kml";
+
+/// Geometry of an imported feature
+#[derive(Clone, Debug, PartialEq)]
+pub enum Geometry {
+    /// A single position, e.g. a waypoint
+    Point(Coordinate),
+    /// A sequence of positions, e.g. a track segment or a polygon ring
+    Path(Vec<Coordinate>),
+}
+
+/// A named geometry read from a data file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feature {
+    /// Name of the feature, empty if the file does not provide one
+    pub name: String,
+    pub geometry: Geometry,
+}
+
+/// Content of a geographic data file
+#[derive(Clone, Debug, Default)]
+pub struct GeoData {
+    /// EPSG code of the reference system of the coordinates
+    pub crs: String,
+    pub features: Vec<Feature>,
+}
+
+impl GeoData {
+    /// Convert the coordinates of the features into another CRS
+    ///
+    /// # Arguments
+    ///
+    /// * `crs` - The EPSG code of the CRS to convert into
+    ///
+    /// # Returns
+    ///
+    /// The converted data, or an error if one of the CRS is not supported
+    pub fn reproject(self, crs: &str) -> Result<GeoData, ProjectError> {
+        let convert = |coordinate| {
+            projection::convert(&self.crs, crs, coordinate).ok_or_else(|| {
+                ProjectError::UnsupportedCrs {
+                    from: self.crs.clone(),
+                    to: String::from(crs),
+                }
+            })
+        };
+        let features = self
+            .features
+            .iter()
+            .map(|feature| {
+                let geometry = match &feature.geometry {
+                    Geometry::Point(coordinate) => Geometry::Point(convert(*coordinate)?),
+                    Geometry::Path(path) => Geometry::Path(
+                        path.iter()
+                            .map(|coordinate| convert(*coordinate))
+                            .collect::<Result<Vec<Coordinate>, ProjectError>>()?,
+                    ),
+                };
+                Ok(Feature {
+                    name: feature.name.clone(),
+                    geometry,
+                })
+            })
+            .collect::<Result<Vec<Feature>, ProjectError>>()?;
+        Ok(GeoData {
+            crs: String::from(crs),
+            features,
+        })
+    }
+}
+
+/// Read a geographic data file, its format being deduced from its extension
+///
+/// # Arguments
+///
+/// * `file` - The path of a GPX, KML or GeoJSON file
+pub fn read_geodata(file: &str) -> Result<GeoData, ProjectError> {
+    let extension = Path::new(file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    let contents = fs::read_to_string(file)?;
+    match extension.as_str() {
+        GPX_EXTENSION => read_gpx(&contents),
+        KML_EXTENSION => read_kml(&contents),
+        GEOJSON_EXTENSION | "json" => read_geojson(&contents),
+        _ => Err(ProjectError::InvalidGeoData(format!(
+            "unknown file type: {}",
+            file
+        ))),
+    }
+}
+
+fn invalid_xml(e: roxmltree::Error) -> ProjectError {
+    ProjectError::InvalidGeoData(e.to_string())
+}
+
+/// Text of the first child element with the given name, empty if there is none
+fn child_text(node: Node, name: &str) -> String {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Read the `lat` and `lon` attributes of a GPX point
+fn gpx_coordinate(node: Node) -> Option<Coordinate> {
+    let lat = node.attribute("lat")?.trim().parse().ok()?;
+    let lon = node.attribute("lon")?.trim().parse().ok()?;
+    Some(Coordinate { x: lon, y: lat })
+}
+
+/// Read a GPX document
+///
+/// Each waypoint gives a point, each route and each track segment a path.
+pub fn read_gpx(contents: &str) -> Result<GeoData, ProjectError> {
+    let document = Document::parse(contents).map_err(invalid_xml)?;
+    let mut features = vec![];
+
+    for node in document.descendants().filter(|node| node.is_element()) {
+        match node.tag_name().name() {
+            "wpt" => {
+                if let Some(coordinate) = gpx_coordinate(node) {
+                    features.push(Feature {
+                        name: child_text(node, "name"),
+                        geometry: Geometry::Point(coordinate),
+                    });
+                }
+            }
+            "rte" => {
+                let path = node
+                    .children()
+                    .filter(|child| child.has_tag_name("rtept"))
+                    .filter_map(gpx_coordinate)
+                    .collect();
+                features.push(Feature {
+                    name: child_text(node, "name"),
+                    geometry: Geometry::Path(path),
+                });
+            }
+            "trkseg" => {
+                let name = node
+                    .parent_element()
+                    .map(|track| child_text(track, "name"))
+                    .unwrap_or_default();
+                let path = node
+                    .children()
+                    .filter(|child| child.has_tag_name("trkpt"))
+                    .filter_map(gpx_coordinate)
+                    .collect();
+                features.push(Feature {
+                    name,
+                    geometry: Geometry::Path(path),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(GeoData {
+        crs: String::from(WGS84),
+        features,
+    })
+}
+
+/// Parse a KML coordinates list, made of `lon,lat[,alt]` tuples separated by spaces
+fn kml_coordinates(text: &str) -> Vec<Coordinate> {
+    text.split_whitespace()
+        .filter_map(|tuple| {
+            let mut values = tuple.split(',');
+            let lon = values.next()?.parse().ok()?;
+            let lat = values.next()?.parse().ok()?;
+            Some(Coordinate { x: lon, y: lat })
+        })
+        .collect()
+}
+
+/// Read a KML document
+///
+/// Each placemark point gives a point, each line string and each polygon ring a path,
+/// all named after their placemark.
+pub fn read_kml(contents: &str) -> Result<GeoData, ProjectError> {
+    let document = Document::parse(contents).map_err(invalid_xml)?;
+    let mut features = vec![];
+
+    for placemark in document
+        .descendants()
+        .filter(|node| node.has_tag_name("Placemark"))
+    {
+        let name = child_text(placemark, "name");
+        for node in placemark.descendants().filter(|node| node.is_element()) {
+            let coordinates = child_text(node, "coordinates");
+            match node.tag_name().name() {
+                "Point" => {
+                    if let Some(&coordinate) = kml_coordinates(&coordinates).first() {
+                        features.push(Feature {
+                            name: name.clone(),
+                            geometry: Geometry::Point(coordinate),
+                        });
+                    }
+                }
+                "LineString" | "LinearRing" => features.push(Feature {
+                    name: name.clone(),
+                    geometry: Geometry::Path(kml_coordinates(&coordinates)),
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(GeoData {
+        crs: String::from(WGS84),
+        features,
+    })
+}
+
+/// Parse a GeoJSON position
+fn geojson_coordinate(position: &Value) -> Option<Coordinate> {
+    let position = position.as_array()?;
+    Some(Coordinate {
+        x: position.first()?.as_f64()?,
+        y: position.get(1)?.as_f64()?,
+    })
+}
+
+/// Parse a GeoJSON list of positions
+fn geojson_path(positions: &Value) -> Vec<Coordinate> {
+    positions
+        .as_array()
+        .map(|positions| positions.iter().filter_map(geojson_coordinate).collect())
+        .unwrap_or_default()
+}
+
+/// Add the features of a GeoJSON geometry, polygons being imported as their rings
+fn add_geojson_geometry(geometry: &Value, name: &str, features: &mut Vec<Feature>) {
+    let coordinates = &geometry["coordinates"];
+    let path = |path: &Value| Feature {
+        name: String::from(name),
+        geometry: Geometry::Path(geojson_path(path)),
+    };
+    match geometry["type"].as_str() {
+        Some("Point") => {
+            if let Some(coordinate) = geojson_coordinate(coordinates) {
+                features.push(Feature {
+                    name: String::from(name),
+                    geometry: Geometry::Point(coordinate),
+                });
+            }
+        }
+        Some("MultiPoint") => {
+            for coordinate in geojson_path(coordinates) {
+                features.push(Feature {
+                    name: String::from(name),
+                    geometry: Geometry::Point(coordinate),
+                });
+            }
+        }
+        Some("LineString") => features.push(path(coordinates)),
+        Some("MultiLineString") | Some("Polygon") => {
+            features.extend(coordinates.as_array().into_iter().flatten().map(path));
+        }
+        Some("MultiPolygon") => {
+            for polygon in coordinates.as_array().into_iter().flatten() {
+                features.extend(polygon.as_array().into_iter().flatten().map(path));
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in geometry["geometries"].as_array().into_iter().flatten() {
+                add_geojson_geometry(geometry, name, features);
+            }
+        }
+        _ => log::warn!("Unsupported GeoJSON geometry: {}", geometry["type"]),
+    }
+}
+
+/// Read the reference system named by the legacy `crs` member of a GeoJSON document
+///
+/// Names such as `urn:ogc:def:crs:EPSG::2154` or `EPSG:2154` are turned into `EPSG:2154`,
+/// documents without `crs` member being in WGS84.
+fn geojson_crs(document: &Value) -> String {
+    let Some(name) = document["crs"]["properties"]["name"].as_str() else {
+        return String::from(WGS84);
+    };
+    if name.ends_with("CRS84") {
+        return String::from(WGS84);
+    }
+    match name.rsplit(':').next() {
+        Some(code) if !code.is_empty() && code.chars().all(|c| c.is_ascii_digit()) => {
+            format!("EPSG:{}", code)
+        }
+        _ => String::from(name),
+    }
+}
+
+/// Read a GeoJSON document, either a feature collection, a feature or a bare geometry
+///
+/// Features are named after their `name` property.
+pub fn read_geojson(contents: &str) -> Result<GeoData, ProjectError> {
+    let document: Value = serde_json::from_str(contents)?;
+    let mut features = vec![];
+
+    let feature_list = match document["type"].as_str() {
+        Some("FeatureCollection") => document["features"]
+            .as_array()
+            .cloned()
+            .ok_or_else(|| ProjectError::InvalidGeoData(String::from("missing features")))?,
+        Some("Feature") => vec![document.clone()],
+        Some(_) => vec![serde_json::json!({ "geometry": document.clone() })],
+        None => {
+            return Err(ProjectError::InvalidGeoData(String::from(
+                "not a GeoJSON document",
+            )))
+        }
+    };
+    for feature in feature_list.iter() {
+        let name = feature["properties"]["name"].as_str().unwrap_or_default();
+        add_geojson_geometry(&feature["geometry"], name, &mut features);
+    }
+
+    Ok(GeoData {
+        crs: geojson_crs(&document),
+        features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn coordinate(x: f64, y: f64) -> Coordinate {
+        Coordinate { x, y }
+    }
+
+    #[test]
+    fn gpx_waypoints_routes_and_tracks() {
+        let data = read_gpx(
+            r#"<?xml version="1.0"?>
+            <gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1">
+              <wpt lat="45.5" lon="6.25"><name> Summit </name></wpt>
+              <wpt lat="not a number" lon="6"/>
+              <rte>
+                <name>Route</name>
+                <rtept lat="45" lon="6"/>
+                <rtept lat="45.1" lon="6.1"/>
+              </rte>
+              <trk>
+                <name>Track</name>
+                <trkseg><trkpt lat="44" lon="5"/><trkpt lat="44.1" lon="5.1"/></trkseg>
+                <trkseg><trkpt lat="44.2" lon="5.2"/></trkseg>
+              </trk>
+            </gpx>"#,
+        )
+        .unwrap();
+        assert_eq!(data.crs, WGS84);
+        assert_eq!(
+            data.features,
+            vec![
+                Feature {
+                    name: String::from("Summit"),
+                    geometry: Geometry::Point(coordinate(6.25, 45.5)),
+                },
+                Feature {
+                    name: String::from("Route"),
+                    geometry: Geometry::Path(vec![coordinate(6., 45.), coordinate(6.1, 45.1)]),
+                },
+                Feature {
+                    name: String::from("Track"),
+                    geometry: Geometry::Path(vec![coordinate(5., 44.), coordinate(5.1, 44.1)]),
+                },
+                Feature {
+                    name: String::from("Track"),
+                    geometry: Geometry::Path(vec![coordinate(5.2, 44.2)]),
+                },
+            ]
+        );
+        assert!(matches!(
+            read_gpx("<gpx><wpt></gpx>"),
+            Err(ProjectError::InvalidGeoData(_))
+        ));
+    }
+
+    #[test]
+    fn kml_placemarks() {
+        let data = read_kml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <kml xmlns="http://www.opengis.net/kml/2.2">
+              <Document>
+                <Placemark>
+                  <name>Camp</name>
+                  <Point><coordinates>6.5,45.25,1200</coordinates></Point>
+                </Placemark>
+                <Placemark>
+                  <name>Trail</name>
+                  <LineString>
+                    <coordinates>
+                      6,45 6.1,45.1,0
+                      6.2,45.2
+                    </coordinates>
+                  </LineString>
+                </Placemark>
+                <Placemark>
+                  <name>Area</name>
+                  <Polygon>
+                    <outerBoundaryIs><LinearRing>
+                      <coordinates>0,0 1,0 1,1 0,0</coordinates>
+                    </LinearRing></outerBoundaryIs>
+                  </Polygon>
+                </Placemark>
+              </Document>
+            </kml>"#,
+        )
+        .unwrap();
+        assert_eq!(data.crs, WGS84);
+        let names = data
+            .features
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Camp", "Trail", "Area"]);
+        assert_eq!(
+            data.features[0].geometry,
+            Geometry::Point(coordinate(6.5, 45.25))
+        );
+        assert_eq!(
+            data.features[1].geometry,
+            Geometry::Path(vec![
+                coordinate(6., 45.),
+                coordinate(6.1, 45.1),
+                coordinate(6.2, 45.2),
+            ])
+        );
+        let Geometry::Path(ring) = &data.features[2].geometry else {
+            panic!("polygon ring read as a point");
+        };
+        assert_eq!(ring.len(), 4);
+    }
+
+    #[test]
+    fn geojson_crs_names() {
+        let named =
+            |name: &str| json!({ "crs": { "type": "name", "properties": { "name": name } } });
+        assert_eq!(geojson_crs(&json!({})), WGS84);
+        assert_eq!(
+            geojson_crs(&named("urn:ogc:def:crs:EPSG::2154")),
+            "EPSG:2154"
+        );
+        assert_eq!(geojson_crs(&named("EPSG:3857")), "EPSG:3857");
+        assert_eq!(geojson_crs(&named("urn:ogc:def:crs:OGC:1.3:CRS84")), WGS84);
+        assert_eq!(geojson_crs(&named("custom")), "custom");
+    }
+
+    #[test]
+    fn geojson_feature_collection() {
+        let data = read_geojson(
+            r#"{
+              "type": "FeatureCollection",
+              "crs": { "type": "name", "properties": { "name": "urn:ogc:def:crs:EPSG::2154" } },
+              "features": [
+                {
+                  "type": "Feature",
+                  "properties": { "name": "Well" },
+                  "geometry": { "type": "Point", "coordinates": [700000, 6600000] }
+                },
+                {
+                  "type": "Feature",
+                  "properties": { "name": "Fields" },
+                  "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [
+                      [
+                        [[0, 0], [10, 0], [10, 10], [0, 0]],
+                        [[2, 2], [3, 2], [3, 3], [2, 2]]
+                      ],
+                      [[[20, 20], [30, 20], [30, 30], [20, 20]]]
+                    ]
+                  }
+                },
+                {
+                  "type": "Feature",
+                  "properties": null,
+                  "geometry": { "type": "LineString", "coordinates": [[1, 2], [3, 4]] }
+                }
+              ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(data.crs, "EPSG:2154");
+        assert_eq!(data.features.len(), 5);
+        assert_eq!(
+            data.features[0].geometry,
+            Geometry::Point(coordinate(700_000., 6_600_000.))
+        );
+        // Each ring of each polygon is a path, holes included
+        for feature in &data.features[1..4] {
+            assert_eq!(feature.name, "Fields");
+            let Geometry::Path(ring) = &feature.geometry else {
+                panic!("polygon ring read as a point");
+            };
+            assert_eq!(ring.len(), 4);
+            assert_eq!(ring.first(), ring.last());
+        }
+        assert_eq!(
+            data.features[4],
+            Feature {
+                name: String::new(),
+                geometry: Geometry::Path(vec![coordinate(1., 2.), coordinate(3., 4.)]),
+            }
+        );
+    }
+
+    #[test]
+    fn geojson_bare_geometry() {
+        let data =
+            read_geojson(r#"{ "type": "MultiPoint", "coordinates": [[1, 2], [3, 4]] }"#).unwrap();
+        assert_eq!(data.crs, WGS84);
+        assert_eq!(
+            data.features
+                .iter()
+                .map(|f| f.geometry.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Geometry::Point(coordinate(1., 2.)),
+                Geometry::Point(coordinate(3., 4.)),
+            ]
+        );
+        assert!(matches!(
+            read_geojson(r#"{ "features": [] }"#),
+            Err(ProjectError::InvalidGeoData(_))
+        ));
+    }
+}
@@ -0,0 +1,393 @@
+//! Project schema migrations
+//!
+//! Project files carry a schema `version`. Files written by an older version of the
+//! application are upgraded one version at a time, each step working on the raw JSON
+//! document, before being deserialized into a `Project`.
+
+use serde_json::{json, Value};
+
+use super::ProjectError;
+
+/// Current version of the project schema
+pub const PROJECT_VERSION: u32 = 13;
+
+/// A migration step, upgrading a document from one version to the next one
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Migration steps, the step at index `n` upgrading a document from version `n` to `n + 1`
+const MIGRATIONS: [Migration; PROJECT_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+    migrate_v8_to_v9,
+    migrate_v9_to_v10,
+    migrate_v10_to_v11,
+    migrate_v11_to_v12,
+    migrate_v12_to_v13,
+];
+
+/// Read the schema version of a project document
+///
+/// Documents written before versioning was introduced have no version and are
+/// considered as version 0.
+///
+/// # Arguments
+///
+/// * `document` - The project document
+pub fn document_version(document: &Value) -> Result<u32, ProjectError> {
+    match document.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| ProjectError::Migration {
+                from: 0,
+                reason: format!("invalid version field: {}", version),
+            }),
+    }
+}
+
+/// Upgrade a project document to the current schema version
+///
+/// # Arguments
+///
+/// * `document` - The project document, in any version up to the current one
+///
+/// # Returns
+///
+/// The document in the current version, or an error if it was written by a newer
+/// version of the application or cannot be upgraded
+pub fn migrate(mut document: Value) -> Result<Value, ProjectError> {
+    let version = document_version(&document)?;
+    if version > PROJECT_VERSION {
+        return Err(ProjectError::UnsupportedVersion {
+            found: version,
+            supported: PROJECT_VERSION,
+        });
+    }
+
+    for from in version..PROJECT_VERSION {
+        log::debug!("Migrating project from version {} to {}", from, from + 1);
+        MIGRATIONS[from as usize](&mut document)
+            .map_err(|reason| ProjectError::Migration { from, reason })?;
+        match document.as_object_mut() {
+            Some(object) => {
+                object.insert(String::from("version"), Value::from(from + 1));
+            }
+            None => {
+                return Err(ProjectError::Migration {
+                    from,
+                    reason: String::from("project is not a JSON object"),
+                })
+            }
+        }
+    }
+    Ok(document)
+}
+
+/// Get the list of drawables of a project document
+fn drawables(document: &mut Value) -> Result<&mut Vec<Value>, String> {
+    document
+        .get_mut("drawables")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| String::from("missing drawables list"))
+}
+
+/// Version 0 to 1: introduce the version field
+///
+/// Points used to be saved as zero radius circles, they are turned back into points.
+fn migrate_v0_to_v1(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        if drawable.get("object_type") == Some(&Value::from("Circle"))
+            && drawable.get("point1") == drawable.get("point2")
+        {
+            drawable.insert(String::from("object_type"), Value::from("Point"));
+        }
+    }
+    Ok(())
+}
+
+/// Version 1 to 2: add the georeference of the background, unknown for older projects
+fn migrate_v1_to_v2(document: &mut Value) -> Result<(), String> {
+    let document = document
+        .as_object_mut()
+        .ok_or_else(|| String::from("project is not a JSON object"))?;
+    document.insert(String::from("georeference"), Value::Null);
+    Ok(())
+}
+
+/// Version 2 to 3: drawables get a name and polylines a list of vertices
+fn migrate_v2_to_v3(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        drawable.insert(String::from("name"), Value::from(""));
+        drawable.insert(String::from("points"), Value::Array(vec![]));
+    }
+    Ok(())
+}
+
+/// Version 3 to 4: georeferences keep the control points they were solved from
+fn migrate_v3_to_v4(document: &mut Value) -> Result<(), String> {
+    if let Some(georeference) = document
+        .get_mut("georeference")
+        .and_then(Value::as_object_mut)
+    {
+        georeference.insert(String::from("kind"), Value::from("Affine"));
+        georeference.insert(String::from("control_points"), Value::Array(vec![]));
+    }
+    Ok(())
+}
+
+/// Version 4 to 5: add the scale of the background, unknown for older projects
+fn migrate_v4_to_v5(document: &mut Value) -> Result<(), String> {
+    let document = document
+        .as_object_mut()
+        .ok_or_else(|| String::from("project is not a JSON object"))?;
+    document.insert(String::from("m_per_px"), Value::Null);
+    Ok(())
+}
+
+/// Version 5 to 6: drawables record the construction they were built from, none for older
+/// projects
+fn migrate_v5_to_v6(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        drawable.insert(String::from("construction"), Value::Null);
+    }
+    Ok(())
+}
+
+/// Version 6 to 7: polygons may be filled, drawables of older projects are not
+fn migrate_v6_to_v7(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        drawable.insert(String::from("fill"), Value::Bool(false));
+    }
+    Ok(())
+}
+
+/// Version 7 to 8: labels hold a text and a font size, drawables of older projects are not
+/// labels and get an empty text with the font size labels had when version 8 was introduced
+fn migrate_v7_to_v8(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        drawable.insert(String::from("text"), Value::from(""));
+        drawable.insert(String::from("font_size"), Value::from(16.));
+    }
+    Ok(())
+}
+
+/// Version 8 to 9: arcs, sectors and annuli hold an inner radius and two bearings, set to
+/// zero for the drawables of older projects
+fn migrate_v8_to_v9(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        for field in ["inner_radius", "start_angle", "end_angle"] {
+            drawable.insert(String::from(field), Value::from(0.));
+        }
+    }
+    Ok(())
+}
+
+/// Version 9 to 10: ellipses hold the length of their second semi-axis, set to zero for
+/// the drawables of older projects
+fn migrate_v9_to_v10(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        drawable.insert(String::from("semi_axis"), Value::from(0.));
+    }
+    Ok(())
+}
+
+/// Version 10 to 11: drawables carry a style, the default one for older projects except
+/// for the round caps polylines and polygons were drawn with
+fn migrate_v10_to_v11(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        let cap = match drawable.get("object_type").and_then(Value::as_str) {
+            Some("Polyline" | "Polygon") => "Round",
+            _ => "Butt",
+        };
+        drawable.insert(
+            String::from("style"),
+            json!({
+                "alpha": 255,
+                "dash": "Solid",
+                "cap": cap,
+                "join": "Round",
+                "fill_color": null,
+                "start_arrow": false,
+                "end_arrow": false,
+            }),
+        );
+    }
+    Ok(())
+}
+
+/// Version 11 to 12: drawables hold a note and tags, empty for older projects
+fn migrate_v11_to_v12(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        drawable.insert(String::from("note"), Value::from(""));
+        drawable.insert(String::from("tags"), json!([]));
+    }
+    Ok(())
+}
+
+/// Version 12 to 13: drawables may belong to a group, older projects have no groups
+fn migrate_v12_to_v13(document: &mut Value) -> Result<(), String> {
+    for drawable in drawables(document)? {
+        let drawable = drawable
+            .as_object_mut()
+            .ok_or_else(|| String::from("drawable is not a JSON object"))?;
+        drawable.insert(String::from("group"), Value::Null);
+    }
+    let document = document
+        .as_object_mut()
+        .ok_or_else(|| String::from("project is not a JSON object"))?;
+    document.insert(String::from("groups"), json!([]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Project;
+    use crate::rendering::overlay::DrawableType;
+    use crate::rendering::style::LineCap;
+
+    /// Project written with version 1 of the schema, with the drawable types of that version
+    fn v1_document() -> Value {
+        json!({
+            "version": 1,
+            "layers": [
+                {"id": 1, "x": 10., "y": 20., "m_per_px": 2., "transparency": 0.5, "file": "layer.png"}
+            ],
+            "background": "map.png",
+            "drawables": [
+                {
+                    "id": 1,
+                    "object_type": "Segment",
+                    "point1": {"x": 0., "y": 0.},
+                    "point2": {"x": 100., "y": 50.},
+                    "color": {"r": 255, "g": 0, "b": 0},
+                    "width": 2.
+                },
+                {
+                    "id": 2,
+                    "object_type": "Circle",
+                    "point1": {"x": 40., "y": 40.},
+                    "point2": {"x": 60., "y": 40.},
+                    "color": {"r": 0, "g": 0, "b": 255},
+                    "width": 1.
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn migrate_v1_to_current() {
+        let document = migrate(v1_document()).unwrap();
+        assert_eq!(document_version(&document).unwrap(), PROJECT_VERSION);
+        assert_eq!(document["georeference"], Value::Null);
+        assert_eq!(document["m_per_px"], Value::Null);
+        assert_eq!(document["groups"], json!([]));
+        for drawable in document["drawables"].as_array().unwrap() {
+            assert_eq!(drawable["name"], "");
+            assert_eq!(drawable["points"], json!([]));
+            assert_eq!(drawable["construction"], Value::Null);
+            assert_eq!(drawable["fill"], false);
+            assert_eq!(drawable["text"], "");
+            assert_eq!(drawable["font_size"], 16.);
+            assert_eq!(drawable["semi_axis"], 0.);
+            assert_eq!(drawable["note"], "");
+            assert_eq!(drawable["tags"], json!([]));
+            assert_eq!(drawable["group"], Value::Null);
+        }
+
+        let project: Project = serde_json::from_value(document).unwrap();
+        assert_eq!(project.version, PROJECT_VERSION);
+        assert_eq!(project.background, "map.png");
+        assert_eq!(project.layers.len(), 1);
+        let [segment, circle] = &project.drawables[..] else {
+            panic!("two drawables expected");
+        };
+        assert_eq!(segment.object_type, DrawableType::Segment);
+        assert_eq!(segment.style.cap, LineCap::Butt);
+        assert_eq!(circle.object_type, DrawableType::Circle);
+        assert_eq!(circle.style.cap, LineCap::Butt);
+        assert_eq!(circle.point2.x, 60.);
+        assert_eq!(circle.width, 1.);
+    }
+
+    #[test]
+    fn migrate_v0_points() {
+        let mut document = v1_document();
+        document.as_object_mut().unwrap().remove("version");
+        assert_eq!(document_version(&document).unwrap(), 0);
+        // Points were saved as zero radius circles
+        let mut point = document["drawables"][1].clone();
+        point["id"] = json!(3);
+        point["point2"] = point["point1"].clone();
+        document["drawables"].as_array_mut().unwrap().push(point);
+
+        let document = migrate(document).unwrap();
+        assert_eq!(document["drawables"][0]["object_type"], "Segment");
+        assert_eq!(document["drawables"][1]["object_type"], "Circle");
+        assert_eq!(document["drawables"][2]["object_type"], "Point");
+        let project: Project = serde_json::from_value(document).unwrap();
+        let point = &project.drawables[2];
+        assert_eq!(point.object_type, DrawableType::Point);
+        assert_eq!(point.point1, point.point2);
+    }
+
+    #[test]
+    fn migrate_v10_polyline_caps() {
+        let mut document = migrate(v1_document()).unwrap();
+        document["version"] = Value::from(10);
+        let drawables = document["drawables"].as_array_mut().unwrap();
+        for drawable in drawables.iter_mut() {
+            drawable.as_object_mut().unwrap().remove("style");
+        }
+        drawables[0]["object_type"] = json!("Polyline");
+        drawables[0]["points"] = json!([{"x": 0., "y": 0.}, {"x": 100., "y": 50.}]);
+
+        let project: Project = serde_json::from_value(migrate(document).unwrap()).unwrap();
+        assert_eq!(project.drawables[0].style.cap, LineCap::Round);
+        assert_eq!(project.drawables[1].style.cap, LineCap::Butt);
+    }
+
+    #[test]
+    fn migrate_newer_version() {
+        let mut document = v1_document();
+        document["version"] = Value::from(PROJECT_VERSION + 1);
+        assert!(matches!(
+            migrate(document),
+            Err(ProjectError::UnsupportedVersion { found, .. }) if found == PROJECT_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,552 @@
+pub mod geojson;
+pub mod import;
+mod migration;
+pub mod recovery;
+
+use std::io::{Read, Error, ErrorKind};
+use std::fs::File;
+use std::convert::From;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{math::Point, rendering::overlay::{Color, DrawableType, Drawable}};
+use crate::rendering::construction::Construction;
+use crate::rendering::group::Group;
+use crate::rendering::style::Style;
+use crate::geo::Georeference;
+use crate::LayerDrawable;
+
+pub use migration::PROJECT_VERSION;
+
+/// Errors raised while loading or saving a project
+#[derive(Debug)]
+pub enum ProjectError {
+    /// The file cannot be read or written
+    Io(Error),
+    /// The file content is not a valid project
+    Parse(serde_json::Error),
+    /// The project was written by a newer version of the application
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// The bundle container was written by a newer version of the application
+    UnsupportedBundleVersion { found: u32, supported: u32 },
+    /// An older project cannot be upgraded to the current version
+    Migration { from: u32, reason: String },
+    /// The operation needs real-world coordinates but the background is not georeferenced
+    NotGeoreferenced,
+    /// Coordinates cannot be converted from one reference system to another
+    UnsupportedCrs { from: String, to: String },
+    /// A geographic data file cannot be interpreted
+    InvalidGeoData(String),
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::Io(e) => write!(f, "{}", e),
+            ProjectError::Parse(e) => write!(f, "invalid project file ({})", e),
+            ProjectError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "project version {} was written by a newer version of the application \
+                 (latest supported is {})",
+                found, supported
+            ),
+            ProjectError::UnsupportedBundleVersion { found, supported } => write!(
+                f,
+                "bundle version {} was written by a newer version of the application \
+                 (latest supported is {})",
+                found, supported
+            ),
+            ProjectError::Migration { from, reason } => write!(
+                f,
+                "cannot upgrade project from version {}: {}",
+                from, reason
+            ),
+            ProjectError::NotGeoreferenced => write!(f, "the background is not georeferenced"),
+            ProjectError::UnsupportedCrs { from, to } => write!(
+                f,
+                "coordinates in {} cannot be converted to {}",
+                from, to
+            ),
+            ProjectError::InvalidGeoData(reason) => write!(f, "invalid data file ({})", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
+
+impl From<Error> for ProjectError {
+    fn from(e: Error) -> ProjectError {
+        ProjectError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ProjectError {
+    fn from(e: serde_json::Error) -> ProjectError {
+        ProjectError::Parse(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ProjectError {
+    fn from(e: zip::result::ZipError) -> ProjectError {
+        ProjectError::Io(Error::from(e))
+    }
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProjectLayer {
+    pub id: i32,
+    pub x: f32,
+    pub y: f32,
+    pub m_per_px: f32,
+    pub transparency: f32,
+    pub file: String,
+}
+
+impl From<LayerDrawable> for ProjectLayer {
+    fn from(layer: LayerDrawable) -> ProjectLayer {
+        ProjectLayer {
+            id: layer.id,
+            x: layer.x,
+            y: layer.y,
+            m_per_px: layer.m_per_px,
+            transparency: layer.transparency,
+            file: layer.file.clone().to_string(),
+        }
+    }
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProjectDrawable {
+    pub id: i32,
+    pub object_type: DrawableType,
+    pub point1: Point,
+    pub point2: Point,
+    pub points: Vec<Point>,
+    pub fill: bool,
+    pub text: String,
+    pub font_size: f32,
+    pub inner_radius: f32,
+    pub start_angle: f32,
+    pub end_angle: f32,
+    pub semi_axis: f32,
+    pub name: String,
+    pub note: String,
+    pub tags: Vec<String>,
+    pub group: Option<i32>,
+    pub construction: Option<Construction>,
+    pub color: Color,
+    pub width: f32,
+    pub style: Style,
+}
+
+impl From<Drawable> for ProjectDrawable {
+    fn from(layer: Drawable) -> ProjectDrawable {
+        ProjectDrawable {
+            id: layer.id,
+            object_type: layer.object_type,
+            point1: layer.point1,
+            point2: layer.point2,
+            points: layer.points,
+            fill: layer.fill,
+            text: layer.text,
+            font_size: layer.font_size,
+            inner_radius: layer.inner_radius,
+            start_angle: layer.start_angle,
+            end_angle: layer.end_angle,
+            semi_axis: layer.semi_axis,
+            name: layer.name,
+            note: layer.note,
+            tags: layer.tags,
+            group: layer.group,
+            construction: layer.construction,
+            color: layer.color,
+            width: layer.width,
+            style: layer.style,
+        }
+    }
+}
+
+/// Extension of self-contained project bundles
+pub const BUNDLE_EXTENSION: &str = "mrsz";
+
+/// Version of the bundle container layout, written in the bundle manifest
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Name of the manifest entry in a project bundle
+const BUNDLE_MANIFEST: &str = "manifest.json";
+
+/// Name of the project entry in a project bundle
+const BUNDLE_PROJECT: &str = "project.mrs";
+
+/// Describes the content of a project bundle
+///
+/// A bundle is a zip archive holding this manifest, the project file and all the
+/// images it uses. Image paths in the bundled project are relative to the archive root.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct BundleManifest {
+    format_version: u32,
+    project: String,
+    images: Vec<String>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct Project {
+    pub version: u32,
+    pub layers: Vec<ProjectLayer>,
+    pub background: String,
+    pub drawables: Vec<ProjectDrawable>,
+    pub groups: Vec<Group>,
+    pub georeference: Option<Georeference>,
+    /// Meters per pixel of the background, unknown for older projects
+    pub m_per_px: Option<f32>,
+}
+
+impl Project {
+    pub fn new(
+        background: &str,
+        layers: &[LayerDrawable],
+        drawables: &[Drawable],
+        groups: &[Group],
+        georeference: Option<&Georeference>,
+        m_per_px: f32,
+    ) -> Project {
+        Project {
+            version: PROJECT_VERSION,
+            layers: layers.iter().map(|layer| ProjectLayer::from(layer.clone())).collect(),
+            background: String::from(background),
+            drawables: drawables.iter().map(|drawable| ProjectDrawable::from(drawable.clone())).collect(),
+            groups: groups.to_vec(),
+            georeference: georeference.cloned(),
+            m_per_px: Some(m_per_px),
+        }
+    }
+
+    /// Load a project file, either a plain project or a project bundle
+    pub fn load_project(file: &str) -> Result<Project, ProjectError> {
+        if is_bundle(file)? {
+            return Project::load_bundle(file);
+        }
+        let mut file = File::open(file)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Project::from_json(&contents)
+    }
+
+    /// Parse a project document, upgrading it first if it was written by an older version
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - The JSON project document
+    pub fn from_json(contents: &str) -> Result<Project, ProjectError> {
+        let document: serde_json::Value = serde_json::from_str(contents)?;
+        let document = migration::migrate(document)?;
+        let project: Project = serde_json::from_value(document)?;
+        Ok(project)
+    }
+
+    pub fn save_project(&mut self, file: &str) -> Result<(), ProjectError> {
+        let file = File::create(file)?;
+        let file = std::io::BufWriter::new(file);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+    /// Save the project as a bundle embedding the background and all layer images
+    ///
+    /// The bundle is first written to a temporary file in the same folder, then renamed over
+    /// the target, so a failure while saving leaves the previous bundle untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path of the bundle to be written
+    pub fn save_bundle(&self, file: &str) -> Result<(), ProjectError> {
+        let mut bundled = self.clone();
+        let mut images = vec![];
+
+        if !self.background.is_empty() {
+            bundled.background = bundle_image_name("background", &self.background)?;
+            images.push((bundled.background.clone(), self.background.clone()));
+        }
+        for layer in bundled.layers.iter_mut() {
+            let name = bundle_image_name(&format!("layers/{}", layer.id), &layer.file)?;
+            images.push((name.clone(), layer.file.clone()));
+            layer.file = name;
+        }
+
+        let manifest = BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            project: String::from(BUNDLE_PROJECT),
+            images: images.iter().map(|(name, _)| name.clone()).collect(),
+        };
+
+        let target = Path::new(file);
+        let temporary = temporary_path(target);
+        let result = Self::write_bundle(&temporary, &manifest, &bundled, &images)
+            .and_then(|()| std::fs::rename(&temporary, target).map_err(ProjectError::from));
+        if result.is_err() {
+            if let Err(e) = std::fs::remove_file(&temporary) {
+                log::warn!("Cannot remove {:?}: {:?}", temporary, e);
+            }
+        }
+        result
+    }
+
+    /// Write the content of a bundle
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path of the bundle to be written
+    /// * `manifest` - The manifest of the bundle
+    /// * `bundled` - The project, pointing to the images inside the bundle
+    /// * `images` - The name of each image inside the bundle and the file it is read from
+    fn write_bundle(
+        file: &Path,
+        manifest: &BundleManifest,
+        bundled: &Project,
+        images: &[(String, String)],
+    ) -> Result<(), ProjectError> {
+        let file = File::create(file)?;
+        let mut writer = ZipWriter::new(std::io::BufWriter::new(file));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        // Images are already compressed, no need to deflate them again
+        let image_options =
+            SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        writer.start_file(BUNDLE_MANIFEST, options)?;
+        serde_json::to_writer(&mut writer, manifest)?;
+        writer.start_file(BUNDLE_PROJECT, options)?;
+        serde_json::to_writer(&mut writer, bundled)?;
+
+        for (name, source) in images {
+            writer.start_file(name.as_str(), image_options)?;
+            let mut source = File::open(source)?;
+            std::io::copy(&mut source, &mut writer)?;
+        }
+        let file = writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Load a project bundle, extracting its images next to each other in a cache folder
+    ///
+    /// The returned project points to the extracted images, so it can be used as a plain project.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path of the bundle to be read
+    pub fn load_bundle(file: &str) -> Result<Project, ProjectError> {
+        let mut archive = ZipArchive::new(std::io::BufReader::new(File::open(file)?))?;
+
+        let manifest: BundleManifest = serde_json::from_reader(archive.by_name(BUNDLE_MANIFEST)?)?;
+        if manifest.format_version > BUNDLE_FORMAT_VERSION {
+            return Err(ProjectError::UnsupportedBundleVersion {
+                found: manifest.format_version,
+                supported: BUNDLE_FORMAT_VERSION,
+            });
+        }
+
+        let mut contents = String::new();
+        archive
+            .by_name(&manifest.project)?
+            .read_to_string(&mut contents)?;
+        let mut project = Project::from_json(&contents)?;
+
+        let folder = bundle_extraction_folder(file);
+        project.background = extract_bundle_image(&mut archive, &project.background, &folder)?;
+        for layer in project.layers.iter_mut() {
+            layer.file = extract_bundle_image(&mut archive, &layer.file, &folder)?;
+        }
+        Ok(project)
+    }
+}
+
+/// Check whether a file is a project bundle, by looking for the zip signature
+fn is_bundle(file: &str) -> Result<bool, Error> {
+    let mut signature = [0u8; 4];
+    let mut file = File::open(file)?;
+    match file.read_exact(&mut signature) {
+        Ok(()) => Ok(&signature == b"PK\x03\x04"),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Temporary file a file is written to before being renamed over it
+///
+/// The temporary file is in the same folder, so that the rename does not cross file
+/// systems, and its name is the full name of the file followed by `.tmp`, so that it does
+/// not collide with the files of other extensions.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Build the name of an image inside a bundle, keeping its original file name
+///
+/// # Arguments
+///
+/// * `folder` - The folder of the image inside the bundle
+/// * `file` - The path of the image on disk
+fn bundle_image_name(folder: &str, file: &str) -> Result<String, Error> {
+    let filename = Path::new(file)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, format!("Invalid image path: {}", file))
+        })?;
+    Ok(format!("images/{}/{}", folder, filename))
+}
+
+/// Folder the images of a bundle are extracted to
+///
+/// Each bundle gets its own folder in the temporary directory, based on its full path.
+fn bundle_extraction_folder(file: &str) -> PathBuf {
+    let path = Path::new(file);
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("bundle");
+    std::env::temp_dir()
+        .join("mapannot_rs")
+        .join(format!("{}-{:016x}", stem, hasher.finish()))
+}
+
+/// Extract an image from a bundle
+///
+/// # Arguments
+///
+/// * `archive` - The bundle archive
+/// * `name` - The name of the image inside the bundle
+/// * `folder` - The folder the image is extracted to
+///
+/// # Returns
+///
+/// The path of the extracted image
+fn extract_bundle_image<R: std::io::Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+    folder: &Path,
+) -> Result<String, Error> {
+    if name.is_empty() {
+        return Ok(String::new());
+    }
+    let mut entry = archive.by_name(name)?;
+    let relative = entry.enclosed_name().ok_or_else(|| {
+        Error::new(ErrorKind::InvalidData, format!("Invalid image name in bundle: {}", name))
+    })?;
+    let path = folder.join(relative);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut output = File::create(&path)?;
+    std::io::copy(&mut entry, &mut output)?;
+    path.to_str().map(String::from).ok_or_else(|| {
+        Error::new(ErrorKind::InvalidData, format!("Invalid extraction path for {}", name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// Empty folder in the temporary directory, unique to a test
+    fn test_folder(name: &str) -> PathBuf {
+        let name = format!("{}-{}", name, std::process::id());
+        let folder = std::env::temp_dir().join("mapannot_rs_tests").join(name);
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn temporary_path_keeps_extension() {
+        assert_eq!(
+            temporary_path(Path::new("/maps/map.mrsz")),
+            PathBuf::from("/maps/map.mrsz.tmp")
+        );
+        assert_ne!(
+            temporary_path(Path::new("map.mrs")),
+            temporary_path(Path::new("map.mrsz"))
+        );
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let folder = test_folder("bundle_round_trip");
+        let background = folder.join("background.png");
+        std::fs::write(&background, b"background image").unwrap();
+        let drawable = Drawable {
+            id: 1,
+            object_type: DrawableType::Segment,
+            point2: Point { x: 10., y: 20. },
+            ..Default::default()
+        };
+        let project = Project::new(
+            background.to_str().unwrap(),
+            &[],
+            &[drawable],
+            &[],
+            None,
+            2.5,
+        );
+
+        let bundle = folder.join("map.mrsz");
+        // A file sharing the stem of the bundle is left alone
+        let neighbour = folder.join("map.tmp");
+        std::fs::write(&neighbour, b"user file").unwrap();
+        std::fs::write(&bundle, b"previous bundle").unwrap();
+        project.save_bundle(bundle.to_str().unwrap()).unwrap();
+        assert!(!temporary_path(&bundle).exists());
+        assert_eq!(std::fs::read(&neighbour).unwrap(), b"user file");
+
+        let loaded = Project::load_bundle(bundle.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.m_per_px, Some(2.5));
+        assert_eq!(loaded.drawables.len(), 1);
+        assert_eq!(loaded.drawables[0].point2, Point { x: 10., y: 20. });
+        assert_ne!(loaded.background, project.background);
+        assert_eq!(
+            std::fs::read(&loaded.background).unwrap(),
+            b"background image"
+        );
+    }
+
+    #[test]
+    fn bundle_entries_outside_folder_rejected() {
+        let folder = test_folder("bundle_outside_folder");
+        let mut project = Project::new("", &[], &[], &[], None, 1.);
+        project.background = String::from("../escaped.png");
+        let manifest = BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            project: String::from(BUNDLE_PROJECT),
+            images: vec![project.background.clone()],
+        };
+
+        let bundle = folder.join("evil.mrsz");
+        let mut writer = ZipWriter::new(File::create(&bundle).unwrap());
+        let options = SimpleFileOptions::default();
+        writer.start_file(BUNDLE_MANIFEST, options).unwrap();
+        serde_json::to_writer(&mut writer, &manifest).unwrap();
+        writer.start_file(BUNDLE_PROJECT, options).unwrap();
+        serde_json::to_writer(&mut writer, &project).unwrap();
+        writer.start_file("../escaped.png", options).unwrap();
+        writer.write_all(b"escaped").unwrap();
+        writer.finish().unwrap();
+
+        let bundle = bundle.to_str().unwrap();
+        assert!(matches!(
+            Project::load_bundle(bundle),
+            Err(ProjectError::Io(e)) if e.kind() == ErrorKind::InvalidData
+        ));
+        let extraction = bundle_extraction_folder(bundle);
+        assert!(!extraction.parent().unwrap().join("escaped.png").exists());
+    }
+}
@@ -0,0 +1,270 @@
+//! Autosave and crash recovery
+//!
+//! While a project has unsaved changes, it is periodically written to a recovery file
+//! next to the project file, or in the temporary folder for untitled projects. A session
+//! marker pointing to that recovery file is kept in the temporary folder and removed when
+//! the application exits cleanly. Each running instance has its own marker, named after
+//! its process identifier and locked as long as the instance runs, so an unlocked marker
+//! found on start-up means its session did not terminate properly and its recovery file
+//! can be offered for restoration.
+
+use std::fs::{self, File, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{temporary_path, Project, ProjectError};
+
+/// Delay between two autosaves of a modified project
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Prefix of the name of the session markers in the application temporary folder
+const SESSION_MARKER_PREFIX: &str = "session-";
+
+/// Extension of the session markers
+const SESSION_MARKER_EXTENSION: &str = "json";
+
+/// Suffix added to the project file stem to name its recovery file
+const RECOVERY_SUFFIX: &str = "recovery";
+
+/// Session marker content, describing the state to be recovered after a crash
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Session {
+    /// Project file being edited, empty for an untitled project
+    pub project_file: String,
+    /// Last autosave of the project
+    pub recovery_file: String,
+    /// Marker the session was read from
+    #[serde(skip)]
+    marker: PathBuf,
+}
+
+/// Folder holding the session marker and the recovery file of untitled projects
+fn application_folder() -> PathBuf {
+    std::env::temp_dir().join("mapannot_rs")
+}
+
+/// Session marker of the current process
+fn session_marker() -> PathBuf {
+    application_folder().join(format!(
+        "{}{}.{}",
+        SESSION_MARKER_PREFIX,
+        std::process::id(),
+        SESSION_MARKER_EXTENSION
+    ))
+}
+
+/// Path of the recovery file of a project
+///
+/// # Arguments
+///
+/// * `project_file` - The project file, empty for an untitled project
+///
+/// # Returns
+///
+/// `map.recovery.mrs` next to `map.mrs`, or a file in the temporary folder for an
+/// untitled project
+pub fn recovery_file(project_file: &str) -> PathBuf {
+    let path = Path::new(project_file);
+    match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) if !project_file.is_empty() => path.with_file_name(format!(
+            "{}.{}.{}",
+            stem,
+            RECOVERY_SUFFIX,
+            crate::fileselector::PROJECT_EXTENSION
+        )),
+        _ => application_folder().join(format!(
+            "untitled-{}.{}.{}",
+            std::process::id(),
+            RECOVERY_SUFFIX,
+            crate::fileselector::PROJECT_EXTENSION
+        )),
+    }
+}
+
+/// Look for a session that did not terminate properly
+///
+/// Markers still locked belong to running instances and are left alone, other markers
+/// whose recovery file is gone or which cannot be read are removed.
+///
+/// # Returns
+///
+/// A previous session, if its marker was left behind and its recovery file still exists
+pub fn unclean_session() -> Option<Session> {
+    let mut markers = fs::read_dir(application_folder())
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == SESSION_MARKER_EXTENSION)
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(SESSION_MARKER_PREFIX))
+        })
+        .collect::<Vec<PathBuf>>();
+    markers.sort();
+    markers.into_iter().find_map(|marker| read_session(&marker))
+}
+
+/// Read the session of a marker which is not locked by a running instance
+///
+/// # Returns
+///
+/// The session, or `None` if it is still running or has nothing to recover
+fn read_session(marker: &Path) -> Option<Session> {
+    let mut file = File::open(marker).ok()?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return None,
+        Err(TryLockError::Error(e)) => {
+            log::warn!("Cannot lock session marker {:?}: {:?}", marker, e);
+            return None;
+        }
+    }
+    let mut contents = String::new();
+    let session = file
+        .read_to_string(&mut contents)
+        .map_err(|e| e.to_string())
+        .and_then(|_| serde_json::from_str::<Session>(&contents).map_err(|e| e.to_string()));
+    drop(file);
+    match session {
+        Ok(session) if Path::new(&session.recovery_file).exists() => Some(Session {
+            marker: marker.to_path_buf(),
+            ..session
+        }),
+        Ok(_) => {
+            remove_file(marker);
+            None
+        }
+        Err(e) => {
+            log::warn!("Invalid session marker {:?}: {}", marker, e);
+            remove_file(marker);
+            None
+        }
+    }
+}
+
+/// Remove a file, ignoring files which do not exist
+fn remove_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Cannot remove {:?}: {:?}", path, e);
+        }
+    }
+}
+
+/// Keeps track of the recovery file written by the current session
+#[derive(Debug, Default)]
+pub struct Autosave {
+    recovery_file: Option<PathBuf>,
+    /// Session marker of the current process, locked while it exists
+    marker: Option<File>,
+}
+
+impl Autosave {
+    pub fn new() -> Autosave {
+        Autosave::default()
+    }
+
+    /// Take over the recovery file of a previous session
+    ///
+    /// The file is then replaced by the next autosave, or removed once the project is saved.
+    /// The marker of the previous session is replaced by the one of the current process.
+    pub fn adopt(&mut self, session: &Session) {
+        self.recovery_file = Some(PathBuf::from(&session.recovery_file));
+        if let Err(e) = self.write_marker(&session.project_file, &session.recovery_file) {
+            log::warn!("Cannot write session marker: {:?}", e);
+            return;
+        }
+        remove_file(&session.marker);
+    }
+
+    /// Write the session marker of the current process, creating and locking it if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `project_file` - The project file, empty for an untitled project
+    /// * `recovery_file` - The last autosave of the project
+    fn write_marker(
+        &mut self,
+        project_file: &str,
+        recovery_file: &str,
+    ) -> Result<(), ProjectError> {
+        let session = Session {
+            project_file: String::from(project_file),
+            recovery_file: String::from(recovery_file),
+            marker: PathBuf::new(),
+        };
+        let contents = serde_json::to_string(&session)?;
+        let marker = match self.marker.as_mut() {
+            Some(marker) => marker,
+            None => {
+                fs::create_dir_all(application_folder())?;
+                let marker = File::options()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(session_marker())?;
+                marker.lock()?;
+                self.marker.insert(marker)
+            }
+        };
+        marker.set_len(0)?;
+        marker.rewind()?;
+        marker.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    /// Write the project to its recovery file and update the session marker
+    ///
+    /// The project is first written to a temporary file, so a crash while saving does not
+    /// corrupt the previous recovery file.
+    ///
+    /// # Arguments
+    ///
+    /// * `project_file` - The project file, empty for an untitled project
+    /// * `project` - The current state of the project
+    pub fn save(&mut self, project_file: &str, project: &mut Project) -> Result<(), ProjectError> {
+        let path = recovery_file(project_file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = temporary_path(&path);
+        project.save_project(&temporary.to_string_lossy())?;
+        fs::rename(&temporary, &path)?;
+
+        // The project may have been saved under another name since the last autosave
+        if let Some(previous) = self.recovery_file.replace(path.clone()) {
+            if previous != path {
+                remove_file(&previous);
+            }
+        }
+
+        self.write_marker(project_file, &path.to_string_lossy())?;
+        log::debug!("Project autosaved to {:?}", path);
+        Ok(())
+    }
+
+    /// Remove the recovery file and the session marker, once the project is saved or discarded
+    pub fn clear(&mut self) {
+        if let Some(previous) = self.recovery_file.take() {
+            remove_file(&previous);
+        }
+        // The marker is unlocked before being removed
+        if self.marker.take().is_some() {
+            remove_file(&session_marker());
+        }
+    }
+}
+
+/// Discard the state left by a previous session
+///
+/// # Arguments
+///
+/// * `session` - The previous session
+pub fn discard_session(session: &Session) {
+    remove_file(Path::new(&session.recovery_file));
+    remove_file(&session.marker);
+}
@@ -1,11 +1,21 @@
 mod fileselector;
+mod geo;
+mod history;
 mod io;
 mod math;
 mod rendering;
 mod utils;
 
-use std::{cell::RefCell, path::PathBuf, process::exit, rc::Rc};
+use std::{cell::RefCell, collections::HashSet, path::PathBuf, process::exit, rc::Rc};
 
+use fileselector::FileFilter;
+use geo::gcp::{self, GroundControlPoint, TransformKind};
+use geo::projection;
+use geo::{AffineTransform, Coordinate, Georeference};
+use history::{Command, History, ProjectSnapshot, DEFAULT_HISTORY_CAPACITY};
+use io::import::{Feature, Geometry};
+use io::recovery::{self, Autosave, AUTOSAVE_INTERVAL};
+use io::ProjectError;
 use log::debug;
 use math::Point;
 use slint::{Model, SharedString, StandardListViewItem, VecModel};
@@ -13,13 +23,1193 @@ use slint::{Model, SharedString, StandardListViewItem, VecModel};
 use rendering::{
     background::BackgroundRenderer,
     layer::LayerRenderer,
-    overlay::{Circle, OverlayRenderer},
+    construction::{Anchor, Construction},
+    group::Group,
+    overlay::{Circle, Color, Drawable, DrawableType, OverlayRenderer, Snap},
+    style::{DashPattern, LineCap, LineJoin, Style},
 };
 
-use std::env::current_dir;
-
 slint::include_modules!();
 
+/// Distance under which pressing the mouse grabs a handle or the body of the selected
+/// drawable, in screen pixels
+const DRAG_TOLERANCE: f32 = 6.;
+
+/// Shortest semi-axis of an ellipse drawn by clicks, in map pixels, flatter ellipses being
+/// invisible
+const MIN_SEMI_AXIS: f32 = 1.;
+
+/// A drawable being dragged on the map
+struct Drag {
+    /// The dragged drawable and the drawables built from it, as they were before the drag
+    before: Vec<Drawable>,
+    /// Index of the dragged handle, `None` when the whole drawable is moved
+    handle: Option<usize>,
+    /// Position the drag started from
+    start: Point,
+    /// Whether the drawable was moved since the drag started
+    moved: bool,
+}
+
+/// Push the drawables of the overlay to the UI, both as overlay tiles and as object list
+/// items
+///
+/// Only the drawables matching the search query of the UI are listed, the others being
+/// dimmed on the map. Drawables of hidden groups are neither listed nor shown. The group
+/// list is refreshed as well.
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `overlay` - The overlay renderer
+/// * `selected_item` - The identifier of the selected drawable, if any
+fn update_overlay_model(ui: &AppWindow, overlay: &mut OverlayRenderer, selected_item: Option<i32>) {
+    update_groups_model(ui, overlay);
+
+    let query = ui.get_object_filter();
+    let mut my_vec = vec![];
+    let mut dimmed = HashSet::new();
+    for dd in overlay.get_drawables() {
+        if !overlay.is_visible(&dd) {
+            overlay.set_listview_id(dd.id, -1);
+            if selected_item == Some(dd.id) {
+                ui.set_current_listview_drawable_item(-1);
+            }
+            continue;
+        }
+        if !dd.matches(&query) {
+            overlay.set_listview_id(dd.id, -1);
+            dimmed.insert(dd.id);
+            if selected_item == Some(dd.id) {
+                ui.set_current_listview_drawable_item(-1);
+            }
+            continue;
+        }
+        let s = slint::StandardListViewItem::from(slint::SharedString::from(dd.label().as_str()));
+        my_vec.push(s);
+        overlay.set_listview_id(dd.id, my_vec.len() as i32 - 1);
+        if selected_item == Some(dd.id) {
+            ui.set_current_listview_drawable_item(my_vec.len() as i32 - 1);
+        }
+    }
+    let model = slint::ModelRc::new(VecModel::from(my_vec));
+    ui.set_item_list(model);
+
+    overlay.set_dimmed(dimmed);
+    update_overlay_tiles(ui, overlay);
+}
+
+/// Render the dirty tiles of the overlay in view and push them to the UI
+///
+/// The overlay follows the part of the map in view and its zoom, so that it is rendered
+/// at the resolution it is displayed with. Only the rendered tiles are replaced in the
+/// model of the UI, unless other tiles came into view.
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `overlay` - The overlay renderer
+fn update_overlay_tiles(ui: &AppWindow, overlay: &mut OverlayRenderer) {
+    let zoom = ui.get_viewport_zoom();
+    if let Some(view) = tiny_skia::Rect::from_xywh(
+        ui.get_view_x() * zoom,
+        ui.get_view_y() * zoom,
+        ui.get_view_width() * zoom,
+        ui.get_view_height() * zoom,
+    ) {
+        let width_scale = if ui.get_constant_stroke_width() {
+            zoom
+        } else {
+            1.
+        };
+        overlay.set_view(view, ui.window().scale_factor() / zoom, width_scale);
+    }
+
+    let rendered = overlay.render_tiles();
+    let visible = overlay.visible_tiles();
+    let model = ui.get_overlay_tiles();
+    let same_tiles = model
+        .as_any()
+        .downcast_ref::<VecModel<OverlayTile>>()
+        .filter(|tiles| {
+            tiles
+                .iter()
+                .map(|tile| tile.id as usize)
+                .eq(visible.iter().copied())
+        });
+    if let Some(tiles) = same_tiles {
+        for (row, index) in visible.iter().enumerate() {
+            if rendered.contains(index) {
+                tiles.set_row_data(row, overlay.tile(*index));
+            }
+        }
+    } else {
+        let tiles = visible
+            .iter()
+            .map(|index| overlay.tile(*index))
+            .collect::<Vec<OverlayTile>>();
+        debug!("Overlay tiles count: {}", tiles.len());
+        ui.set_overlay_tiles(slint::ModelRc::new(VecModel::from(tiles)));
+    }
+}
+
+/// Show the groups of the overlay in the UI, along with the group new drawables are
+/// added to
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `overlay` - The overlay renderer
+fn update_groups_model(ui: &AppWindow, overlay: &OverlayRenderer) {
+    let current = overlay.current_group();
+    let groups_list = VecModel::from(
+        overlay
+            .groups
+            .iter()
+            .map(|group| {
+                let count = overlay
+                    .drawables
+                    .iter()
+                    .filter(|d| d.group == Some(group.id))
+                    .count();
+                StandardListViewItem::from(SharedString::from(
+                    group.label(count, current == Some(group.id)),
+                ))
+            })
+            .collect::<Vec<StandardListViewItem>>(),
+    );
+    ui.set_groups_list(slint::ModelRc::new(groups_list));
+    if ui.get_selected_group() >= overlay.groups.len() as i32 {
+        ui.set_selected_group(-1);
+    }
+    ui.set_current_group_name(SharedString::from(
+        current
+            .and_then(|id| overlay.group(id))
+            .map(|group| group.name.as_str())
+            .unwrap_or("None"),
+    ));
+}
+
+/// Move the selected drawable to another group, recording it in the history
+///
+/// # Arguments
+///
+/// * `ui` - The application window, giving the selected drawable
+/// * `overlay` - The overlay renderer
+/// * `history` - The command history
+/// * `target` - The group to move the drawable to, `None` to remove it from its group
+///
+/// # Returns
+///
+/// The message to display to the user
+fn regroup_selected(
+    ui: &AppWindow,
+    overlay: &mut OverlayRenderer,
+    history: &mut History,
+    target: Option<&Group>,
+) -> String {
+    let listview_id = ui.get_current_listview_drawable_item();
+    let Some(drawable) = overlay.listed_drawable(listview_id).cloned() else {
+        return String::from("No object selected");
+    };
+    if overlay.is_locked(&drawable) {
+        return format!("{} is in a locked group", drawable.label());
+    }
+    let moved = Drawable {
+        group: target.map(|group| group.id),
+        ..drawable.clone()
+    };
+    overlay.replace_drawable(moved.clone());
+    history.push(Command::EditDrawables {
+        before: vec![drawable.clone()],
+        after: vec![moved],
+    });
+    match target {
+        Some(group) => format!("Object {} moved to group {}", drawable.id, group.name),
+        None => format!("Object {} removed from its group", drawable.id),
+    }
+}
+
+/// Apply an action of the group panel to the selected group, recording it in the history
+///
+/// # Arguments
+///
+/// * `ui` - The application window, giving the selected group, the typed name, the
+///   selected drawable and the palette
+/// * `overlay` - The overlay renderer
+/// * `history` - The command history
+/// * `action` - The action to apply
+///
+/// # Returns
+///
+/// The message to display to the user
+fn apply_group_action(
+    ui: &AppWindow,
+    overlay: &mut OverlayRenderer,
+    history: &mut History,
+    action: GroupAction,
+) -> String {
+    let name = ui.get_group_name().trim().to_string();
+    // Actions which do not depend on the selected group
+    match action {
+        GroupAction::Add => {
+            let name = if name.is_empty() {
+                format!("Group {}", overlay.groups.len() + 1)
+            } else {
+                name
+            };
+            let group = overlay.add_group(&name);
+            overlay.set_current_group(Some(group.id));
+            ui.set_selected_group(overlay.groups.len() as i32 - 1);
+            history.push(Command::AddGroup(group));
+            return format!("Group {} added, new objects are drawn in it", name);
+        }
+        GroupAction::ClearCurrent => {
+            overlay.set_current_group(None);
+            return String::from("New objects are not grouped");
+        }
+        GroupAction::UngroupSelected => return regroup_selected(ui, overlay, history, None),
+        _ => {}
+    }
+
+    let Some(group) = usize::try_from(ui.get_selected_group())
+        .ok()
+        .and_then(|index| overlay.groups.get(index))
+        .cloned()
+    else {
+        return String::from("No group selected");
+    };
+    let mut edit_group = |after: Group| {
+        overlay.replace_group(after.clone());
+        history.push(Command::EditGroup {
+            before: group.clone(),
+            after,
+        });
+    };
+    match action {
+        GroupAction::Add | GroupAction::ClearCurrent | GroupAction::UngroupSelected => {
+            unreachable!("handled above")
+        }
+        GroupAction::Rename if name.is_empty() => String::from("Type the new name of the group"),
+        GroupAction::Rename => {
+            edit_group(Group {
+                name: name.clone(),
+                ..group.clone()
+            });
+            format!("Group {} renamed to {}", group.name, name)
+        }
+        GroupAction::ToggleVisible => {
+            edit_group(Group {
+                visible: !group.visible,
+                ..group.clone()
+            });
+            let state = if group.visible { "hidden" } else { "shown" };
+            format!("Group {} {}", group.name, state)
+        }
+        GroupAction::ToggleLocked => {
+            edit_group(Group {
+                locked: !group.locked,
+                ..group.clone()
+            });
+            let state = if group.locked { "unlocked" } else { "locked" };
+            format!("Group {} {}", group.name, state)
+        }
+        GroupAction::SetCurrent if !group.visible || group.locked => {
+            format!("Group {} is hidden or locked", group.name)
+        }
+        GroupAction::SetCurrent => {
+            overlay.set_current_group(Some(group.id));
+            format!("New objects are drawn in group {}", group.name)
+        }
+        GroupAction::MoveSelected | GroupAction::ApplyStyle | GroupAction::Delete
+            if group.locked =>
+        {
+            format!("Group {} is locked", group.name)
+        }
+        GroupAction::MoveSelected => regroup_selected(ui, overlay, history, Some(&group)),
+        GroupAction::ApplyStyle => {
+            let color = Color {
+                r: ui.get_stroke_red().round() as u8,
+                g: ui.get_stroke_green().round() as u8,
+                b: ui.get_stroke_blue().round() as u8,
+            };
+            let width = ui.get_stroke_width();
+            let before = overlay.group_drawables(group.id);
+            for d in before.iter() {
+                overlay.set_drawable_style(d.id, color, width, d.style);
+            }
+            let after = overlay.group_drawables(group.id);
+            let count = after.len();
+            if count > 0 {
+                history.push(Command::EditDrawables { before, after });
+            }
+            format!(
+                "Color and width applied to {} objects of group {}",
+                count, group.name
+            )
+        }
+        GroupAction::Delete => {
+            // Drawables built from the ones of the group are deleted along with them
+            let ids = overlay
+                .group_drawables(group.id)
+                .iter()
+                .map(|d| d.id)
+                .collect::<Vec<i32>>();
+            if overlay
+                .dependents(&ids)
+                .iter()
+                .any(|d| overlay.is_locked(d))
+            {
+                return String::from("Objects built from the group are in a locked group");
+            }
+            let drawables = overlay.remove_with_dependents(&ids);
+            overlay.remove_group(group.id);
+            let count = drawables.len();
+            history.push(Command::DeleteGroup {
+                group: group.clone(),
+                drawables,
+            });
+            format!("Group {} deleted along with {} objects", group.name, count)
+        }
+    }
+}
+
+/// Whether clicks of a tool give a position, which is snapped to the drawables, rather
+/// than pick an existing object
+fn snaps_clicks(action: NextAction) -> bool {
+    matches!(
+        action,
+        NextAction::Vertical
+            | NextAction::Horizontal
+            | NextAction::Point
+            | NextAction::Segment
+            | NextAction::Segment2
+            | NextAction::Line
+            | NextAction::Line2
+            | NextAction::HalfLine
+            | NextAction::HalfLine2
+            | NextAction::Parallel
+            | NextAction::TwoPointsMedian
+            | NextAction::TwoPointsMedian2
+            | NextAction::Perpendicular
+            | NextAction::MeasurePointToLine
+            | NextAction::CenterAndEdge
+            | NextAction::CenterAndEdge2
+            | NextAction::CircleThreeEdgePoints
+            | NextAction::CircleThreeEdgePoints2
+            | NextAction::CircleThreeEdgePoints3
+            | NextAction::MeasureTwoPoints
+            | NextAction::MeasureTwoPoints2
+            | NextAction::Tangent
+            | NextAction::FromAngle
+            | NextAction::CircleRadiusLength
+            | NextAction::GeoreferencePoint
+            | NextAction::Calibrate
+            | NextAction::Calibrate2
+            | NextAction::MovePoint2
+            | NextAction::Polyline
+            | NextAction::Polygon
+            | NextAction::Label
+            | NextAction::Label2
+            | NextAction::Arc
+            | NextAction::Arc2
+            | NextAction::Arc3
+            | NextAction::Sector
+            | NextAction::Sector2
+            | NextAction::Sector3
+            | NextAction::Annulus
+            | NextAction::Annulus2
+            | NextAction::Annulus3
+            | NextAction::Annulus4
+            | NextAction::Ellipse
+            | NextAction::Ellipse2
+            | NextAction::Ellipse3
+            | NextAction::EllipseFoci
+            | NextAction::EllipseFoci2
+            | NextAction::EllipseFoci3
+    )
+}
+
+/// Snap the mouse position to the drawables, when the current tool gives a position
+///
+/// Snapping is skipped when disabled in the UI or while the modifier key is held.
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `overlay` - The overlay renderer
+/// * `point` - The mouse position
+fn snap_position(ui: &AppWindow, overlay: &OverlayRenderer, point: Point) -> Option<Snap> {
+    if !ui.get_snapping() || ui.get_snap_disabled() || !snaps_clicks(ui.get_current_action()) {
+        return None;
+    }
+    overlay.snap(point, ui.get_snap_tolerance() as f32 * ui.get_viewport_zoom())
+}
+
+/// Style of the palette, applied to new drawables and by the style tool
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+fn current_style(ui: &AppWindow) -> Style {
+    let fill_color = ui.get_fill_color_enabled().then(|| Color {
+        r: ui.get_fill_red().round() as u8,
+        g: ui.get_fill_green().round() as u8,
+        b: ui.get_fill_blue().round() as u8,
+    });
+    // Combo box indices, clamped in case nothing is selected
+    let index = |index: i32, count: usize| (index.max(0) as usize).min(count - 1);
+    Style {
+        alpha: ui.get_stroke_alpha().round().clamp(0., 255.) as u8,
+        dash: DashPattern::ALL[index(ui.get_stroke_dash(), DashPattern::ALL.len())],
+        cap: LineCap::ALL[index(ui.get_stroke_cap(), LineCap::ALL.len())],
+        join: LineJoin::ALL[index(ui.get_stroke_join(), LineJoin::ALL.len())],
+        fill_color,
+        start_arrow: ui.get_start_arrow(),
+        end_arrow: ui.get_end_arrow(),
+    }
+}
+
+/// Show the handles of the selected drawable, if any
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `drawable` - The selected drawable
+fn update_handles_model(ui: &AppWindow, drawable: Option<&Drawable>) {
+    let handles = drawable
+        .map(|drawable| drawable.handles())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|point| Handle {
+            x: point.x,
+            y: point.y,
+        })
+        .collect::<Vec<Handle>>();
+    ui.set_handles(slint::ModelRc::new(VecModel::from(handles)));
+}
+
+/// Push the layers to the UI, both as images and as layer list items
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `layer_renderer` - The layer renderer
+fn update_layers_model(ui: &AppWindow, layer_renderer: &LayerRenderer) {
+    let items = VecModel::from(layer_renderer.layers.clone());
+    debug!("Layer items count: {}", items.row_count());
+    ui.set_layers(slint::ModelRc::new(items));
+
+    let layers_list = slint::VecModel::from(
+        layer_renderer
+            .layers
+            .iter()
+            .map(|layer| {
+                slint::StandardListViewItem::from(slint::SharedString::from(layer.name.as_str()))
+            })
+            .collect::<Vec<StandardListViewItem>>(),
+    );
+
+    ui.set_layers_list(slint::ModelRc::new(layers_list));
+}
+
+/// Undo or redo the last command and refresh the UI accordingly
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `history` - The command history
+/// * `overlay` - The overlay renderer
+/// * `layer_renderer` - The layer renderer
+/// * `georeference` - The georeference of the background
+/// * `undo` - `true` to undo, `false` to redo
+fn replay_history(
+    ui: &AppWindow,
+    history: &RefCell<History>,
+    overlay: &RefCell<OverlayRenderer>,
+    layer_renderer: &RefCell<LayerRenderer>,
+    georeference: &RefCell<Option<Georeference>>,
+    undo: bool,
+) {
+    let command = {
+        let mut history = history.borrow_mut();
+        let mut overlay = overlay.borrow_mut();
+        let mut layer_renderer = layer_renderer.borrow_mut();
+        if undo {
+            history.undo(&mut overlay, &mut layer_renderer)
+        } else {
+            history.redo(&mut overlay, &mut layer_renderer)
+        }
+    };
+
+    let Some(command) = command else {
+        ui.set_contextual_text(SharedString::from(if undo {
+            "Nothing to undo"
+        } else {
+            "Nothing to redo"
+        }));
+        return;
+    };
+
+    if let Some(m_per_px) = command.background_scale(undo) {
+        ui.set_m_per_px(m_per_px);
+    }
+
+    if let Some(restored) = command.georeference(undo) {
+        georeference.replace(restored.cloned());
+        update_georeference_model(ui, restored);
+    }
+
+    if let Some(project) = command.project(undo) {
+        let background = project.background.as_str();
+        if background != ui.get_background_file().as_str() {
+            if background.is_empty() {
+                ui.set_map(slint::Image::default());
+            } else {
+                match BackgroundRenderer::new(background) {
+                    Ok(mut renderer_bg) => {
+                        if let Some(map) = renderer_bg.render_background() {
+                            ui.set_map(map);
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("Error loading background: {:?}", e);
+                        ui.set_map(slint::Image::default());
+                    }
+                }
+            }
+            ui.set_background_file(SharedString::from(background));
+        }
+    }
+
+    update_overlay_model(ui, &mut overlay.borrow_mut(), None);
+    update_layers_model(ui, &layer_renderer.borrow());
+    ui.set_dirty(history.borrow().is_modified());
+    ui.invoke_load_properties();
+
+    let text = if undo { "Undone" } else { "Redone" };
+    ui.set_contextual_text(SharedString::from(format!(
+        "{}: {}",
+        text,
+        command.description()
+    )));
+}
+
+/// Show the state of the georeference in the UI
+///
+/// The map scale is left as set by the user, measurements use the georeference directly
+/// and layers are displayed against its scale, see [`display_m_per_px`].
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `georeference` - The georeference of the background, if any
+fn update_georeference_model(ui: &AppWindow, georeference: Option<&Georeference>) {
+    let Some(georeference) = georeference else {
+        ui.set_georeference_m_per_px(0.);
+        ui.set_georeference_status(SharedString::from("Background not georeferenced"));
+        return;
+    };
+    let size = ui.get_map().size();
+    ui.set_georeference_m_per_px(display_m_per_px(
+        ui.get_m_per_px(),
+        Some(georeference),
+        (size.width, size.height),
+    ));
+    ui.set_georeference_crs(SharedString::from(georeference.crs.as_str()));
+    ui.set_georeference_status(SharedString::from(format!(
+        "{:?} transform in {} from {} control points",
+        georeference.kind,
+        georeference.crs,
+        georeference.control_points.len()
+    )));
+}
+
+/// Push the ground control points to the georeference panel
+///
+/// # Arguments
+///
+/// * `ui` - The application window
+/// * `points` - The control points
+/// * `transform` - The transform the residuals are computed with, if any
+fn update_control_points_model(
+    ui: &AppWindow,
+    points: &[GroundControlPoint],
+    transform: Option<&AffineTransform>,
+) {
+    let rows = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let residual = transform
+                .map(|transform| {
+                    let world = transform.apply(point.pixel);
+                    let residual = ((world.x - point.world.x).powi(2)
+                        + (world.y - point.world.y).powi(2))
+                    .sqrt();
+                    format!("{:.3}", residual)
+                })
+                .unwrap_or_else(|| String::from("-"));
+            let cells = [
+                format!("{}", i + 1),
+                format!("{:.1}", point.pixel.x),
+                format!("{:.1}", point.pixel.y),
+                format!("{}", point.world.x),
+                format!("{}", point.world.y),
+                residual,
+            ]
+            .iter()
+            .map(|cell| StandardListViewItem::from(SharedString::from(cell.as_str())))
+            .collect::<Vec<StandardListViewItem>>();
+            slint::ModelRc::new(VecModel::from(cells))
+        })
+        .collect::<Vec<slint::ModelRc<StandardListViewItem>>>();
+    ui.set_control_points(slint::ModelRc::new(VecModel::from(rows)));
+}
+
+/// Describe the position of the cursor, in pixels and, for a georeferenced background,
+/// in the georeference CRS, in Lambert 93 and in WGS84
+///
+/// # Arguments
+///
+/// * `georeference` - The georeference of the background, if any
+/// * `pixel` - The position of the cursor on the background
+fn cursor_position(georeference: Option<&Georeference>, pixel: Point) -> String {
+    let mut positions = vec![format!("{:.0}, {:.0} px", pixel.x, pixel.y)];
+    if let Some(georeference) = georeference {
+        let world = georeference.to_world(pixel);
+        if georeference.crs != projection::LAMBERT_93 && georeference.crs != geo::WGS84 {
+            positions.push(projection::format_coordinate(&georeference.crs, world));
+        }
+        for crs in [projection::LAMBERT_93, geo::WGS84] {
+            if let Some(coordinate) = projection::convert(&georeference.crs, crs, world) {
+                positions.push(projection::format_coordinate(crs, coordinate));
+            }
+        }
+    }
+    positions.join("  |  ")
+}
+
+/// Meters per pixel of the background around a point
+///
+/// The local scale of the georeference is used when there is one, the background scale
+/// otherwise. Layers are displayed stretched to this scale at the center of the
+/// background, see [`display_m_per_px`], so points over a layer are measured like any
+/// other point of the background.
+///
+/// # Arguments
+///
+/// * `background_m_per_px` - The scale of the background
+/// * `georeference` - The georeference of the background, if any
+/// * `point` - The position on the background, in pixels
+fn scale_at(background_m_per_px: f32, georeference: Option<&Georeference>, point: Point) -> f32 {
+    match georeference {
+        Some(georeference) => georeference.m_per_px(point) as f32,
+        None => background_m_per_px,
+    }
+}
+
+/// Meters per pixel of the background the layers are stretched against
+///
+/// This is the scale measurements use, the scale of the georeference at the center of the
+/// background when there is one, so that a distance measured over a layer matches the
+/// scale of the layer.
+///
+/// # Arguments
+///
+/// * `background_m_per_px` - The scale of the background
+/// * `georeference` - The georeference of the background, if any
+/// * `size` - The width and height of the background, in pixels
+fn display_m_per_px(
+    background_m_per_px: f32,
+    georeference: Option<&Georeference>,
+    (width, height): (u32, u32),
+) -> f32 {
+    let center = Point {
+        x: width as f32 / 2.,
+        y: height as f32 / 2.,
+    };
+    scale_at(background_m_per_px, georeference, center)
+}
+
+/// Number of background pixels a pixel of a layer is displayed over
+///
+/// # Arguments
+///
+/// * `layer_m_per_px` - The scale of the layer
+/// * `display_m_per_px` - The scale of the background, see [`display_m_per_px`]
+fn layer_stretch(layer_m_per_px: f32, display_m_per_px: f32) -> f32 {
+    layer_m_per_px / display_m_per_px
+}
+
+/// Distance in meters between two points of the background
+///
+/// Distances on a georeferenced background use its real-world coordinates, other
+/// distances the background scale, see [`scale_at`].
+fn measured_distance(
+    background_m_per_px: f32,
+    georeference: Option<&Georeference>,
+    point1: Point,
+    point2: Point,
+) -> f32 {
+    match georeference {
+        Some(georeference) => georeference.distance(point1, point2) as f32,
+        None => math::distance(point1, point2) * background_m_per_px,
+    }
+}
+
+/// Units the properties of a drawable are edited in
+#[derive(Clone, Copy)]
+enum PropertyUnits<'a> {
+    /// Pixels of the background
+    Pixels,
+    /// Meters from the top left corner of the background, at the given scale
+    Meters(f32),
+    /// Coordinates of the georeference, lengths in meters at the given scale
+    World(&'a Georeference, f32),
+}
+
+impl PropertyUnits<'_> {
+    /// Units the properties of a drawable are displayed in
+    ///
+    /// # Arguments
+    ///
+    /// * `map_units` - Whether map units are requested rather than pixels
+    /// * `background_m_per_px` - The scale of the background
+    /// * `georeference` - The georeference of the background, if any
+    /// * `drawable` - The edited drawable, lengths are measured at its first point
+    fn new<'a>(
+        map_units: bool,
+        background_m_per_px: f32,
+        georeference: Option<&'a Georeference>,
+        drawable: &Drawable,
+    ) -> PropertyUnits<'a> {
+        if !map_units {
+            return PropertyUnits::Pixels;
+        }
+        let m_per_px = scale_at(background_m_per_px, georeference, drawable.point1);
+        match georeference {
+            Some(georeference) => PropertyUnits::World(georeference, m_per_px),
+            None => PropertyUnits::Meters(m_per_px),
+        }
+    }
+
+    /// Name of the units, displayed in the properties panel
+    fn name(&self) -> String {
+        match self {
+            PropertyUnits::Pixels => String::from("px"),
+            PropertyUnits::Meters(_) => String::from("m"),
+            PropertyUnits::World(georeference, _) => {
+                format!("{}, m", projection::crs_name(&georeference.crs))
+            }
+        }
+    }
+
+    fn format_position(&self, point: Point) -> (String, String) {
+        match self {
+            PropertyUnits::Pixels => (format!("{:.1}", point.x), format!("{:.1}", point.y)),
+            PropertyUnits::Meters(m_per_px) => (
+                format!("{:.2}", point.x * m_per_px),
+                format!("{:.2}", point.y * m_per_px),
+            ),
+            PropertyUnits::World(georeference, _) => {
+                let world = georeference.to_world(point);
+                let decimals = if georeference.crs == geo::WGS84 { 7 } else { 2 };
+                (
+                    format!("{:.*}", decimals, world.x),
+                    format!("{:.*}", decimals, world.y),
+                )
+            }
+        }
+    }
+
+    fn parse_position(&self, x: &str, y: &str) -> Option<Point> {
+        let (x, y) = (parse_coordinate(x)?, parse_coordinate(y)?);
+        match self {
+            PropertyUnits::Pixels => Some(Point {
+                x: x as f32,
+                y: y as f32,
+            }),
+            PropertyUnits::Meters(m_per_px) => Some(Point {
+                x: x as f32 / m_per_px,
+                y: y as f32 / m_per_px,
+            }),
+            PropertyUnits::World(georeference, _) => georeference.to_pixel(Coordinate { x, y }),
+        }
+    }
+
+    fn format_length(&self, pixels: f32) -> String {
+        match self {
+            PropertyUnits::Pixels => format!("{:.1}", pixels),
+            PropertyUnits::Meters(m_per_px) | PropertyUnits::World(_, m_per_px) => {
+                format!("{:.2}", pixels * m_per_px)
+            }
+        }
+    }
+
+    fn parse_length(&self, text: &str) -> Option<f32> {
+        let length = parse_coordinate(text)? as f32;
+        let pixels = match self {
+            PropertyUnits::Pixels => length,
+            PropertyUnits::Meters(m_per_px) | PropertyUnits::World(_, m_per_px) => {
+                length / m_per_px
+            }
+        };
+        (pixels.is_finite() && pixels > 0.).then_some(pixels)
+    }
+}
+
+/// Fill the properties panel with the values of a drawable
+///
+/// # Arguments
+///
+/// * `drawable` - The drawable
+/// * `units` - The units the values are displayed in
+/// * `locked` - Whether the drawable is in a locked group, its values being read-only
+fn drawable_properties(
+    drawable: &Drawable,
+    units: PropertyUnits,
+    locked: bool,
+) -> DrawableProperties {
+    let (x1, y1) = units.format_position(drawable.point1);
+    let (x2, y2) = units.format_position(drawable.point2);
+    DrawableProperties {
+        id: drawable.id,
+        kind: SharedString::from(format!("{:?}", drawable.object_type)),
+        construction: drawable
+            .construction
+            .as_ref()
+            .map(|construction| SharedString::from(construction.describe()))
+            .unwrap_or_default(),
+        x1: SharedString::from(x1),
+        y1: SharedString::from(y1),
+        x2: SharedString::from(x2),
+        y2: SharedString::from(y2),
+        radius: SharedString::from(units.format_length(drawable.radius())),
+        inner_radius: SharedString::from(units.format_length(drawable.inner_radius)),
+        start_angle: SharedString::from(format!("{:.1}", drawable.start_angle)),
+        end_angle: SharedString::from(format!("{:.1}", drawable.end_angle)),
+        semi_axis: SharedString::from(units.format_length(drawable.semi_axis)),
+        width: SharedString::from(units.format_length(drawable.width)),
+        red: drawable.color.r as f32,
+        green: drawable.color.g as f32,
+        blue: drawable.color.b as f32,
+        fill: drawable.fill,
+        text: SharedString::from(drawable.text.as_str()),
+        font_size: drawable.font_size,
+        leader: drawable.has_leader(),
+        name: SharedString::from(drawable.name.as_str()),
+        note: SharedString::from(drawable.note.as_str()),
+        tags: SharedString::from(drawable.tags.join(", ")),
+        locked,
+    }
+}
+
+/// Parse a comma separated list of tags, dropping empty and duplicate ones
+///
+/// # Arguments
+///
+/// * `text` - The tags as typed in the properties panel
+fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+        if !tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+/// Apply the values of the properties panel to a drawable
+///
+/// # Returns
+///
+/// The edited drawable, or `None` if a value is invalid
+fn edited_drawable(
+    drawable: &Drawable,
+    properties: &DrawableProperties,
+    units: PropertyUnits,
+) -> Option<Drawable> {
+    let (point1, point2) = match drawable.object_type {
+        DrawableType::Point => {
+            let point = units.parse_position(&properties.x1, &properties.y1)?;
+            (point, point)
+        }
+        DrawableType::Segment
+        | DrawableType::HalfLine
+        | DrawableType::Line
+        | DrawableType::Ellipse => (
+            units.parse_position(&properties.x1, &properties.y1)?,
+            units.parse_position(&properties.x2, &properties.y2)?,
+        ),
+        DrawableType::Circle
+        | DrawableType::Arc
+        | DrawableType::Sector
+        | DrawableType::Annulus => {
+            let center = units.parse_position(&properties.x1, &properties.y1)?;
+            let radius = units.parse_length(&properties.radius)?;
+            (
+                center,
+                Point {
+                    x: center.x + radius,
+                    y: center.y,
+                },
+            )
+        }
+        // Vertices of polylines and polygons are not edited
+        DrawableType::Polyline | DrawableType::Polygon => (drawable.point1, drawable.point2),
+        DrawableType::Label => {
+            let position = units.parse_position(&properties.x1, &properties.y1)?;
+            let target = if properties.leader {
+                units.parse_position(&properties.x2, &properties.y2)?
+            } else {
+                position
+            };
+            // A leader line just switched on starts below the text, to be moved from there
+            if properties.leader && target == position {
+                let target = Point {
+                    x: position.x,
+                    y: position.y + 2. * properties.font_size,
+                };
+                (position, target)
+            } else {
+                (position, target)
+            }
+        }
+    };
+    if drawable.object_type == DrawableType::Label
+        && (properties.text.trim().is_empty() || properties.font_size < 1.)
+    {
+        return None;
+    }
+    let inner_radius = if drawable.object_type == DrawableType::Annulus {
+        let inner_radius = units.parse_length(&properties.inner_radius)?;
+        if inner_radius >= math::distance(point1, point2) {
+            return None;
+        }
+        inner_radius
+    } else {
+        drawable.inner_radius
+    };
+    let semi_axis = if drawable.object_type == DrawableType::Ellipse {
+        units.parse_length(&properties.semi_axis)?
+    } else {
+        drawable.semi_axis
+    };
+    let start_angle = parse_coordinate(&properties.start_angle)? as f32;
+    let end_angle = parse_coordinate(&properties.end_angle)? as f32;
+    Some(Drawable {
+        point1,
+        point2,
+        color: Color {
+            r: properties.red.round() as u8,
+            g: properties.green.round() as u8,
+            b: properties.blue.round() as u8,
+        },
+        width: units.parse_length(&properties.width)?,
+        fill: properties.fill,
+        text: properties.text.to_string(),
+        font_size: properties.font_size,
+        name: properties.name.trim().to_string(),
+        note: properties.note.to_string(),
+        tags: parse_tags(&properties.tags),
+        inner_radius,
+        start_angle: start_angle.rem_euclid(360.),
+        end_angle: end_angle.rem_euclid(360.),
+        semi_axis,
+        ..drawable.clone()
+    })
+}
+
+/// Length, and area for a polygon, of a polyline or polygon in map units
+///
+/// Lengths are measured edge by edge as in [`measured_distance`], areas with the scale
+/// at the centroid of the vertices. Arcs, sectors and annuli are measured with the scale
+/// at their center, giving the length of their outer arc and the area of sectors and
+/// annuli, as are ellipses.
+///
+/// # Returns
+///
+/// The measurement as displayed to the user, or `None` for other drawables
+fn shape_measurement(
+    background_m_per_px: f32,
+    georeference: Option<&Georeference>,
+    drawable: &Drawable,
+) -> Option<String> {
+    if let DrawableType::Arc | DrawableType::Sector | DrawableType::Annulus = drawable.object_type
+    {
+        let m_per_px = scale_at(background_m_per_px, georeference, drawable.point1);
+        let sweep = drawable.sweep().to_radians();
+        let radius = drawable.radius();
+        let inner_radius = if drawable.object_type == DrawableType::Annulus {
+            drawable.inner_radius
+        } else {
+            0.
+        };
+        let arc_length = radius * sweep;
+        let length = format!(
+            "arc length {:.2} km or {:.1} px",
+            arc_length * m_per_px / 1000.,
+            arc_length
+        );
+        if drawable.object_type == DrawableType::Arc {
+            return Some(length);
+        }
+        let area = sweep / 2. * (radius * radius - inner_radius * inner_radius);
+        return Some(format!(
+            "{}, area {:.3} km²",
+            length,
+            area * m_per_px * m_per_px / 1_000_000.
+        ));
+    }
+    if drawable.object_type == DrawableType::Ellipse {
+        let m_per_px = scale_at(background_m_per_px, georeference, drawable.point1);
+        let (a, b) = (drawable.radius(), drawable.semi_axis);
+        let perimeter = math::ellipse_perimeter(a, b);
+        let area = std::f32::consts::PI * a * b;
+        return Some(format!(
+            "perimeter {:.2} km or {:.1} px, area {:.3} km²",
+            perimeter * m_per_px / 1000.,
+            perimeter,
+            area * m_per_px * m_per_px / 1_000_000.
+        ));
+    }
+    let closed = match drawable.object_type {
+        DrawableType::Polyline => false,
+        DrawableType::Polygon => true,
+        _ => return None,
+    };
+    let points = &drawable.points;
+    let closing_edge = match (closed, points.last(), points.first()) {
+        (true, Some(last), Some(first)) => Some((*last, *first)),
+        _ => None,
+    };
+    let length: f32 = points
+        .windows(2)
+        .map(|edge| (edge[0], edge[1]))
+        .chain(closing_edge)
+        .map(|(p1, p2)| measured_distance(background_m_per_px, georeference, p1, p2))
+        .sum();
+    let pixels = math::path_length(points, closed);
+    if !closed {
+        return Some(format!("length {:.2} km or {:.1} px", length / 1000., pixels));
+    }
+
+    let count = points.len().max(1) as f32;
+    let centroid = Point {
+        x: points.iter().map(|p| p.x).sum::<f32>() / count,
+        y: points.iter().map(|p| p.y).sum::<f32>() / count,
+    };
+    let m_per_px = scale_at(background_m_per_px, georeference, centroid);
+    let area = math::polygon_area(points) * m_per_px * m_per_px;
+    Some(format!(
+        "perimeter {:.2} km or {:.1} px, area {:.3} km²",
+        length / 1000.,
+        pixels,
+        area / 1_000_000.
+    ))
+}
+
+/// Length in meters of a distance unit offered for scale calibration
+fn unit_length(unit: &str) -> Option<f64> {
+    match unit {
+        "m" => Some(1.),
+        "km" => Some(1_000.),
+        "ft" => Some(0.3048),
+        "yd" => Some(0.9144),
+        "mi" => Some(1_609.344),
+        "NM" => Some(1_852.),
+        _ => None,
+    }
+}
+
+/// Compute the scale of the background from a known distance
+///
+/// # Arguments
+///
+/// * `pixels` - The distance measured on the background, in pixels
+/// * `distance` - The real distance, as typed by the user
+/// * `unit` - The unit of the real distance
+///
+/// # Returns
+///
+/// The number of meters per pixel, or `None` if the distance is not a positive number
+fn calibrated_scale(pixels: f32, distance: &str, unit: &str) -> Option<f32> {
+    let meters = parse_coordinate(distance)? * unit_length(unit)?;
+    let m_per_px = (meters / pixels as f64) as f32;
+    (m_per_px.is_finite() && m_per_px > 0.).then_some(m_per_px)
+}
+
+/// Parse a coordinate typed by the user, accepting both decimal separators
+fn parse_coordinate(text: &str) -> Option<f64> {
+    text.trim().replace(',', ".").parse().ok()
+}
+
+/// Add imported features to the overlay, using the current color and width
+///
+/// Features entirely outside the background are skipped, as their position is most
+/// likely the result of a georeference or coordinate system mismatch. Named waypoints and
+/// placemarks are added as labels above their position, with a leader line to it, so that
+/// their name shows on the map.
+///
+/// # Arguments
+///
+/// * `overlay` - The overlay renderer
+/// * `georeference` - The georeference of the background
+/// * `features` - The features, in the reference system of the georeference
+/// * `font_size` - The font size of the labels of named points, in pixels
+///
+/// # Returns
+///
+/// The added drawables, and the number of skipped features
+fn import_features(
+    overlay: &mut OverlayRenderer,
+    georeference: &Georeference,
+    features: Vec<Feature>,
+    font_size: f32,
+) -> (Vec<Drawable>, usize) {
+    let (width, height) = overlay.image_size();
+    let inside = |point: &Point| {
+        point.x >= 0. && point.y >= 0. && point.x <= width as f32 && point.y <= height as f32
+    };
+    let count = overlay.drawables.len();
+    let mut skipped = 0;
+
+    for feature in features {
+        match feature.geometry {
+            Geometry::Point(coordinate) => match georeference.to_pixel(coordinate) {
+                Some(point) if inside(&point) && !feature.name.trim().is_empty() => {
+                    // Labels of points near the top of the map go below them
+                    let offset = 2. * font_size;
+                    let position = Point {
+                        x: point.x,
+                        y: if point.y >= offset {
+                            point.y - offset
+                        } else {
+                            point.y + offset
+                        },
+                    };
+                    overlay.add_label(position, Some(point), &feature.name, font_size);
+                }
+                Some(point) if inside(&point) => overlay.add_point(point),
+                _ => skipped += 1,
+            },
+            Geometry::Path(path) => {
+                let points = path
+                    .into_iter()
+                    .filter_map(|coordinate| georeference.to_pixel(coordinate))
+                    .collect::<Vec<Point>>();
+                if points.len() >= 2 && points.iter().any(inside) {
+                    overlay.add_polyline(points, &feature.name);
+                } else {
+                    skipped += 1;
+                }
+            }
+        }
+    }
+    (overlay.drawables[count..].to_vec(), skipped)
+}
+
 fn main() -> Result<(), slint::PlatformError> {
     env_logger::builder().format_timestamp_millis().init();
 
@@ -29,9 +1219,18 @@ fn main() -> Result<(), slint::PlatformError> {
 
     let layer_renderer2 = layer_renderer.clone();
 
+    let history = Rc::new(RefCell::new(History::new(DEFAULT_HISTORY_CAPACITY)));
+
+    let autosave = Rc::new(RefCell::new(Autosave::new()));
+
+    let georeference: Rc<RefCell<Option<Georeference>>> = Rc::new(RefCell::new(None));
+
     let mut standing_point = Point { x: 0.0, y: 0.0 };
     let mut standing_point_2 = Point { x: 0.0, y: 0.0 };
+    let mut standing_point_3 = Point { x: 0.0, y: 0.0 };
     let mut standing_drawable = None;
+    let mut standing_anchor = Anchor::Fixed(Point { x: 0.0, y: 0.0 });
+    let mut standing_anchor_2 = Anchor::Fixed(Point { x: 0.0, y: 0.0 });
     let mut next_action = NextAction::None;
 
     let ui: AppWindow = AppWindow::new()?;
@@ -44,118 +1243,107 @@ fn main() -> Result<(), slint::PlatformError> {
     let ui_handle_2 = ui_handle.clone();
     let layer_renderer3 = layer_renderer.clone();
     let overlay = renderer.clone();
-    ui.on_load(move || {
+    let history2 = history.clone();
+    let georeference2 = georeference.clone();
+    ui.on_load(move |file| {
         let ui = ui_handle_2.clone();
         let ui = ui.unwrap();
-        debug!("Stub to load project into file");
-        let project = io::Project::load_project("file.mrs").unwrap();
+        debug!("Loading project from {}", file);
+        let project = match io::Project::load_project(file.as_str()) {
+            Ok(project) => project,
+            Err(e) => {
+                log::warn!("Error loading project: {:?}", e);
+                ui.set_contextual_text(SharedString::from(format!("Cannot load {}: {}", file, e)));
+                return;
+            }
+        };
         if !std::path::Path::new(project.background.as_str()).exists() {
             ui.set_contextual_text(SharedString::from("Background file not found"));
             return;
         }
-        let mut renderer_bg = BackgroundRenderer::new(project.background.as_str());
+        let before = ProjectSnapshot::capture(
+            ui.get_background_file().as_str(),
+            georeference2.borrow().as_ref(),
+            &overlay.borrow(),
+            &layer_renderer3.borrow(),
+        );
+
+        let mut renderer_bg = match BackgroundRenderer::new(project.background.as_str()) {
+            Ok(renderer_bg) => renderer_bg,
+            Err(e) => {
+                log::warn!("Error loading background: {:?}", e);
+                ui.set_contextual_text(SharedString::from(format!(
+                    "Cannot load background {}: {}",
+                    project.background, e
+                )));
+                return;
+            }
+        };
 
         let layer = layer_renderer3.clone();
         layer.borrow_mut().reset();
 
         for layer in project.layers {
-            layer_renderer3.borrow_mut().add_layer(
+            if let Err(e) = layer_renderer3.borrow_mut().add_layer(
                 layer.file.as_str(),
                 layer.x as i32,
                 layer.y as i32,
                 layer.transparency,
                 layer.m_per_px,
-            );
+            ) {
+                log::warn!("Error loading layer {}: {:?}", layer.file, e);
+            }
         }
 
         let overlay = overlay.clone();
         overlay
             .borrow_mut()
-            .reset(renderer_bg.image_height, renderer_bg.image_width);
+            .reset(renderer_bg.image_width, renderer_bg.image_height);
 
+        overlay.borrow_mut().replace_groups(&project.groups);
         overlay.borrow_mut().restore_drawables(project.drawables);
 
-        ui.set_map(renderer_bg.render_background().unwrap());
-
-        let items = VecModel::from(
-            layer_renderer3
-                .borrow()
-                .layers
-                .iter()
-                .map(|layer| LayerDrawable {
-                    id: layer.id,
-                    data: layer.data.clone(),
-                    x: layer.x,
-                    y: layer.y,
-                    transparency: layer.transparency,
-                    m_per_px: layer.m_per_px,
-                    file: layer.file.clone(),
-                    name: layer.name.clone(),
-                })
-                .collect::<Vec<LayerDrawable>>(),
-        );
-        debug!("Layer items count: {}", items.row_count());
-        ui.set_layers(slint::ModelRc::new(items));
-
-        let layers_list = slint::VecModel::from(
-            layer_renderer3
-                .borrow()
-                .layers
-                .iter()
-                .map(|layer| {
-                    slint::StandardListViewItem::from(slint::SharedString::from(
-                        layer.name.as_str(),
-                    ))
-                })
-                .collect::<Vec<StandardListViewItem>>(),
+        if let Some(map) = renderer_bg.render_background() {
+            ui.set_map(map);
+        }
+        ui.set_background_file(SharedString::from(project.background.as_str()));
+        if let Some(m_per_px) = project.m_per_px {
+            ui.set_m_per_px(m_per_px);
+        }
+        georeference2.replace(project.georeference);
+        update_georeference_model(&ui, georeference2.borrow().as_ref());
+
+        let after = ProjectSnapshot::capture(
+            project.background.as_str(),
+            georeference2.borrow().as_ref(),
+            &overlay.borrow(),
+            &layer_renderer3.borrow(),
         );
+        history2.borrow_mut().push(Command::RestoreProject {
+            before: Box::new(before),
+            after: Box::new(after),
+        });
+        history2.borrow_mut().mark_saved();
+        ui.set_project_file(file.clone());
+        ui.set_dirty(false);
 
-        ui.set_layers_list(slint::ModelRc::new(layers_list));
-
-        let mut my_vec = vec![];
-        let d = overlay.borrow_mut().drawables.clone();
-        for dd in d {
-            let s = format!("{} - {:?}", dd.id, dd.object_type);
-            let s = slint::StandardListViewItem::from(slint::SharedString::from(s.as_str()));
-            my_vec.push(s);
-            overlay
-                .borrow_mut()
-                .set_listview_id(dd.id, my_vec.len() as i32 - 1);
-            if selected_listview_item.is_some() && selected_listview_item.unwrap() == dd.id {
-                ui.set_current_listview_drawable_item(my_vec.len() as i32 - 1);
-            }
-        }
-        let model = slint::ModelRc::new(VecModel::from(my_vec));
-        ui.set_item_list(model);
+        update_layers_model(&ui, &layer_renderer3.borrow());
+        update_overlay_model(&ui, &mut overlay.borrow_mut(), selected_listview_item);
 
         ui.set_current_action(next_action);
 
-        let items = VecModel::from(
-            overlay
-                .borrow()
-                .drawable_images
-                .iter()
-                .map(|d| OverlayDrawable {
-                    id: d.id,
-                    data: d.data.clone(),
-                    x: d.x,
-                    y: d.y,
-                })
-                .collect::<Vec<OverlayDrawable>>(),
-        );
-        debug!("Overlay items count: {}", items.row_count());
-        ui.set_overlay_drawables(slint::ModelRc::new(items));
-
-        ui.set_contextual_text(SharedString::from("File loaded"));
+        ui.set_contextual_text(SharedString::from(format!("Project loaded from {}", file)));
     });
 
     let ui_handle_2 = ui_handle.clone();
     let layer_renderer3 = layer_renderer.clone();
     let overlay = renderer.clone();
-    ui.on_save(move || {
+    let history2 = history.clone();
+    let georeference2 = georeference.clone();
+    ui.on_save(move |file| {
         let ui = ui_handle_2.clone();
         let ui = ui.unwrap();
-        debug!("Stub to save project from file");
+        debug!("Saving project to {}", file);
 
         let background_file = ui.get_background_file().to_string();
 
@@ -166,256 +1354,1049 @@ fn main() -> Result<(), slint::PlatformError> {
             background_file.as_str(),
             layer.borrow().layers.as_ref(),
             overlay.borrow().drawables.as_ref(),
+            overlay.borrow().groups.as_ref(),
+            georeference2.borrow().as_ref(),
+            ui.get_m_per_px(),
+        );
+        let result = if file.ends_with(io::BUNDLE_EXTENSION) {
+            project.save_bundle(file.as_str())
+        } else {
+            project.save_project(file.as_str())
+        };
+        match result {
+            Ok(()) => {
+                history2.borrow_mut().mark_saved();
+                ui.set_project_file(file.clone());
+                ui.set_dirty(false);
+                ui.set_contextual_text(SharedString::from(format!("Project saved to {}", file)));
+                true
+            }
+            Err(e) => {
+                log::warn!("Error saving project: {:?}", e);
+                ui.set_contextual_text(SharedString::from(format!("Cannot save {}: {}", file, e)));
+                false
+            }
+        }
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    ui.on_save_current(move || {
+        let ui = ui_handle_2.unwrap();
+        let project_file = ui.get_project_file();
+        if project_file.is_empty() {
+            ui.invoke_show_save_dialog(false, false);
+        } else {
+            ui.invoke_save(project_file);
+        }
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    ui.on_show_open_dialog(move || {
+        let file_selector =
+            match fileselector::show_file_selector("Open project", FileFilter::Projects, false) {
+                Ok(file_selector) => file_selector,
+                Err(e) => {
+                    log::warn!("Cannot open file selector: {:?}", e);
+                    return;
+                }
+            };
+        let file_selector_weak = file_selector.as_weak();
+
+        file_selector.on_send_ok({
+            let ui = ui_handle_2.clone();
+            move || {
+                let (Some(ui), Some(ui_fs)) = (ui.upgrade(), file_selector_weak.upgrade()) else {
+                    return;
+                };
+                let Some(path) = fileselector::selected_path(&ui_fs) else {
+                    return;
+                };
+                let _ = ui_fs.hide();
+                ui.invoke_load(SharedString::from(path.to_string_lossy().as_ref()));
+            }
+        });
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    ui.on_show_save_dialog(move |bundle, close_after_save| {
+        let Some(ui) = ui_handle_2.upgrade() else {
+            return;
+        };
+        let (title, extension) = if bundle {
+            ("Export project bundle", io::BUNDLE_EXTENSION)
+        } else {
+            ("Save project as", fileselector::PROJECT_EXTENSION)
+        };
+
+        // Start from the current project file, if any
+        let project_file = PathBuf::from(ui.get_project_file().as_str());
+        let ui_handle = ui_handle_2.clone();
+        let result = fileselector::show_save_selector(
+            title,
+            FileFilter::Projects,
+            extension,
+            project_file.file_stem(),
+            move |path| {
+                let Some(ui) = ui_handle.upgrade() else {
+                    return false;
+                };
+                let saved = ui.invoke_save(SharedString::from(path.to_string_lossy().as_ref()));
+                if saved && close_after_save {
+                    ui.invoke_quit();
+                }
+                saved
+            },
+        );
+        if let Err(e) = result {
+            log::warn!("Cannot open file selector: {:?}", e);
+        }
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    let layer_renderer3 = layer_renderer.clone();
+    let overlay = renderer.clone();
+    let georeference2 = georeference.clone();
+    ui.on_export_geojson(move |file| {
+        let ui = ui_handle_2.unwrap();
+        debug!("Exporting annotations to {}", file);
+        let project = io::Project::new(
+            ui.get_background_file().as_str(),
+            layer_renderer3.borrow().layers.as_ref(),
+            overlay.borrow().drawables.as_ref(),
+            overlay.borrow().groups.as_ref(),
+            georeference2.borrow().as_ref(),
+            ui.get_m_per_px(),
         );
-        project.save_project("file.mrs").unwrap();
+        match io::geojson::export_geojson(&project, file.as_str()) {
+            Ok(()) => {
+                ui.set_contextual_text(SharedString::from(format!(
+                    "Annotations exported to {}",
+                    file
+                )));
+                true
+            }
+            Err(e) => {
+                log::warn!("Error exporting annotations: {:?}", e);
+                ui.set_contextual_text(SharedString::from(format!(
+                    "Cannot export {}: {}",
+                    file, e
+                )));
+                false
+            }
+        }
     });
 
-    ui.on_close(|| {
+    let ui_handle_2 = ui_handle.clone();
+    let georeference2 = georeference.clone();
+    ui.on_show_export_dialog(move || {
+        let Some(ui) = ui_handle_2.upgrade() else {
+            return;
+        };
+        if georeference2.borrow().is_none() {
+            ui.set_contextual_text(SharedString::from(
+                "Georeference the background before exporting annotations",
+            ));
+            return;
+        }
+        let project_file = PathBuf::from(ui.get_project_file().as_str());
+        let ui_handle = ui_handle_2.clone();
+        let result = fileselector::show_save_selector(
+            "Export annotations as GeoJSON",
+            FileFilter::GeoData,
+            io::geojson::GEOJSON_EXTENSION,
+            project_file.file_stem(),
+            move |path| {
+                ui_handle.upgrade().is_some_and(|ui| {
+                    ui.invoke_export_geojson(SharedString::from(path.to_string_lossy().as_ref()))
+                })
+            },
+        );
+        if let Err(e) = result {
+            log::warn!("Cannot open file selector: {:?}", e);
+        }
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    let overlay = renderer.clone();
+    let history2 = history.clone();
+    let georeference2 = georeference.clone();
+    ui.on_import_geodata(move |file| {
+        let ui = ui_handle_2.unwrap();
+        debug!("Importing geographic data from {}", file);
+        let result = io::import::read_geodata(file.as_str()).and_then(|data| {
+            let georeference = georeference2.borrow();
+            let georeference = georeference.as_ref().ok_or(ProjectError::NotGeoreferenced)?;
+            let data = data.reproject(&georeference.crs)?;
+            Ok(import_features(
+                &mut overlay.borrow_mut(),
+                georeference,
+                data.features,
+                ui.get_label_font_size() as f32,
+            ))
+        });
+        match result {
+            Ok((added, skipped)) => {
+                if !added.is_empty() {
+                    history2.borrow_mut().push(Command::AddDrawables(added.clone()));
+                }
+                ui.set_dirty(history2.borrow().is_modified());
+                update_overlay_model(&ui, &mut overlay.borrow_mut(), None);
+                ui.set_contextual_text(SharedString::from(format!(
+                    "{} objects imported from {}, {} outside the map skipped",
+                    added.len(),
+                    file,
+                    skipped
+                )));
+            }
+            Err(e) => {
+                log::warn!("Error importing data: {:?}", e);
+                ui.set_contextual_text(SharedString::from(format!(
+                    "Cannot import {}: {}",
+                    file, e
+                )));
+            }
+        }
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    ui.on_show_import_dialog(move || {
+        let file_selector =
+            match fileselector::show_file_selector("Import data", FileFilter::GeoData, false) {
+                Ok(file_selector) => file_selector,
+                Err(e) => {
+                    log::warn!("Cannot open file selector: {:?}", e);
+                    return;
+                }
+            };
+        let file_selector_weak = file_selector.as_weak();
+
+        file_selector.on_send_ok({
+            let ui = ui_handle_2.clone();
+            move || {
+                let (Some(ui), Some(ui_fs)) = (ui.upgrade(), file_selector_weak.upgrade()) else {
+                    return;
+                };
+                let Some(path) = fileselector::selected_path(&ui_fs) else {
+                    return;
+                };
+                let _ = ui_fs.hide();
+                ui.invoke_import_geodata(SharedString::from(path.to_string_lossy().as_ref()));
+            }
+        });
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    ui.on_close(move || {
+        let ui = ui_handle_2.unwrap();
+        if ui.get_dirty() {
+            ui.invoke_show_save_changes();
+        } else {
+            ui.invoke_quit();
+        }
+    });
+
+    let autosave2 = autosave.clone();
+    ui.on_quit(move || {
         debug!("Terminate application");
+        // The session ends cleanly, nothing has to be recovered
+        autosave2.borrow_mut().clear();
         exit(0);
     });
 
-    let overlay = renderer.clone();
-    let layer_renderer3 = layer_renderer.clone();
-    ui.on_show_fileselector_bg(move || {
-        log::debug!("Entering on_show_fileselector");
-        let file_selector_bg = FileSelector::new().unwrap();
-        file_selector_bg.show().unwrap();
-        let file_selector_weak = file_selector_bg.as_weak();
+    let ui_handle_2 = ui_handle.clone();
+    ui.on_save_and_close(move || {
+        let ui = ui_handle_2.unwrap();
+        let project_file = ui.get_project_file();
+        if project_file.is_empty() {
+            ui.invoke_show_save_dialog(false, true);
+        } else if ui.invoke_save(project_file) {
+            ui.invoke_quit();
+        }
+    });
 
-        if file_selector_bg.get_path().is_empty() {
-            file_selector_bg.set_path(SharedString::from(current_dir().unwrap().to_str().unwrap()));
+    let ui_handle_2 = ui_handle.clone();
+    ui.window().on_close_requested(move || {
+        let ui = ui_handle_2.unwrap();
+        if ui.get_dirty() {
+            ui.invoke_show_save_changes();
+            slint::CloseRequestResponse::KeepWindowShown
+        } else {
+            slint::CloseRequestResponse::HideWindow
         }
+    });
 
-        let path = file_selector_bg.get_path().to_string();
+    let ui_handle_2 = ui_handle.clone();
+    let layer_renderer3 = layer_renderer.clone();
+    let overlay = renderer.clone();
+    let autosave2 = autosave.clone();
+    let georeference2 = georeference.clone();
+    let autosave_timer = slint::Timer::default();
+    autosave_timer.start(slint::TimerMode::Repeated, AUTOSAVE_INTERVAL, move || {
+        let Some(ui) = ui_handle_2.upgrade() else {
+            return;
+        };
+        if !ui.get_dirty() {
+            // Saved or unchanged project, the last autosave is outdated
+            autosave2.borrow_mut().clear();
+            return;
+        }
+        let mut project = io::Project::new(
+            ui.get_background_file().as_str(),
+            layer_renderer3.borrow().layers.as_ref(),
+            overlay.borrow().drawables.as_ref(),
+            overlay.borrow().groups.as_ref(),
+            georeference2.borrow().as_ref(),
+            ui.get_m_per_px(),
+        );
+        if let Err(e) = autosave2
+            .borrow_mut()
+            .save(ui.get_project_file().as_str(), &mut project)
+        {
+            log::warn!("Autosave failed: {:?}", e);
+            ui.set_contextual_text(SharedString::from(format!("Autosave failed: {}", e)));
+        }
+    });
+
+    if let Some(session) = recovery::unclean_session() {
+        log::info!("Previous session did not terminate properly");
+        let name = if session.project_file.is_empty() {
+            String::from("untitled project")
+        } else {
+            session.project_file.clone()
+        };
+        ui.set_recovered_project(SharedString::from(name));
+
+        let ui_handle_2 = ui_handle.clone();
+        let history2 = history.clone();
+        let autosave2 = autosave.clone();
+        let session2 = session.clone();
+        ui.on_restore_recovery(move || {
+            let ui = ui_handle_2.unwrap();
+            ui.invoke_load(SharedString::from(session2.recovery_file.as_str()));
+            if ui.get_project_file().as_str() != session2.recovery_file {
+                // Loading failed, the reason is displayed by the load callback
+                return;
+            }
+            ui.set_project_file(SharedString::from(session2.project_file.as_str()));
+            history2.borrow_mut().mark_unsaved();
+            ui.set_dirty(true);
+            autosave2.borrow_mut().adopt(&session2);
+            ui.set_contextual_text(SharedString::from("Project restored from autosave"));
+        });
 
-        let folders = fileselector::get_slint_folders_from_folder(&path);
+        ui.on_discard_recovery(move || recovery::discard_session(&session));
 
-        file_selector_bg.set_folders(folders);
+        let ui_handle_2 = ui_handle.clone();
+        slint::Timer::single_shot(std::time::Duration::ZERO, move || {
+            if let Some(ui) = ui_handle_2.upgrade() {
+                ui.invoke_show_recovery_prompt();
+            }
+        });
+    }
 
-        let files = fileselector::get_slint_files_from_folder(&path);
-        file_selector_bg.set_files(files);
+    let ui_handle_2 = ui_handle.clone();
+    let layer_renderer3 = layer_renderer.clone();
+    let overlay = renderer.clone();
+    let history2 = history.clone();
+    let georeference2 = georeference.clone();
+    ui.on_undo(move || {
+        let ui = ui_handle_2.unwrap();
+        replay_history(
+            &ui,
+            &history2,
+            &overlay,
+            &layer_renderer3,
+            &georeference2,
+            true,
+        );
+    });
+
+    let ui_handle_2 = ui_handle.clone();
+    let layer_renderer3 = layer_renderer.clone();
+    let overlay = renderer.clone();
+    let history2 = history.clone();
+    let georeference2 = georeference.clone();
+    ui.on_redo(move || {
+        let ui = ui_handle_2.unwrap();
+        replay_history(
+            &ui,
+            &history2,
+            &overlay,
+            &layer_renderer3,
+            &georeference2,
+            false,
+        );
+    });
+
+    let overlay = renderer.clone();
+    let layer_renderer3 = layer_renderer.clone();
+    let history2 = history.clone();
+    let georeference2 = georeference.clone();
+    ui.on_show_fileselector_bg(move || {
+        log::debug!("Entering on_show_fileselector");
+        let file_selector_bg =
+            match fileselector::show_file_selector("Load background", FileFilter::Images, false) {
+                Ok(file_selector) => file_selector,
+                Err(e) => {
+                    log::warn!("Cannot open file selector: {:?}", e);
+                    return;
+                }
+            };
+        let file_selector_weak = file_selector_bg.as_weak();
 
         file_selector_bg.on_send_ok({
             let ui_fs = file_selector_weak.unwrap();
             let ui = ui_handle3.unwrap();
             let overlay = overlay.clone();
             let layer = layer_renderer3.clone();
+            let history = history2.clone();
+            let georeference = georeference2.clone();
             move || {
-                let parent_path = ui_fs.get_path().to_string();
-                let parent_path = PathBuf::from(&parent_path);
-                let file = ui_fs.get_filename().to_string();
-                let image_path = parent_path.join(file);
+                let Some(image_path) = fileselector::selected_path(&ui_fs) else {
+                    return;
+                };
+
+                let before = ProjectSnapshot::capture(
+                    ui.get_background_file().as_str(),
+                    georeference.borrow().as_ref(),
+                    &overlay.borrow(),
+                    &layer.borrow(),
+                );
 
-                let mut renderer_bg = BackgroundRenderer::new(image_path.to_str().unwrap());
+                let image_path = image_path.to_string_lossy();
+                let mut renderer_bg = match BackgroundRenderer::new(&image_path) {
+                    Ok(renderer_bg) => renderer_bg,
+                    Err(e) => {
+                        log::warn!("Error loading background: {:?}", e);
+                        ui.set_contextual_text(SharedString::from(format!(
+                            "Cannot load {}: {}",
+                            image_path, e
+                        )));
+                        return;
+                    }
+                };
 
                 layer.borrow_mut().reset();
                 overlay
                     .borrow_mut()
-                    .reset(renderer_bg.image_height, renderer_bg.image_width);
+                    .reset(renderer_bg.image_width, renderer_bg.image_height);
 
-                ui.set_map(renderer_bg.render_background().unwrap());
+                if let Some(map) = renderer_bg.render_background() {
+                    ui.set_map(map);
+                }
 
-                ui.set_background_file(SharedString::from(image_path.to_str().unwrap()));
+                ui.set_background_file(SharedString::from(image_path.as_ref()));
+                // The georeference belongs to the previous background
+                georeference.replace(None);
+                update_georeference_model(&ui, None);
 
-                ui_fs.hide().unwrap();
-            }
-        });
+                let after = ProjectSnapshot::capture(
+                    &image_path,
+                    None,
+                    &overlay.borrow(),
+                    &layer.borrow(),
+                );
+                history.borrow_mut().push(Command::RestoreProject {
+                    before: Box::new(before),
+                    after: Box::new(after),
+                });
+                ui.set_dirty(history.borrow().is_modified());
 
-        file_selector_bg.on_send_cancel({
-            let ui_fs = file_selector_weak.unwrap();
-            move || {
-                ui_fs.hide().unwrap();
+                update_layers_model(&ui, &layer.borrow());
+                update_overlay_model(&ui, &mut overlay.borrow_mut(), None);
+
+                let _ = ui_fs.hide();
             }
         });
+    });
+
+    let history2 = history.clone();
+    ui.on_show_fileselector(move || {
+        log::debug!("Entering on_show_fileselector");
+        let file_selector =
+            match fileselector::show_file_selector("Add layer", FileFilter::Images, false) {
+                Ok(file_selector) => file_selector,
+                Err(e) => {
+                    log::warn!("Cannot open file selector: {:?}", e);
+                    return;
+                }
+            };
+        let file_selector_weak = file_selector.as_weak();
 
-        file_selector_bg.on_set_folder({
+        file_selector.on_send_ok({
             let ui_fs = file_selector_weak.unwrap();
+            let ui = ui_handle.unwrap();
+            let layer_renderer3 = layer_renderer2.clone();
+            let history = history2.clone();
             move || {
-                let parent_path = ui_fs.get_path().to_string();
-                let parent_path = PathBuf::from(&parent_path);
-                let child_path = ui_fs.get_current_folder().to_string();
+                let Some(image_path) = fileselector::selected_path(&ui_fs) else {
+                    return;
+                };
+                // New layers are displayed at the scale of the background
+                let m_per_px = ui.get_display_m_per_px();
+                let image_path = image_path.to_string_lossy();
+                if let Err(e) =
+                    layer_renderer3
+                        .borrow_mut()
+                        .add_layer(&image_path, 0, 0, 1., m_per_px)
+                {
+                    log::warn!("Error loading layer: {:?}", e);
+                    ui.set_contextual_text(SharedString::from(format!(
+                        "Cannot load {}: {}",
+                        image_path, e
+                    )));
+                    return;
+                }
 
-                let parent_path = parent_path.join(child_path);
-                let parent_path = parent_path.canonicalize().unwrap();
+                if let Some(layer) = layer_renderer3.borrow().layers.last() {
+                    history.borrow_mut().push(Command::AddLayer(layer.clone()));
+                }
+                ui.set_dirty(history.borrow().is_modified());
 
-                let parent_path = parent_path.to_str().unwrap();
+                update_layers_model(&ui, &layer_renderer3.borrow());
 
-                ui_fs.set_path(SharedString::from(parent_path));
+                let _ = ui_fs.hide();
+            }
+        });
+    });
 
-                let files_bg = fileselector::get_slint_files_from_folder(parent_path);
-                ui_fs.set_files(files_bg);
+    let control_points: Rc<RefCell<Vec<GroundControlPoint>>> = Rc::new(RefCell::new(vec![]));
+
+    let ui_handle_2 = ui.as_weak();
+    let georeference2 = georeference.clone();
+    let control_points2 = control_points.clone();
+    ui.on_open_georeference(move || {
+        let ui = ui_handle_2.unwrap();
+        let georeference = georeference2.borrow();
+        let points = georeference
+            .as_ref()
+            .map(|georeference| georeference.control_points.clone())
+            .unwrap_or_default();
+        update_control_points_model(
+            &ui,
+            &points,
+            georeference.as_ref().map(|georeference| &georeference.transform),
+        );
+        control_points2.replace(points);
+        ui.set_control_point_pending(false);
+        update_georeference_model(&ui, georeference.as_ref());
+    });
 
-                let folders_bg = fileselector::get_slint_folders_from_folder(parent_path);
-                ui_fs.set_folders(folders_bg);
-            }
+    let ui_handle_2 = ui.as_weak();
+    let georeference2 = georeference.clone();
+    let control_points2 = control_points.clone();
+    ui.on_add_control_point(move |x, y| {
+        let ui = ui_handle_2.unwrap();
+        let (Some(x), Some(y)) = (parse_coordinate(x.as_str()), parse_coordinate(y.as_str()))
+        else {
+            ui.set_georeference_status(SharedString::from("Invalid coordinates"));
+            return;
+        };
+        control_points2.borrow_mut().push(GroundControlPoint {
+            pixel: Point {
+                x: ui.get_control_point_x(),
+                y: ui.get_control_point_y(),
+            },
+            world: Coordinate { x, y },
         });
+        ui.set_control_point_pending(false);
+        update_control_points_model(
+            &ui,
+            &control_points2.borrow(),
+            georeference2
+                .borrow()
+                .as_ref()
+                .map(|georeference| &georeference.transform),
+        );
+    });
 
-        file_selector_bg.on_load_preview({
-            let ui_fs = file_selector_weak.clone();
-            move || {
-                let ui = ui_fs.unwrap();
-                let parent_path = ui.get_path().to_string();
-                let parent_path = PathBuf::from(&parent_path);
-                let file = ui.get_filename().to_string();
-                let image_path = parent_path.join(file);
-                log::debug!("Entering clossure");
-                let ui_fs = ui_fs.clone();
-                let _thread = std::thread::spawn(move || {
-                    log::debug!("Entering thread");
-                    let _ = slint::invoke_from_event_loop(move || {
-                        log::debug!("Entering invoke_from_event_loop");
-                        let ui = ui_fs.unwrap();
-                        log::debug!("Doing it");
-                        match slint::Image::load_from_path(image_path.as_path()) {
-                            Ok(image) => {
-                                ui.set_preview(image);
-                                log::debug!("Loading preview image")
-                            }
-                            Err(e) => {
-                                log::warn!("Error loading image: {:?}", e);
-                            }
-                        }
-                    });
-                });
+    let ui_handle_2 = ui.as_weak();
+    let georeference2 = georeference.clone();
+    let control_points2 = control_points.clone();
+    ui.on_remove_control_point(move |row| {
+        let ui = ui_handle_2.unwrap();
+        if row < 0 || row as usize >= control_points2.borrow().len() {
+            return;
+        }
+        control_points2.borrow_mut().remove(row as usize);
+        update_control_points_model(
+            &ui,
+            &control_points2.borrow(),
+            georeference2
+                .borrow()
+                .as_ref()
+                .map(|georeference| &georeference.transform),
+        );
+    });
+
+    let ui_handle_2 = ui.as_weak();
+    let georeference2 = georeference.clone();
+    let control_points2 = control_points.clone();
+    let history2 = history.clone();
+    ui.on_solve_georeference(move |helmert| {
+        let ui = ui_handle_2.unwrap();
+        let kind = if helmert {
+            TransformKind::Helmert
+        } else {
+            TransformKind::Affine
+        };
+        let points = control_points2.borrow().clone();
+        let solution = match gcp::solve(&points, kind) {
+            Ok(solution) => solution,
+            Err(e) => {
+                ui.set_georeference_status(SharedString::from(format!("Cannot solve: {}", e)));
+                return;
             }
+        };
+        let after = Georeference {
+            crs: ui.get_georeference_crs().trim().to_string(),
+            transform: solution.transform,
+            kind,
+            control_points: points,
+        };
+        let before = georeference2.replace(Some(after.clone()));
+        history2.borrow_mut().push(Command::SetGeoreference {
+            before,
+            after: Some(after.clone()),
         });
-    });
+        ui.set_dirty(history2.borrow().is_modified());
 
-    ui.on_show_fileselector(move || {
-        log::debug!("Entering on_show_fileselector");
-        let file_selector = FileSelector::new().unwrap();
-        file_selector.show().unwrap();
-        let file_selector_weak = file_selector.as_weak();
+        update_control_points_model(&ui, &after.control_points, Some(&after.transform));
+        update_georeference_model(&ui, Some(&after));
+        let status = format!("{:?} transform solved, RMS error {:.3}", kind, solution.rms);
+        ui.set_georeference_status(SharedString::from(status.as_str()));
+        ui.set_contextual_text(SharedString::from(status));
+    });
 
-        if file_selector.get_path().is_empty() {
-            file_selector.set_path(SharedString::from(current_dir().unwrap().to_str().unwrap()));
+    let ui_handle_2 = ui.as_weak();
+    let georeference2 = georeference.clone();
+    let history2 = history.clone();
+    ui.on_clear_georeference(move || {
+        let ui = ui_handle_2.unwrap();
+        let before = georeference2.replace(None);
+        if before.is_none() {
+            return;
         }
+        history2.borrow_mut().push(Command::SetGeoreference {
+            before,
+            after: None,
+        });
+        ui.set_dirty(history2.borrow().is_modified());
+        update_georeference_model(&ui, None);
+    });
 
-        let path = file_selector.get_path().to_string();
+    let ui_handle_2 = ui.as_weak();
+    let layer_renderer3 = layer_renderer.clone();
+    let history2 = history.clone();
+    ui.on_layer_edited(move |index| {
+        let ui = ui_handle_2.unwrap();
+        let Some(after) = usize::try_from(index)
+            .ok()
+            .and_then(|index| ui.get_layers().row_data(index))
+        else {
+            return;
+        };
+        let Some(before) = layer_renderer3
+            .borrow()
+            .layers
+            .iter()
+            .find(|layer| layer.id == after.id)
+            .cloned()
+        else {
+            return;
+        };
+        layer_renderer3.borrow_mut().update_layer(&after);
+
+        // Successive edits of the same layer, e.g. while dragging a slider, are undone at once
+        let mut history = history2.borrow_mut();
+        match history.last_unsaved_mut() {
+            Some(Command::EditLayer { before, after: last }) if before.id == after.id => {
+                *last = after;
+            }
+            _ => history.push(Command::EditLayer { before, after }),
+        }
+        ui.set_dirty(history.is_modified());
+    });
 
-        let folders = fileselector::get_slint_folders_from_folder(&path);
+    ui.on_calibration_scale(|pixels, distance, unit| {
+        calibrated_scale(pixels, distance.as_str(), unit.as_str()).unwrap_or(0.)
+    });
 
-        file_selector.set_folders(folders);
+    let ui_handle_2 = ui.as_weak();
+    let layer_renderer3 = layer_renderer.clone();
+    let history2 = history.clone();
+    ui.on_apply_calibration(move |distance, unit, to_layer| {
+        let ui = ui_handle_2.unwrap();
+        let Some(m_per_px) =
+            calibrated_scale(ui.get_calibration_px(), distance.as_str(), unit.as_str())
+        else {
+            ui.set_contextual_text(SharedString::from("Invalid distance"));
+            return;
+        };
+
+        let (command, calibrated) = if to_layer {
+            let layers = ui.get_layers();
+            let index = ui.get_selected_layer();
+            let Some(mut layer) = usize::try_from(index)
+                .ok()
+                .and_then(|index| layers.row_data(index))
+            else {
+                ui.set_contextual_text(SharedString::from("No layer selected"));
+                return;
+            };
+            // The distance was measured in background pixels, on the layer as currently
+            // scaled against the background
+            let after = m_per_px * layer_stretch(layer.m_per_px, ui.get_display_m_per_px());
+            let command = Command::SetScale {
+                layer: Some(layer.id),
+                before: layer.m_per_px,
+                after,
+            };
+            layer_renderer3.borrow_mut().set_scale(layer.id, after);
+            layer.m_per_px = after;
+            layers.set_row_data(index as usize, layer);
+            (command, after)
+        } else {
+            let command = Command::SetScale {
+                layer: None,
+                before: ui.get_m_per_px(),
+                after: m_per_px,
+            };
+            ui.set_m_per_px(m_per_px);
+            (command, m_per_px)
+        };
+
+        history2.borrow_mut().push(command);
+        ui.set_dirty(history2.borrow().is_modified());
+        ui.set_contextual_text(SharedString::from(format!(
+            "Scale calibrated to {:.4} m/px",
+            calibrated
+        )));
+    });
 
-        let files = fileselector::get_slint_files_from_folder(&path);
-        file_selector.set_files(files);
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    let georeference2 = georeference.clone();
+    ui.on_load_properties(move || {
+        let ui = ui_handle_2.unwrap();
+        let listview_id = ui.get_current_listview_drawable_item();
+        let overlay = overlay2.borrow();
+        let drawable = overlay.listed_drawable(listview_id);
+        // The handles of the selected drawable are shown along with its properties, unless
+        // it is locked
+        update_handles_model(&ui, drawable.filter(|d| !overlay.is_locked(d)));
+        let Some(drawable) = drawable else {
+            ui.set_drawable_properties(DrawableProperties {
+                id: -1,
+                ..Default::default()
+            });
+            return;
+        };
+        let georeference = georeference2.borrow();
+        let units = PropertyUnits::new(
+            ui.get_properties_map_units(),
+            ui.get_m_per_px(),
+            georeference.as_ref(),
+            drawable,
+        );
+        ui.set_properties_unit(SharedString::from(units.name()));
+        ui.set_drawable_properties(drawable_properties(
+            drawable,
+            units,
+            overlay.is_locked(drawable),
+        ));
+    });
 
-        file_selector.on_send_ok({
-            let ui_fs = file_selector_weak.unwrap();
-            let ui = ui_handle.unwrap();
-            let layer_renderer3 = layer_renderer2.clone();
-            move || {
-                let parent_path = ui_fs.get_path().to_string();
-                let parent_path = PathBuf::from(&parent_path);
-                let file = ui_fs.get_filename().to_string();
-                let image_path = parent_path.join(file);
-                let m_per_px = ui.get_m_per_px();
-                layer_renderer3.borrow_mut().add_layer(
-                    image_path.to_str().unwrap(),
-                    0,
-                    0,
-                    1.,
-                    m_per_px,
-                );
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    let georeference2 = georeference.clone();
+    let history2 = history.clone();
+    ui.on_apply_properties(move |properties| {
+        let ui = ui_handle_2.unwrap();
+        let Some(drawable) = overlay2.borrow().drawable(properties.id).cloned() else {
+            return;
+        };
+        if overlay2.borrow().is_locked(&drawable) {
+            ui.set_contextual_text(SharedString::from(format!(
+                "Object {} is in a locked group",
+                drawable.id
+            )));
+            return;
+        }
+        if overlay2.borrow().has_locked_dependents(drawable.id) {
+            ui.set_contextual_text(SharedString::from(format!(
+                "Objects built from object {} are in a locked group",
+                drawable.id
+            )));
+            return;
+        }
+        let edited = {
+            let georeference = georeference2.borrow();
+            let units = PropertyUnits::new(
+                ui.get_properties_map_units(),
+                ui.get_m_per_px(),
+                georeference.as_ref(),
+                &drawable,
+            );
+            edited_drawable(&drawable, &properties, units)
+        };
+        let Some(edited) = edited else {
+            ui.set_contextual_text(SharedString::from("Invalid value in the properties"));
+            return;
+        };
+        let Some((before, after)) = overlay2.borrow_mut().edit_drawable(edited) else {
+            ui.set_contextual_text(SharedString::from("The line does not cross the map"));
+            return;
+        };
+        let count = after.len() - 1;
+        history2
+            .borrow_mut()
+            .push(Command::EditDrawables { before, after });
+        ui.set_dirty(history2.borrow().is_modified());
+
+        update_overlay_model(&ui, &mut overlay2.borrow_mut(), Some(drawable.id));
+        ui.invoke_load_properties();
+        ui.set_contextual_text(SharedString::from(if count == 0 {
+            format!("Object {} edited", drawable.id)
+        } else {
+            format!("Object {} edited, {} objects built again", drawable.id, count)
+        }));
+    });
 
-                let items = VecModel::from(
-                    layer_renderer3
-                        .borrow()
-                        .layers
-                        .iter()
-                        .map(|layer| LayerDrawable {
-                            id: layer.id,
-                            data: layer.data.clone(),
-                            x: layer.x,
-                            y: layer.y,
-                            transparency: layer.transparency,
-                            m_per_px: layer.m_per_px,
-                            file: layer.file.clone(),
-                            name: layer.name.clone(),
-                        })
-                        .collect::<Vec<LayerDrawable>>(),
-                );
-                debug!("Layer items count: {}", items.row_count());
-                ui.set_layers(slint::ModelRc::new(items));
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    let history2 = history.clone();
+    ui.on_group_action(move |action| {
+        let ui = ui_handle_2.unwrap();
+        let mut overlay = overlay2.borrow_mut();
+        let text = apply_group_action(&ui, &mut overlay, &mut history2.borrow_mut(), action);
+        let selected = overlay
+            .listed_drawable(ui.get_current_listview_drawable_item())
+            .map(|d| d.id);
+        update_overlay_model(&ui, &mut overlay, selected);
+        drop(overlay);
+        ui.set_dirty(history2.borrow().is_modified());
+        ui.invoke_load_properties();
+        ui.set_contextual_text(SharedString::from(text));
+    });
 
-                let layers_list = slint::VecModel::from(
-                    layer_renderer3
-                        .borrow()
-                        .layers
-                        .iter()
-                        .map(|layer| {
-                            slint::StandardListViewItem::from(slint::SharedString::from(
-                                layer.name.as_str(),
-                            ))
-                        })
-                        .collect::<Vec<StandardListViewItem>>(),
-                );
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    ui.on_update_image(move || {
+        let ui = ui_handle_2.unwrap();
+        update_overlay_tiles(&ui, &mut overlay2.borrow_mut());
+    });
 
-                ui.set_layers_list(slint::ModelRc::new(layers_list));
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    ui.on_filter_objects(move || {
+        let ui = ui_handle_2.unwrap();
+        let mut overlay = overlay2.borrow_mut();
+        let selected = overlay
+            .listed_drawable(ui.get_current_listview_drawable_item())
+            .map(|d| d.id);
+        update_overlay_model(&ui, &mut overlay, selected);
+        let query = ui.get_object_filter();
+        if query.trim().is_empty() {
+            ui.set_contextual_text(SharedString::default());
+        } else {
+            let count = overlay
+                .drawables
+                .iter()
+                .filter(|d| d.matches(&query))
+                .count();
+            ui.set_contextual_text(SharedString::from(format!(
+                "{} objects matching \"{}\"",
+                count,
+                query.trim()
+            )));
+        }
+        ui.invoke_load_properties();
+    });
 
-                ui_fs.hide().unwrap();
-            }
-        });
+    let drag = Rc::new(RefCell::new(None::<Drag>));
+    // Vertices of the polyline or polygon being drawn
+    let shape_vertices = Rc::new(RefCell::new(Vec::<Point>::new()));
+
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    let georeference2 = georeference.clone();
+    let history2 = history.clone();
+    let shape_vertices2 = shape_vertices.clone();
+    ui.on_finish_shape(move || {
+        let ui = ui_handle_2.unwrap();
+        let (object_type, minimum) = match ui.get_current_action() {
+            NextAction::Polyline => (DrawableType::Polyline, 2),
+            NextAction::Polygon => (DrawableType::Polygon, 3),
+            _ => return,
+        };
+        let vertices = shape_vertices2.take();
+        ui.set_shape_preview_visible(false);
+        ui.set_current_action(NextAction::None);
+        if vertices.len() < minimum {
+            ui.set_contextual_text(SharedString::from(format!(
+                "A {:?} needs at least {} vertices",
+                object_type, minimum
+            )));
+            return;
+        }
 
-        file_selector.on_send_cancel({
-            let ui_fs = file_selector_weak.unwrap();
-            move || {
-                ui_fs.hide().unwrap();
+        let added = {
+            let mut overlay = overlay2.borrow_mut();
+            if object_type == DrawableType::Polygon {
+                overlay.add_polygon(vertices, ui.get_shape_fill());
+            } else {
+                overlay.add_polyline(vertices, "");
             }
-        });
-
-        file_selector.on_set_folder({
-            let ui_fs = file_selector_weak.unwrap();
-            move || {
-                let parent_path = ui_fs.get_path().to_string();
-                let parent_path = PathBuf::from(&parent_path);
-                let child_path = ui_fs.get_current_folder().to_string();
+            overlay.drawables.last().cloned()
+        };
+        let Some(added) = added else {
+            return;
+        };
+        history2
+            .borrow_mut()
+            .push(Command::AddDrawables(vec![added.clone()]));
+        ui.set_dirty(history2.borrow().is_modified());
+        update_overlay_model(&ui, &mut overlay2.borrow_mut(), Some(added.id));
+        ui.invoke_load_properties();
+
+        let measurement =
+            shape_measurement(ui.get_m_per_px(), georeference2.borrow().as_ref(), &added)
+                .unwrap_or_default();
+        ui.set_contextual_text(SharedString::from(format!(
+            "{:?} added, {}",
+            object_type, measurement
+        )));
+    });
 
-                let parent_path = parent_path.join(child_path);
-                let parent_path = parent_path.canonicalize().unwrap();
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    let drag2 = drag.clone();
+    ui.on_drag_start(move || {
+        let ui = ui_handle_2.unwrap();
+        if ui.get_current_action() != NextAction::None {
+            return false;
+        }
+        let listview_id = ui.get_current_listview_drawable_item();
+        let overlay = overlay2.borrow();
+        let Some(drawable) = overlay.listed_drawable(listview_id) else {
+            return false;
+        };
+        let handles = drawable.handles();
+        if handles.is_empty() || overlay.is_locked(drawable) {
+            return false;
+        }
 
-                let parent_path = parent_path.to_str().unwrap();
+        let start = Point {
+            x: ui.get_mouse_x() as f32,
+            y: ui.get_mouse_y() as f32,
+        };
+        let tolerance = DRAG_TOLERANCE * ui.get_viewport_zoom();
+        let handle = handles
+            .iter()
+            .map(|handle| math::distance(*handle, start))
+            .enumerate()
+            .filter(|(_, distance)| *distance <= tolerance)
+            .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2))
+            .map(|(index, _)| index);
+        if handle.is_none() && drawable.distance(start) > tolerance {
+            return false;
+        }
+        if overlay.has_locked_dependents(drawable.id) {
+            ui.set_contextual_text(SharedString::from(format!(
+                "Objects built from object {} are in a locked group",
+                drawable.id
+            )));
+            return false;
+        }
 
-                ui_fs.set_path(SharedString::from(parent_path));
+        let before = std::iter::once(drawable.clone())
+            .chain(overlay.dependents(&[drawable.id]))
+            .collect();
+        drag2.replace(Some(Drag {
+            before,
+            handle,
+            start,
+            moved: false,
+        }));
+        true
+    });
 
-                let files = fileselector::get_slint_files_from_folder(parent_path);
-                ui_fs.set_files(files);
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    let drag2 = drag.clone();
+    ui.on_drag_move(move || {
+        let ui = ui_handle_2.unwrap();
+        let mut drag = drag2.borrow_mut();
+        let Some(drag) = drag.as_mut() else {
+            return;
+        };
+        let position = Point {
+            x: ui.get_mouse_x() as f32,
+            y: ui.get_mouse_y() as f32,
+        };
+        let original = &drag.before[0];
+        let moved = match drag.handle {
+            Some(index) => original.with_handle_moved(index, position),
+            None => original.translated(position.x - drag.start.x, position.y - drag.start.y),
+        };
+        // Positions where a line does not cross the map are skipped
+        let mut overlay = overlay2.borrow_mut();
+        if overlay.edit_drawable(moved).is_some() {
+            drag.moved = true;
+            // The object list is only built again once the drag ends
+            update_overlay_tiles(&ui, &mut overlay);
+            update_handles_model(&ui, overlay.drawable(original.id));
+        }
+    });
 
-                let folders = fileselector::get_slint_folders_from_folder(parent_path);
-                ui_fs.set_folders(folders);
-            }
-        });
+    let ui_handle_2 = ui.as_weak();
+    let overlay2 = renderer.clone();
+    let history2 = history.clone();
+    let drag2 = drag.clone();
+    ui.on_drag_end(move || {
+        let ui = ui_handle_2.unwrap();
+        // A press without a move leaves nothing to record
+        let Some(Drag {
+            before,
+            moved: true,
+            ..
+        }) = drag2.take()
+        else {
+            return;
+        };
+        let after = before
+            .iter()
+            .filter_map(|d| overlay2.borrow().drawable(d.id).cloned())
+            .collect::<Vec<Drawable>>();
+        let id = before[0].id;
+        history2
+            .borrow_mut()
+            .push(Command::EditDrawables { before, after });
+        ui.set_dirty(history2.borrow().is_modified());
+        update_overlay_model(&ui, &mut overlay2.borrow_mut(), Some(id));
+        ui.invoke_load_properties();
+        ui.set_contextual_text(SharedString::from(format!("Object {} moved", id)));
+    });
 
-        file_selector.on_load_preview({
-            let ui_fs = file_selector_weak.clone();
-            move || {
-                let ui = ui_fs.unwrap();
-                let parent_path = ui.get_path().to_string();
-                let parent_path = PathBuf::from(&parent_path);
-                let file = ui.get_filename().to_string();
-                let image_path = parent_path.join(file);
-                log::debug!("Entering clossure");
-                let ui_fs = ui_fs.clone();
-                let _thread = std::thread::spawn(move || {
-                    log::debug!("Entering thread");
-                    let _ = slint::invoke_from_event_loop(move || {
-                        log::debug!("Entering invoke_from_event_loop");
-                        let ui = ui_fs.unwrap();
-                        log::debug!("Doing it");
-                        match slint::Image::load_from_path(image_path.as_path()) {
-                            Ok(image) => {
-                                ui.set_preview(image);
-                                log::debug!("Loading preview image")
-                            }
-                            Err(e) => {
-                                log::warn!("Error loading image: {:?}", e);
-                            }
-                        }
-                    });
-                });
-            }
-        });
+    let ui_handle_2 = ui.as_weak();
+    let georeference2 = georeference.clone();
+    let overlay2 = renderer.clone();
+    ui.on_cursor_moved(move || {
+        let ui = ui_handle_2.unwrap();
+        let pixel = Point {
+            x: ui.get_mouse_x() as f32,
+            y: ui.get_mouse_y() as f32,
+        };
+        let snap = snap_position(&ui, &overlay2.borrow(), pixel);
+        let mut position = cursor_position(georeference2.borrow().as_ref(), pixel);
+        if let Some(snap) = snap {
+            ui.set_snap_x(snap.point.x);
+            ui.set_snap_y(snap.point.y);
+            position = format!("Snap: {}  |  {}", snap.kind.describe(), position);
+        }
+        ui.set_snap_visible(snap.is_some());
+        ui.set_cursor_position(SharedString::from(position));
     });
 
     ui.on_image_click({
@@ -424,10 +2405,27 @@ fn main() -> Result<(), slint::PlatformError> {
         let ui = ui_handle.unwrap();
 
         let layer_renderer4 = layer_renderer.clone();
+        let history = history.clone();
+        let georeference = georeference.clone();
 
         move || {
-            let x = ui.get_mouse_x() as f32;
-            let y = ui.get_mouse_y() as f32;
+            let mouse = Point {
+                x: ui.get_mouse_x() as f32,
+                y: ui.get_mouse_y() as f32,
+            };
+            let snap = snap_position(&ui, &renderer.borrow(), mouse);
+            let Point { x, y } = snap.map(|snap| snap.point).unwrap_or(mouse);
+            ui.set_snap_visible(false);
+
+            // Vertices of an unfinished polyline or polygon are dropped when changing tool
+            if !matches!(
+                ui.get_current_action(),
+                NextAction::Polyline | NextAction::Polygon
+            ) && !shape_vertices.borrow().is_empty()
+            {
+                shape_vertices.borrow_mut().clear();
+                ui.set_shape_preview_visible(false);
+            }
             let red = ui.get_stroke_red().round() as u8;
             let green = ui.get_stroke_green().round() as u8;
             let blue = ui.get_stroke_blue().round() as u8;
@@ -435,45 +2433,73 @@ fn main() -> Result<(), slint::PlatformError> {
 
             renderer.borrow_mut().set_width(width);
             renderer.borrow_mut().set_color(red, green, blue);
+            let style = current_style(&ui);
+            renderer.borrow_mut().set_style(style);
 
             log::debug!("Mouse position = {x}, {y}");
 
             log::debug!("Current action: {:?}", ui.get_current_action());
 
+            let last_id = renderer
+                .borrow()
+                .drawables
+                .iter()
+                .map(|d| d.id)
+                .max()
+                .unwrap_or(0);
+
             let contextual_text = match ui.get_current_action() {
                 NextAction::None => None,
                 // Get closest object
                 NextAction::UpdateSelectedItem => {
-                    selected_listview_item = Some(ui.get_current_listview_drawable_item());
-                    renderer.borrow_mut().discard_overlay();
+                    let listview_id = ui.get_current_listview_drawable_item();
+                    selected_listview_item =
+                        renderer.borrow().listed_drawable(listview_id).map(|d| d.id);
                     None
                 }
                 NextAction::SelectObject => {
                     let closest_object = renderer.borrow().closest_object(Point { x, y });
                     match closest_object {
                         Some(object) => {
-                            renderer.borrow_mut().discard_overlay();
                             selected_listview_item = Some(object.id);
-                            Some(format!("{:?}", object))
+                            let measurement = shape_measurement(
+                                ui.get_m_per_px(),
+                                georeference.borrow().as_ref(),
+                                &object,
+                            )
+                            .map(|measurement| format!(", {}", measurement))
+                            .unwrap_or_default();
+                            if object.handles().is_empty() {
+                                Some(format!("{} selected{}", object.label(), measurement))
+                            } else {
+                                Some(format!(
+                                    "{} selected{}, drag its handles or its body to change it",
+                                    object.label(),
+                                    measurement
+                                ))
+                            }
                         }
                         None => {
                             selected_listview_item = None;
+                            ui.set_current_listview_drawable_item(-1);
                             Some("No line found".to_string())
                         }
                     }
                 }
                 // Vertical line
                 NextAction::Vertical => {
+                    let through = renderer.borrow().anchor_at(Point { x, y });
                     renderer
                         .borrow_mut()
-                        .add_line(Point { x, y: 0.0 }, Point { x, y: 480.0 });
+                        .add_construction(Construction::Vertical { through });
                     Some("Vertical line added".to_string())
                 }
                 // Horizontal line
                 NextAction::Horizontal => {
+                    let through = renderer.borrow().anchor_at(Point { x, y });
                     renderer
                         .borrow_mut()
-                        .add_line(Point { x: 0.0, y }, Point { x: 640.0, y });
+                        .add_construction(Construction::Horizontal { through });
                     Some("Horizontal line added".to_string())
                 }
                 // Point
@@ -495,6 +2521,33 @@ fn main() -> Result<(), slint::PlatformError> {
                     next_action = NextAction::None;
                     Some("Segment added".to_string())
                 }
+                // Label position
+                NextAction::Label => {
+                    if ui.get_label_leader() {
+                        standing_point = Point { x, y };
+                        next_action = NextAction::Label2;
+                        Some("Click on the point the leader line goes to".to_string())
+                    } else {
+                        renderer.borrow_mut().add_label(
+                            Point { x, y },
+                            None,
+                            ui.get_label_text().as_str(),
+                            ui.get_label_font_size() as f32,
+                        );
+                        Some("Label added".to_string())
+                    }
+                }
+                // Target of the label leader line
+                NextAction::Label2 => {
+                    renderer.borrow_mut().add_label(
+                        standing_point,
+                        Some(Point { x, y }),
+                        ui.get_label_text().as_str(),
+                        ui.get_label_font_size() as f32,
+                    );
+                    next_action = NextAction::None;
+                    Some("Label added".to_string())
+                }
                 // First angle computation line
                 NextAction::MeasureAngle => {
                     let closest_line = renderer.borrow().closest_line(Point { x, y });
@@ -511,9 +2564,8 @@ fn main() -> Result<(), slint::PlatformError> {
                 NextAction::MeasureAngle2 => {
                     next_action = NextAction::None;
                     let closest_line = renderer.borrow().closest_line(Point { x, y });
-                    match closest_line {
-                        Some(line) => {
-                            let line1 = standing_drawable.unwrap();
+                    match (closest_line, standing_drawable.take()) {
+                        (Some(line), Some(line1)) => {
                             let angle = math::angle_between(
                                 line1.point1,
                                 line1.point2,
@@ -528,7 +2580,7 @@ fn main() -> Result<(), slint::PlatformError> {
                                 180. - angle.to_degrees().abs()
                             ))
                         }
-                        None => Some("No line found".to_string()),
+                        _ => Some("No line found".to_string()),
                     }
                 }
                 // First line point
@@ -574,18 +2626,233 @@ fn main() -> Result<(), slint::PlatformError> {
                     );
                     Some("Circle added".to_string())
                 }
+                // Center of an arc or sector
+                NextAction::Arc | NextAction::Sector => {
+                    standing_point = Point { x, y };
+                    next_action = if ui.get_current_action() == NextAction::Arc {
+                        NextAction::Arc2
+                    } else {
+                        NextAction::Sector2
+                    };
+                    Some("Click where the edge starts, giving the radius".to_string())
+                }
+                // Start of the edge of an arc or sector
+                NextAction::Arc2 | NextAction::Sector2 => {
+                    standing_point_2 = Point { x, y };
+                    next_action = if ui.get_current_action() == NextAction::Arc2 {
+                        NextAction::Arc3
+                    } else {
+                        NextAction::Sector3
+                    };
+                    Some("Click in the direction the edge ends, clockwise".to_string())
+                }
+                // End of the edge of an arc or sector
+                NextAction::Arc3 | NextAction::Sector3 => {
+                    next_action = NextAction::None;
+                    let radius = math::distance(standing_point, standing_point_2);
+                    let start_angle = math::bearing(standing_point, standing_point_2);
+                    let end_angle = math::bearing(standing_point, Point { x, y });
+                    if ui.get_current_action() == NextAction::Arc3 {
+                        renderer
+                            .borrow_mut()
+                            .add_arc(standing_point, radius, start_angle, end_angle);
+                        Some("Arc added".to_string())
+                    } else {
+                        renderer.borrow_mut().add_sector(
+                            standing_point,
+                            radius,
+                            start_angle,
+                            end_angle,
+                            ui.get_shape_fill(),
+                        );
+                        Some("Sector added".to_string())
+                    }
+                }
+                // Center of an annulus
+                NextAction::Annulus => {
+                    standing_point = Point { x, y };
+                    next_action = NextAction::Annulus2;
+                    Some("Click on the inner edge".to_string())
+                }
+                // Inner edge of an annulus
+                NextAction::Annulus2 => {
+                    standing_point_2 = Point { x, y };
+                    next_action = NextAction::Annulus3;
+                    Some("Click on the outer edge".to_string())
+                }
+                // Start of the outer edge of an annulus, giving its radius
+                NextAction::Annulus3 => {
+                    standing_point_3 = Point { x, y };
+                    next_action = NextAction::Annulus4;
+                    Some(
+                        "Click in the direction the edge ends, clockwise, or on the center for a \
+                         full ring"
+                            .to_string(),
+                    )
+                }
+                // End of the edge of an annulus
+                NextAction::Annulus4 => {
+                    next_action = NextAction::None;
+                    let start_angle = math::bearing(standing_point, standing_point_3);
+                    let tolerance = ui.get_snap_tolerance() as f32 * ui.get_viewport_zoom();
+                    let end_angle = if math::distance(standing_point, Point { x, y }) <= tolerance {
+                        start_angle
+                    } else {
+                        math::bearing(standing_point, Point { x, y })
+                    };
+                    renderer.borrow_mut().add_annulus(
+                        standing_point,
+                        math::distance(standing_point, standing_point_2),
+                        math::distance(standing_point, standing_point_3),
+                        start_angle,
+                        end_angle,
+                        ui.get_shape_fill(),
+                    );
+                    Some("Annulus added".to_string())
+                }
+                // Center of an ellipse
+                NextAction::Ellipse => {
+                    standing_point = Point { x, y };
+                    next_action = NextAction::Ellipse2;
+                    Some("Click on the end of the first axis".to_string())
+                }
+                // End of the first axis of an ellipse
+                NextAction::Ellipse2 => {
+                    standing_point_2 = Point { x, y };
+                    next_action = NextAction::Ellipse3;
+                    Some("Click at the distance of the second axis".to_string())
+                }
+                // Length of the second axis of an ellipse
+                NextAction::Ellipse3 => {
+                    next_action = NextAction::None;
+                    let semi_axis = math::perpendicular_distance(
+                        Point { x, y },
+                        standing_point,
+                        standing_point_2,
+                    );
+                    if semi_axis < MIN_SEMI_AXIS
+                        || math::distance(standing_point, standing_point_2) < MIN_SEMI_AXIS
+                    {
+                        Some("The axes of the ellipse are too short".to_string())
+                    } else {
+                        renderer.borrow_mut().add_ellipse(
+                            standing_point,
+                            standing_point_2,
+                            semi_axis,
+                            ui.get_shape_fill(),
+                        );
+                        Some("Ellipse added".to_string())
+                    }
+                }
+                // First focus of an ellipse
+                NextAction::EllipseFoci => {
+                    standing_anchor = renderer.borrow().anchor_at(Point { x, y });
+                    next_action = NextAction::EllipseFoci2;
+                    Some("Click on the second focus".to_string())
+                }
+                // Second focus of an ellipse
+                NextAction::EllipseFoci2 => {
+                    standing_anchor_2 = renderer.borrow().anchor_at(Point { x, y });
+                    next_action = NextAction::EllipseFoci3;
+                    Some("Click on a point of the ellipse".to_string())
+                }
+                // Point the ellipse goes through
+                NextAction::EllipseFoci3 => {
+                    next_action = NextAction::None;
+                    let through = renderer.borrow().anchor_at(Point { x, y });
+                    if renderer
+                        .borrow_mut()
+                        .add_construction(Construction::EllipseFoci {
+                            focus1: standing_anchor,
+                            focus2: standing_anchor_2,
+                            through,
+                        })
+                    {
+                        Some("Ellipse added".to_string())
+                    } else {
+                        Some("No ellipse goes through a point between its foci".to_string())
+                    }
+                }
                 // Delete object
                 NextAction::Delete => {
                     let closest_line = renderer.borrow().closest_object(Point { x, y });
                     match closest_line {
+                        Some(line) if renderer.borrow().is_locked(&line) => {
+                            Some(format!("{} is in a locked group", line.label()))
+                        }
+                        Some(line)
+                            if renderer
+                                .borrow()
+                                .dependents(&[line.id])
+                                .iter()
+                                .any(|d| renderer.borrow().is_locked(d)) =>
+                        {
+                            Some("Objects built from it are in a locked group".to_string())
+                        }
                         Some(line) => {
-                            // Remove line from drawables based on id
-                            renderer.borrow_mut().remove_drawable(line.id);
-                            Some("Object deleted".to_string())
+                            // Drawables built from the object are deleted along with it
+                            let removed = renderer.borrow_mut().remove_with_dependents(&[line.id]);
+                            let dependents = removed.len().saturating_sub(1);
+                            if !removed.is_empty() {
+                                history
+                                    .borrow_mut()
+                                    .push(Command::DeleteDrawables(removed));
+                            }
+                            if dependents == 0 {
+                                Some("Object deleted".to_string())
+                            } else {
+                                Some(format!(
+                                    "Object deleted, along with {} objects built from it",
+                                    dependents
+                                ))
+                            }
+                        }
+                        None => Some("No object found".to_string()),
+                    }
+                }
+                // Apply current color, width and style to an object
+                NextAction::ApplyStyle => {
+                    let closest_object = renderer.borrow().closest_object(Point { x, y });
+                    match closest_object {
+                        Some(object) if renderer.borrow().is_locked(&object) => {
+                            Some(format!("{} is in a locked group", object.label()))
+                        }
+                        Some(object) => {
+                            let color = Color {
+                                r: red,
+                                g: green,
+                                b: blue,
+                            };
+                            renderer
+                                .borrow_mut()
+                                .set_drawable_style(object.id, color, width, style);
+                            history.borrow_mut().push(Command::ChangeStyle {
+                                id: object.id,
+                                before: (object.color, object.width, object.style),
+                                after: (color, width, style),
+                            });
+                            Some("Style applied".to_string())
                         }
                         None => Some("No object found".to_string()),
                     }
                 }
+                // First end of the known distance to calibrate the scale
+                NextAction::Calibrate => {
+                    standing_point = Point { x, y };
+                    next_action = NextAction::Calibrate2;
+                    Some("Click on the other end of the known distance".to_string())
+                }
+                // Other end of the known distance, its real length is then typed in
+                NextAction::Calibrate2 => {
+                    next_action = NextAction::None;
+                    let distance = math::distance(standing_point, Point { x, y });
+                    ui.set_calibration_px(distance);
+                    ui.invoke_show_calibration();
+                    Some(format!(
+                        "Enter the real length of the {:.1} px distance",
+                        distance
+                    ))
+                }
                 // First point to measure distance
                 NextAction::MeasureTwoPoints => {
                     standing_point = Point { x, y };
@@ -595,15 +2862,29 @@ fn main() -> Result<(), slint::PlatformError> {
                 // Second point to measure distance
                 NextAction::MeasureTwoPoints2 => {
                     next_action = NextAction::None;
-                    let m_per_px = ui.get_m_per_px();
                     let distance = math::distance(standing_point, Point { x, y });
                     debug!("Distance: {} px", distance);
+                    let meters = measured_distance(
+                        ui.get_m_per_px(),
+                        georeference.borrow().as_ref(),
+                        standing_point,
+                        Point { x, y },
+                    );
                     Some(format!(
                         "Distance beetwen two points is {:.2} km or {:.1} px",
-                        distance * m_per_px / 1000.,
+                        meters / 1000.,
                         distance
                     ))
                 }
+                // Pixel of a ground control point, its coordinates are then typed in
+                NextAction::GeoreferencePoint => {
+                    next_action = NextAction::None;
+                    ui.set_control_point_x(x);
+                    ui.set_control_point_y(y);
+                    ui.set_control_point_pending(true);
+                    ui.invoke_show_georeference_panel();
+                    Some("Enter the coordinates of the point".to_string())
+                }
                 // Point to measure distance to line
                 NextAction::MeasurePointToLine => {
                     standing_point = Point { x, y };
@@ -613,7 +2894,11 @@ fn main() -> Result<(), slint::PlatformError> {
                 // Line to measure distance
                 NextAction::MeasurePointToLine2 => {
                     next_action = NextAction::None;
-                    let m_per_px = ui.get_m_per_px();
+                    let m_per_px = scale_at(
+                        ui.get_m_per_px(),
+                        georeference.borrow().as_ref(),
+                        standing_point,
+                    );
                     let closest_line = renderer.borrow().closest_line(Point { x, y });
                     match closest_line {
                         Some(line) => {
@@ -633,11 +2918,15 @@ fn main() -> Result<(), slint::PlatformError> {
                 // Circle radius
                 NextAction::MeasureRadius => {
                     next_action = NextAction::None;
-                    let m_per_px = ui.get_m_per_px();
                     let closest_circle = renderer.borrow().closest_circle(Point { x, y });
                     match closest_circle {
                         Some(circle) => {
                             let distance = math::distance(circle.point1, circle.point2);
+                            let m_per_px = scale_at(
+                                ui.get_m_per_px(),
+                                georeference.borrow().as_ref(),
+                                circle.point1,
+                            );
                             Some(format!(
                                 "The radius is {:.2} km",
                                 distance * m_per_px / 1000.
@@ -648,7 +2937,7 @@ fn main() -> Result<(), slint::PlatformError> {
                 }
                 // Point the parallel line will go through
                 NextAction::Parallel => {
-                    standing_point = Point { x, y };
+                    standing_anchor = renderer.borrow().anchor_at(Point { x, y });
                     next_action = NextAction::Parallel2;
                     Some("Click on a line".to_string())
                 }
@@ -658,12 +2947,12 @@ fn main() -> Result<(), slint::PlatformError> {
                     let closest_line = renderer.borrow().closest_line(Point { x, y });
                     match closest_line {
                         Some(closest_line) => {
-                            let (point1, point2) = math::parallel_line(
-                                standing_point,
-                                closest_line.point1,
-                                closest_line.point2,
-                            );
-                            renderer.borrow_mut().add_line(point1, point2);
+                            renderer
+                                .borrow_mut()
+                                .add_construction(Construction::Parallel {
+                                    line: closest_line.id,
+                                    through: standing_anchor,
+                                });
                             Some("Parallel line added".to_string())
                         }
                         None => Some("No line found".to_string()),
@@ -671,20 +2960,23 @@ fn main() -> Result<(), slint::PlatformError> {
                 }
                 // First point for median
                 NextAction::TwoPointsMedian => {
-                    standing_point = Point { x, y };
+                    standing_anchor = renderer.borrow().anchor_at(Point { x, y });
                     next_action = NextAction::TwoPointsMedian2;
                     Some("Click on the second point".to_string())
                 }
                 // Second point for median
                 NextAction::TwoPointsMedian2 => {
                     next_action = NextAction::None;
-                    let (point1, point2) = math::median_line(standing_point, Point { x, y });
-                    renderer.borrow_mut().add_line(point1, point2);
+                    let point2 = renderer.borrow().anchor_at(Point { x, y });
+                    renderer.borrow_mut().add_construction(Construction::Median {
+                        point1: standing_anchor,
+                        point2,
+                    });
                     Some("Median line added".to_string())
                 }
                 // Point the perpendicular line will go through
                 NextAction::Perpendicular => {
-                    standing_point = Point { x, y };
+                    standing_anchor = renderer.borrow().anchor_at(Point { x, y });
                     next_action = NextAction::Perpendicular2;
                     Some("Click on a line".to_string())
                 }
@@ -694,12 +2986,12 @@ fn main() -> Result<(), slint::PlatformError> {
                     let closest_line = renderer.borrow().closest_line(Point { x, y });
                     match closest_line {
                         Some(closest_line) => {
-                            let (point1, point2) = math::perpendicular_line(
-                                standing_point,
-                                closest_line.point1,
-                                closest_line.point2,
-                            );
-                            renderer.borrow_mut().add_line(point1, point2);
+                            renderer
+                                .borrow_mut()
+                                .add_construction(Construction::Perpendicular {
+                                    line: closest_line.id,
+                                    through: standing_anchor,
+                                });
                             Some("Perpendicular line added".to_string())
                         }
                         None => Some("No line found".to_string()),
@@ -707,7 +2999,7 @@ fn main() -> Result<(), slint::PlatformError> {
                 }
                 // Point the new lines will go through, computed from given angle
                 NextAction::FromAngle => {
-                    standing_point = Point { x, y };
+                    standing_anchor = renderer.borrow().anchor_at(Point { x, y });
                     next_action = NextAction::FromAngle2;
                     Some("Click on a reference line".to_string())
                 }
@@ -717,20 +3009,16 @@ fn main() -> Result<(), slint::PlatformError> {
                     let closest_line = renderer.borrow().closest_line(Point { x, y });
                     match closest_line {
                         Some(closest_line) => {
-                            let (point1, point2) = math::parallel_line(
-                                standing_point,
-                                closest_line.point1,
-                                closest_line.point2,
-                            );
-
-                            let ((point1, point2), (point3, point4)) = math::get_lines_from_angles(
-                                point1,
-                                point2,
-                                standing_point,
-                                ui.get_angle(),
-                            );
-                            renderer.borrow_mut().add_line(point1, point2);
-                            renderer.borrow_mut().add_line(point3, point4);
+                            for index in 0..2 {
+                                renderer
+                                    .borrow_mut()
+                                    .add_construction(Construction::FromAngle {
+                                        line: closest_line.id,
+                                        through: standing_anchor,
+                                        angle: ui.get_angle(),
+                                        index,
+                                    });
+                            }
                             Some("Lines added".to_string())
                         }
                         None => Some("No line found".to_string()),
@@ -738,7 +3026,7 @@ fn main() -> Result<(), slint::PlatformError> {
                 }
                 // Point the tangent linse will go through
                 NextAction::Tangent => {
-                    standing_point = Point { x, y };
+                    standing_anchor = renderer.borrow().anchor_at(Point { x, y });
                     next_action = NextAction::Tangent2;
                     Some("Click on a line".to_string())
                 }
@@ -748,15 +3036,17 @@ fn main() -> Result<(), slint::PlatformError> {
                     let closest_circle = renderer.borrow().closest_circle(Point { x, y });
                     match closest_circle {
                         Some(closest_circle) => {
-                            if let Some(((point1, point2), (point3, point4))) =
-                                math::tangent_lines_to_circle(
-                                    standing_point,
-                                    closest_circle.center(),
-                                    closest_circle.radius(),
-                                )
-                            {
-                                renderer.borrow_mut().add_line(point1, point2);
-                                renderer.borrow_mut().add_line(point3, point4);
+                            let mut added = false;
+                            for index in 0..2 {
+                                added |= renderer
+                                    .borrow_mut()
+                                    .add_construction(Construction::Tangent {
+                                        circle: closest_circle.id,
+                                        through: standing_anchor,
+                                        index,
+                                    });
+                            }
+                            if added {
                                 Some("Tangent lines added".to_string())
                             } else {
                                 Some("Selected point is inside the circle".to_string())
@@ -768,10 +3058,134 @@ fn main() -> Result<(), slint::PlatformError> {
                 // Circle with center and radius in km
                 NextAction::CircleRadiusLength => {
                     next_action = NextAction::None;
-                    let radius = ui.get_radius() / ui.get_m_per_px() * 1_000.;
+                    let m_per_px = scale_at(
+                        ui.get_m_per_px(),
+                        georeference.borrow().as_ref(),
+                        Point { x, y },
+                    );
+                    let radius = ui.get_radius() / m_per_px * 1_000.;
                     renderer.borrow_mut().add_circle(Point { x, y }, radius);
                     Some("Circle added".to_string())
                 }
+                // First object to intersect
+                NextAction::Intersection => {
+                    let closest_object = renderer.borrow().closest_object(Point { x, y });
+                    match closest_object {
+                        Some(drawable) => {
+                            standing_drawable = Some(drawable);
+                            next_action = NextAction::Intersection2;
+                            Some("Click on the second object".to_string())
+                        }
+                        None => Some("No object found".to_string()),
+                    }
+                }
+                // Second object to intersect, intersection points are added
+                NextAction::Intersection2 => {
+                    next_action = NextAction::None;
+                    let closest_object = renderer.borrow().closest_object(Point { x, y });
+                    match (standing_drawable.take(), closest_object) {
+                        (Some(first), Some(second)) if first.id != second.id => {
+                            let (width, height) = renderer.borrow().image_size();
+                            let points = first
+                                .intersections(&second)
+                                .into_iter()
+                                .enumerate()
+                                .filter(|(_, point)| {
+                                    point.x >= 0.
+                                        && point.y >= 0.
+                                        && point.x <= width as f32
+                                        && point.y <= height as f32
+                                })
+                                .collect::<Vec<(usize, Point)>>();
+                            for (index, _) in points.iter() {
+                                renderer
+                                    .borrow_mut()
+                                    .add_construction(Construction::Intersection {
+                                        first: first.id,
+                                        second: second.id,
+                                        index: *index,
+                                    });
+                            }
+                            if points.is_empty() {
+                                Some(format!(
+                                    "{} and {} do not intersect on the map",
+                                    first.id, second.id
+                                ))
+                            } else {
+                                Some(format!(
+                                    "{} intersection points of {} and {} added",
+                                    points.len(),
+                                    first.id,
+                                    second.id
+                                ))
+                            }
+                        }
+                        (Some(_), Some(_)) => Some("Click on two different objects".to_string()),
+                        _ => Some("No object found".to_string()),
+                    }
+                }
+                // Vertex of a polyline or polygon, finished by a double-click
+                NextAction::Polyline | NextAction::Polygon => {
+                    next_action = ui.get_current_action();
+                    let object_type = if next_action == NextAction::Polygon {
+                        DrawableType::Polygon
+                    } else {
+                        DrawableType::Polyline
+                    };
+                    let mut vertices = shape_vertices.borrow_mut();
+                    // The second click of the double-click finishing the shape is not a vertex
+                    if vertices
+                        .last()
+                        .is_none_or(|last| math::distance(*last, Point { x, y }) > 1.)
+                    {
+                        vertices.push(Point { x, y });
+                    }
+                    ui.set_shape_preview(renderer.borrow().preview(object_type, &vertices));
+                    ui.set_shape_preview_visible(vertices.len() > 1);
+                    Some(format!(
+                        "{} vertices, double-click on the last one to finish",
+                        vertices.len()
+                    ))
+                }
+                // Point to be moved
+                NextAction::MovePoint => {
+                    let closest_point = renderer.borrow().closest_point(Point { x, y });
+                    match closest_point {
+                        Some(point) if point.construction.is_some() => Some(format!(
+                            "{} is built from other objects, move them instead",
+                            point.id
+                        )),
+                        Some(point) if renderer.borrow().is_locked(&point) => {
+                            Some(format!("{} is in a locked group", point.label()))
+                        }
+                        Some(point) if renderer.borrow().has_locked_dependents(point.id) => Some(
+                            format!("Objects built from {} are in a locked group", point.label()),
+                        ),
+                        Some(point) => {
+                            standing_drawable = Some(point);
+                            next_action = NextAction::MovePoint2;
+                            Some("Click on the new position of the point".to_string())
+                        }
+                        None => Some("No point found".to_string()),
+                    }
+                }
+                // New position of the point, objects built from it follow
+                NextAction::MovePoint2 => {
+                    next_action = NextAction::None;
+                    let moved = standing_drawable
+                        .take()
+                        .and_then(|point| renderer.borrow_mut().move_point(point.id, Point { x, y }));
+                    match moved {
+                        Some((before, after)) => {
+                            let count = after.len() - 1;
+                            history
+                                .borrow_mut()
+                                .push(Command::EditDrawables { before, after });
+                            Some(format!("Point moved, {} objects built again", count))
+                        }
+                        None => Some("No point found".to_string()),
+                    }
+                }
                 // First point for three point circle
                 NextAction::CircleThreeEdgePoints => {
                     standing_point = Point { x, y };
@@ -797,78 +3211,27 @@ fn main() -> Result<(), slint::PlatformError> {
                 }
             };
 
-            let d = renderer.borrow().get_drawables();
-
-            let mut my_vec = vec![];
-            for dd in d {
-                let s = format!("{} - {:?}", dd.id, dd.object_type);
-                let s = slint::StandardListViewItem::from(slint::SharedString::from(s.as_str()));
-                my_vec.push(s);
-                renderer
-                    .borrow_mut()
-                    .set_listview_id(dd.id, my_vec.len() as i32 - 1);
-                if selected_listview_item.is_some() && selected_listview_item.unwrap() == dd.id {
-                    ui.set_current_listview_drawable_item(my_vec.len() as i32 - 1);
-                }
+            let added = renderer
+                .borrow()
+                .drawables
+                .iter()
+                .filter(|d| d.id > last_id)
+                .cloned()
+                .collect::<Vec<_>>();
+            if !added.is_empty() {
+                history.borrow_mut().push(Command::AddDrawables(added));
             }
-            let model = slint::ModelRc::new(VecModel::from(my_vec));
-            ui.set_item_list(model);
+            ui.set_dirty(history.borrow().is_modified());
 
-            if contextual_text.is_some() {
-                ui.set_contextual_text(SharedString::from(contextual_text.unwrap().as_str()));
+            update_overlay_model(&ui, &mut renderer.borrow_mut(), selected_listview_item);
+
+            if let Some(contextual_text) = contextual_text {
+                ui.set_contextual_text(SharedString::from(contextual_text.as_str()));
             }
             ui.set_current_action(next_action);
 
-            let items = VecModel::from(
-                renderer
-                    .borrow()
-                    .drawable_images
-                    .iter()
-                    .map(|d| OverlayDrawable {
-                        id: d.id,
-                        data: d.data.clone(),
-                        x: d.x,
-                        y: d.y,
-                    })
-                    .collect::<Vec<OverlayDrawable>>(),
-            );
-            debug!("Overlay items count: {}", items.row_count());
-            ui.set_overlay_drawables(slint::ModelRc::new(items));
-
-            let items = VecModel::from(
-                layer_renderer4
-                    .borrow()
-                    .layers
-                    .iter()
-                    .map(|layer| LayerDrawable {
-                        id: layer.id,
-                        data: layer.data.clone(),
-                        x: layer.x,
-                        y: layer.y,
-                        transparency: layer.transparency,
-                        m_per_px: layer.m_per_px,
-                        file: layer.file.clone(),
-                        name: layer.name.clone(),
-                    })
-                    .collect::<Vec<LayerDrawable>>(),
-            );
-            debug!("Layer items count: {}", items.row_count());
-            ui.set_layers(slint::ModelRc::new(items));
-
-            let layers_list = slint::VecModel::from(
-                layer_renderer4
-                    .borrow()
-                    .layers
-                    .iter()
-                    .map(|layer| {
-                        slint::StandardListViewItem::from(slint::SharedString::from(
-                            layer.name.as_str(),
-                        ))
-                    })
-                    .collect::<Vec<StandardListViewItem>>(),
-            );
-
-            ui.set_layers_list(slint::ModelRc::new(layers_list));
+            update_layers_model(&ui, &layer_renderer4.borrow());
+            ui.invoke_load_properties();
         }
     });
 
@@ -882,5 +3245,54 @@ fn main() -> Result<(), slint::PlatformError> {
         //For example: hide the console window peculiar to slint
     });
 
-    ui.run()
+    let result = ui.run();
+    autosave.borrow_mut().clear();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Background in Lambert 93 with square pixels of the given size, north up
+    fn georeference(m_per_px: f64) -> Georeference {
+        Georeference {
+            crs: String::from(projection::LAMBERT_93),
+            transform: AffineTransform {
+                a: m_per_px,
+                b: 0.,
+                c: 700_000.,
+                d: 0.,
+                e: -m_per_px,
+                f: 6_600_000.,
+            },
+            kind: TransformKind::Affine,
+            control_points: vec![],
+        }
+    }
+
+    #[test]
+    fn distance_over_layer_matches_layer_scale() {
+        // A 1:100000 layer over a 1:25000 background, the scale typed in the UI being
+        // unrelated to the georeference
+        let background_m_per_px = 170.;
+        let layer_m_per_px = 10.;
+        for georeference in [None, Some(georeference(2.5))] {
+            let display =
+                display_m_per_px(background_m_per_px, georeference.as_ref(), (4000, 3000));
+            let stretch = layer_stretch(layer_m_per_px, display);
+            // 100 pixels of the layer, from a layer placed at (500, 400) on the background
+            let start = Point { x: 500., y: 400. };
+            let end = Point {
+                x: start.x + 60. * stretch,
+                y: start.y + 80. * stretch,
+            };
+            let meters = measured_distance(background_m_per_px, georeference.as_ref(), start, end);
+            assert!(
+                (meters - 100. * layer_m_per_px).abs() < 1e-2,
+                "{} m",
+                meters
+            );
+        }
+    }
 }
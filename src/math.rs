@@ -21,6 +21,55 @@ pub fn distance(p1: Point, p2: Point) -> f32 {
     ((p1.x - p2.x).powf(2.0)  + (p1.y - p2.y).powf(2.0) ).sqrt()
 }
 
+/// Calculate the length of a path through a list of vertices
+///
+/// # Arguments
+///
+/// * `points` - The vertices of the path
+/// * `closed` - Whether the path goes back from the last vertex to the first one
+pub fn path_length(points: &[Point], closed: bool) -> f32 {
+    let open: f32 = points.windows(2).map(|edge| distance(edge[0], edge[1])).sum();
+    match (closed, points.first(), points.last()) {
+        (true, Some(first), Some(last)) => open + distance(*last, *first),
+        _ => open,
+    }
+}
+
+/// Calculate the area of a polygon with the shoelace formula
+///
+/// The polygon is implicitly closed, and a self-intersecting polygon gives the
+/// difference of the areas of its loops.
+///
+/// # Arguments
+///
+/// * `points` - The vertices of the polygon
+pub fn polygon_area(points: &[Point]) -> f32 {
+    let twice_area: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(p1, p2)| p1.x * p2.y - p2.x * p1.y)
+        .sum();
+    twice_area.abs() / 2.
+}
+
+/// Check whether a point is inside a polygon, with the even-odd rule
+///
+/// # Arguments
+///
+/// * `point` - The point
+/// * `points` - The vertices of the polygon
+pub fn polygon_contains(point: Point, points: &[Point]) -> bool {
+    let mut inside = false;
+    for (p1, p2) in points.iter().zip(points.iter().cycle().skip(1)) {
+        if (p1.y > point.y) != (p2.y > point.y)
+            && point.x < p1.x + (point.y - p1.y) * (p2.x - p1.x) / (p2.y - p1.y)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
 /// Calculate the angle between two lines in radians
 ///
 /// # Arguments
@@ -100,9 +149,7 @@ pub fn distance_to_segment(point: Point, point1: Point, point2: Point) -> f32 {
     let distance_to_p1 = distance(point, point1);
     let distance_to_p2 = distance(point, point2);
 
-    let distance = perp_distance.min(distance_to_p1).min(distance_to_p2);
-
-    distance
+    perp_distance.min(distance_to_p1).min(distance_to_p2)
 }
 
 /// Calculate the distance from a point to a drawn part of a halfline
@@ -120,9 +167,7 @@ pub fn distance_to_half_line(point: Point, point1: Point, point2: Point) -> f32
     let perp_distance = perpendicular_distance(point, point1, point2);
 
     let distance_to_p1 = distance(point, point1);
-    let distance = perp_distance.min(distance_to_p1);
-
-    distance
+    perp_distance.min(distance_to_p1)
 }
 
 
@@ -139,7 +184,7 @@ pub fn distance_to_half_line(point: Point, point1: Point, point2: Point) -> f32
 ///
 /// # Returns
 ///
-/// The extreme coordinates of the line
+/// The extreme coordinates of the line, or `None` if the line does not cross the area
 pub fn find_line_extreme_coordinates(
     p1: Point,
     p2: Point,
@@ -147,9 +192,12 @@ pub fn find_line_extreme_coordinates(
     x_max: f32,
     y_min: f32,
     y_max: f32,
-) -> (Point, Point) {
+) -> Option<(Point, Point)> {
     if p1.x == p2.x {
-        return (
+        if p1.x < x_min || p1.x > x_max {
+            return None;
+        }
+        return Some((
             Point {
                 x: p1.x,
                 y: y_min,
@@ -158,18 +206,24 @@ pub fn find_line_extreme_coordinates(
                 x: p1.x,
                 y: y_max ,
             },
-        );
+        ));
     }
     let slope = (p2.y - p1.y)  / (p2.x - p1.x) ;
     let intercept = p1.y  - slope * p1.x ;
 
-    let mut points = Vec::new();
+    let mut points: Vec<Point> = Vec::new();
+    // A line going through a corner hits two sides at the same point
+    let mut add_point = |point: Point| {
+        if !points.contains(&point) {
+            points.push(point);
+        }
+    };
 
     // Intersection with y = y_min
     if slope != 0.0 {
         let x = (y_min - intercept) / slope;
         if x >= x_min && x <= x_max {
-            points.push(Point {
+            add_point(Point {
                 x,
                 y: y_min,
             });
@@ -180,7 +234,7 @@ pub fn find_line_extreme_coordinates(
     if slope != 0.0 {
         let x = (y_max - intercept) / slope;
         if x >= x_min && x <= x_max {
-            points.push(Point {
+            add_point(Point {
                 x,
                 y: y_max ,
             });
@@ -190,7 +244,7 @@ pub fn find_line_extreme_coordinates(
     // Intersection with x = x_min
     let y = slope * x_min + intercept;
     if y >= y_min && y <= y_max {
-        points.push(Point {
+        add_point(Point {
             x: x_min ,
             y,
         });
@@ -199,18 +253,18 @@ pub fn find_line_extreme_coordinates(
     // Intersection with x = x_max
     let y = slope * x_max + intercept;
     if y >= y_min && y <= y_max {
-        points.push(Point {
+        add_point(Point {
             x: x_max ,
             y,
         });
     }
 
-    // Ensure we have exactly two points
+    // The line may miss the area, or only touch one of its corners
     if points.len() != 2 {
-        panic!("Line does not intersect the bounding box in exactly two points");
+        return None;
     }
 
-    (points[0], points[1])
+    Some((points[0], points[1]))
 }
 
 /// Find the coordinates of a line parallel to a given line and passing through a specific point
@@ -447,4 +501,562 @@ pub fn circle_from_three_points(p1: Point, p2: Point, p3: Point) -> (Point, f32)
     let center = circle_center_from_three_points(p1, p2, p3);
 
     (center, distance(center, p1))
-}
\ No newline at end of file
+}
+/// Tolerance used when comparing line parameters and distances in intersections
+const INTERSECTION_EPSILON: f32 = 1e-4;
+
+/// Part of a line that is actually drawn
+///
+/// A point of the line through `p1` and `p2` is written `p1 + t * (p2 - p1)`, the extent
+/// gives the values of `t` belonging to the drawn part.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineExtent {
+    /// Infinite line, any `t`
+    Line,
+    /// Half-line starting at `p1` and going through `p2`, `t >= 0`
+    HalfLine,
+    /// Segment between `p1` and `p2`, `0 <= t <= 1`
+    Segment,
+}
+
+impl LineExtent {
+    /// Check whether the point of parameter `t` is drawn
+    pub fn contains(&self, t: f32) -> bool {
+        match self {
+            LineExtent::Line => true,
+            LineExtent::HalfLine => t >= -INTERSECTION_EPSILON,
+            LineExtent::Segment => (-INTERSECTION_EPSILON..=1. + INTERSECTION_EPSILON).contains(&t),
+        }
+    }
+}
+
+/// Compute the intersection of two lines
+///
+/// # Arguments
+///
+/// * `p1` - The first point of the first line
+/// * `p2` - The second point of the first line
+/// * `extent1` - The drawn part of the first line
+/// * `p3` - The first point of the second line
+/// * `p4` - The second point of the second line
+/// * `extent2` - The drawn part of the second line
+///
+/// # Returns
+///
+/// The intersection point, or `None` if the lines are parallel or if the intersection
+/// lies outside of the drawn parts
+pub fn line_intersection(
+    p1: Point,
+    p2: Point,
+    extent1: LineExtent,
+    p3: Point,
+    p4: Point,
+    extent2: LineExtent,
+) -> Option<Point> {
+    let (dx1, dy1) = (p2.x - p1.x, p2.y - p1.y);
+    let (dx2, dy2) = (p4.x - p3.x, p4.y - p3.y);
+    let denominator = dx1 * dy2 - dy1 * dx2;
+    // Parallel or degenerated lines, overlapping lines have no single intersection
+    if denominator.abs() <= f32::EPSILON * (dx1.hypot(dy1) * dx2.hypot(dy2)) {
+        return None;
+    }
+    let (ex, ey) = (p3.x - p1.x, p3.y - p1.y);
+    let t = (ex * dy2 - ey * dx2) / denominator;
+    let u = (ex * dy1 - ey * dx1) / denominator;
+    if !extent1.contains(t) || !extent2.contains(u) {
+        return None;
+    }
+    Some(Point {
+        x: p1.x + t * dx1,
+        y: p1.y + t * dy1,
+    })
+}
+
+/// Compute the intersections of a line and a circle
+///
+/// # Arguments
+///
+/// * `p1` - The first point of the line
+/// * `p2` - The second point of the line
+/// * `extent` - The drawn part of the line
+/// * `center` - The circle center
+/// * `radius` - The circle radius
+///
+/// # Returns
+///
+/// The intersection points, none if the line misses the circle, one if it is tangent
+pub fn line_circle_intersections(
+    p1: Point,
+    p2: Point,
+    extent: LineExtent,
+    center: Point,
+    radius: f32,
+) -> Vec<Point> {
+    let (dx, dy) = (p2.x - p1.x, p2.y - p1.y);
+    let (fx, fy) = (p1.x - center.x, p1.y - center.y);
+    let a = dx * dx + dy * dy;
+    if a == 0. {
+        return vec![];
+    }
+    let b = 2. * (fx * dx + fy * dy);
+    let c = fx * fx + fy * fy - radius * radius;
+    let discriminant = b * b - 4. * a * c;
+    // Relative tolerance, so that a tangent line computed with rounding errors still touches
+    if discriminant < -INTERSECTION_EPSILON * b * b {
+        return vec![];
+    }
+    let root = discriminant.max(0.).sqrt();
+    let mut parameters = vec![(-b - root) / (2. * a)];
+    if root > INTERSECTION_EPSILON * b.abs().max(1.) {
+        parameters.push((-b + root) / (2. * a));
+    }
+    parameters
+        .into_iter()
+        .filter(|t| extent.contains(*t))
+        .map(|t| Point {
+            x: p1.x + t * dx,
+            y: p1.y + t * dy,
+        })
+        .collect()
+}
+
+/// Compute the intersections of two circles
+///
+/// # Arguments
+///
+/// * `center1` - The first circle center
+/// * `radius1` - The first circle radius
+/// * `center2` - The second circle center
+/// * `radius2` - The second circle radius
+///
+/// # Returns
+///
+/// The intersection points, none if the circles do not meet or are concentric, one if
+/// they are tangent
+pub fn circle_intersections(
+    center1: Point,
+    radius1: f32,
+    center2: Point,
+    radius2: f32,
+) -> Vec<Point> {
+    let d = distance(center1, center2);
+    let tolerance = INTERSECTION_EPSILON * radius1.max(radius2).max(1.);
+    if d <= tolerance
+        || d > radius1 + radius2 + tolerance
+        || d < (radius1 - radius2).abs() - tolerance
+    {
+        return vec![];
+    }
+    // Distance from the first center to the chord joining the intersections
+    let a = (radius1 * radius1 - radius2 * radius2 + d * d) / (2. * d);
+    let h = (radius1 * radius1 - a * a).max(0.).sqrt();
+    let (ux, uy) = ((center2.x - center1.x) / d, (center2.y - center1.y) / d);
+    let base = Point {
+        x: center1.x + a * ux,
+        y: center1.y + a * uy,
+    };
+    if h <= tolerance {
+        return vec![base];
+    }
+    vec![
+        Point {
+            x: base.x - h * uy,
+            y: base.y + h * ux,
+        },
+        Point {
+            x: base.x + h * uy,
+            y: base.y - h * ux,
+        },
+    ]
+}
+
+/// Find the point of the drawn part of a line closest to a given point
+///
+/// # Arguments
+///
+/// * `point` - The point
+/// * `p1` - The first point of the line
+/// * `p2` - The second point of the line
+/// * `extent` - The drawn part of the line
+pub fn project_on_line_extent(point: Point, p1: Point, p2: Point, extent: LineExtent) -> Point {
+    let (dx, dy) = (p2.x - p1.x, p2.y - p1.y);
+    let length = dx * dx + dy * dy;
+    if length == 0. {
+        return p1;
+    }
+    let t = ((point.x - p1.x) * dx + (point.y - p1.y) * dy) / length;
+    let t = match extent {
+        LineExtent::Line => t,
+        LineExtent::HalfLine => t.max(0.),
+        LineExtent::Segment => t.clamp(0., 1.),
+    };
+    Point {
+        x: p1.x + t * dx,
+        y: p1.y + t * dy,
+    }
+}
+
+/// Calculate the distance from a point to the drawn part of a line
+///
+/// # Arguments
+///
+/// * `point` - The point
+/// * `p1` - The first point of the line
+/// * `p2` - The second point of the line
+/// * `extent` - The drawn part of the line
+pub fn distance_to_line_extent(point: Point, p1: Point, p2: Point, extent: LineExtent) -> f32 {
+    distance(point, project_on_line_extent(point, p1, p2, extent))
+}
+
+/// Find the point of a circle closest to a given point
+///
+/// # Arguments
+///
+/// * `point` - The point
+/// * `center` - The center of the circle
+/// * `radius` - The radius of the circle
+pub fn project_on_circle(point: Point, center: Point, radius: f32) -> Point {
+    let d = distance(point, center);
+    if d == 0. {
+        // Every point of the circle is as close, pick the rightmost one
+        return Point {
+            x: center.x + radius,
+            y: center.y,
+        };
+    }
+    Point {
+        x: center.x + (point.x - center.x) * radius / d,
+        y: center.y + (point.y - center.y) * radius / d,
+    }
+}
+
+/// Calculate the bearing of a point seen from a center
+///
+/// Bearings are in degrees, clockwise from the top of the map, in `[0, 360)`.
+///
+/// # Arguments
+///
+/// * `center` - The center
+/// * `point` - The point
+pub fn bearing(center: Point, point: Point) -> f32 {
+    (point.x - center.x)
+        .atan2(center.y - point.y)
+        .to_degrees()
+        .rem_euclid(360.)
+}
+
+/// Find the point at a given distance and bearing from a center
+///
+/// # Arguments
+///
+/// * `center` - The center
+/// * `radius` - The distance from the center
+/// * `bearing` - The bearing, in degrees clockwise from the top of the map
+pub fn point_at_bearing(center: Point, radius: f32, bearing: f32) -> Point {
+    let angle = bearing.to_radians();
+    Point {
+        x: center.x + radius * angle.sin(),
+        y: center.y - radius * angle.cos(),
+    }
+}
+
+/// Calculate the clockwise sweep from a start bearing to an end bearing
+///
+/// # Returns
+///
+/// The sweep in degrees, in `(0, 360]`, a full turn when both bearings are equal
+pub fn sweep(start: f32, end: f32) -> f32 {
+    let sweep = (end - start).rem_euclid(360.);
+    if sweep == 0. {
+        360.
+    } else {
+        sweep
+    }
+}
+
+/// Check whether a bearing lies within a clockwise sweep
+///
+/// # Arguments
+///
+/// * `bearing` - The bearing to check
+/// * `start` - The start bearing of the sweep
+/// * `sweep` - The sweep, as returned by [`sweep`]
+pub fn bearing_in_sweep(bearing: f32, start: f32, sweep: f32) -> bool {
+    (bearing - start).rem_euclid(360.) <= sweep
+}
+
+/// Approximate an arc of circle by a polyline, with a vertex every degree
+///
+/// # Arguments
+///
+/// * `center` - The center of the arc
+/// * `radius` - The radius of the arc
+/// * `start` - The start bearing of the arc
+/// * `sweep` - The clockwise sweep of the arc, in degrees
+///
+/// # Returns
+///
+/// The vertices from the start to the end of the arc
+pub fn arc_points(center: Point, radius: f32, start: f32, sweep: f32) -> Vec<Point> {
+    let steps = (sweep.abs().ceil() as usize).max(1);
+    (0..=steps)
+        .map(|i| point_at_bearing(center, radius, start + sweep * i as f32 / steps as f32))
+        .collect()
+}
+
+/// Approximate an ellipse by a polygon
+///
+/// # Arguments
+///
+/// * `center` - The center of the ellipse
+/// * `axis_end` - The end of the first semi-axis, giving its length and the rotation
+/// * `semi_axis` - The length of the second semi-axis, perpendicular to the first one
+/// * `count` - The number of vertices
+///
+/// # Returns
+///
+/// The vertices, starting at the end of the first semi-axis
+pub fn ellipse_points(center: Point, axis_end: Point, semi_axis: f32, count: usize) -> Vec<Point> {
+    let a = distance(center, axis_end);
+    let rotation = (axis_end.y - center.y).atan2(axis_end.x - center.x);
+    let (sin, cos) = rotation.sin_cos();
+    (0..count)
+        .map(|i| {
+            let t = 2. * std::f32::consts::PI * i as f32 / count as f32;
+            let (u, v) = (a * t.cos(), semi_axis * t.sin());
+            Point {
+                x: center.x + u * cos - v * sin,
+                y: center.y + u * sin + v * cos,
+            }
+        })
+        .collect()
+}
+
+/// Check whether a point is inside an ellipse
+///
+/// # Arguments
+///
+/// * `point` - The point
+/// * `center` - The center of the ellipse
+/// * `axis_end` - The end of the first semi-axis
+/// * `semi_axis` - The length of the second semi-axis
+pub fn ellipse_contains(point: Point, center: Point, axis_end: Point, semi_axis: f32) -> bool {
+    let a = distance(center, axis_end);
+    if a == 0. || semi_axis == 0. {
+        return false;
+    }
+    let rotation = (axis_end.y - center.y).atan2(axis_end.x - center.x);
+    let (sin, cos) = rotation.sin_cos();
+    let (dx, dy) = (point.x - center.x, point.y - center.y);
+    let u = (dx * cos + dy * sin) / a;
+    let v = (-dx * sin + dy * cos) / semi_axis;
+    u * u + v * v <= 1.
+}
+
+/// Calculate the perimeter of an ellipse with Ramanujan's approximation
+///
+/// # Arguments
+///
+/// * `a` - The length of the first semi-axis
+/// * `b` - The length of the second semi-axis
+pub fn ellipse_perimeter(a: f32, b: f32) -> f32 {
+    std::f32::consts::PI * (3. * (a + b) - ((3. * a + b) * (a + 3. * b)).sqrt())
+}
+
+/// Find the ellipse with two given foci going through a point
+///
+/// # Arguments
+///
+/// * `focus1` - The first focus
+/// * `focus2` - The second focus
+/// * `through` - A point of the ellipse
+///
+/// # Returns
+///
+/// The center, the end of the major semi-axis on the side of the second focus and the
+/// length of the minor semi-axis, or `None` if the point lies between the foci
+pub fn ellipse_from_foci(
+    focus1: Point,
+    focus2: Point,
+    through: Point,
+) -> Option<(Point, Point, f32)> {
+    let a = (distance(focus1, through) + distance(focus2, through)) / 2.;
+    let c = distance(focus1, focus2) / 2.;
+    let b = (a * a - c * c).max(0.).sqrt();
+    if b < 1e-3 {
+        return None;
+    }
+    let center = Point {
+        x: (focus1.x + focus2.x) / 2.,
+        y: (focus1.y + focus2.y) / 2.,
+    };
+    // Coincident foci give a circle, whose axes may point anywhere
+    let (ux, uy) = if c > 0. {
+        (
+            (focus2.x - focus1.x) / (2. * c),
+            (focus2.y - focus1.y) / (2. * c),
+        )
+    } else {
+        (1., 0.)
+    };
+    let axis_end = Point {
+        x: center.x + a * ux,
+        y: center.y + a * uy,
+    };
+    Some((center, axis_end, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    fn point(x: f32, y: f32) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn segments_intersect_within_their_extent() {
+        let crossing = line_intersection(
+            point(0., 0.),
+            point(10., 10.),
+            LineExtent::Segment,
+            point(0., 10.),
+            point(10., 0.),
+            LineExtent::Segment,
+        )
+        .unwrap();
+        assert_close(crossing.x, 5., 1e-4);
+        assert_close(crossing.y, 5., 1e-4);
+
+        // The lines cross at (5, 5), beyond the end of the second segment
+        let short = (point(0., 10.), point(4., 6.));
+        assert!(line_intersection(
+            point(0., 0.),
+            point(10., 10.),
+            LineExtent::Segment,
+            short.0,
+            short.1,
+            LineExtent::Segment,
+        )
+        .is_none());
+        assert!(line_intersection(
+            point(0., 0.),
+            point(10., 10.),
+            LineExtent::Segment,
+            short.0,
+            short.1,
+            LineExtent::Line,
+        )
+        .is_some());
+
+        // Parallel lines do not intersect
+        assert!(line_intersection(
+            point(0., 0.),
+            point(10., 0.),
+            LineExtent::Line,
+            point(0., 1.),
+            point(10., 1.),
+            LineExtent::Line,
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn half_line_pointing_away_misses() {
+        // Half-line starting at (20, 20) and going away from the segment
+        let away = line_intersection(
+            point(20., 20.),
+            point(30., 30.),
+            LineExtent::HalfLine,
+            point(0., 10.),
+            point(10., 0.),
+            LineExtent::Segment,
+        );
+        assert!(away.is_none());
+        let towards = line_intersection(
+            point(20., 20.),
+            point(10., 10.),
+            LineExtent::HalfLine,
+            point(0., 10.),
+            point(10., 0.),
+            LineExtent::Segment,
+        )
+        .unwrap();
+        assert_close(towards.x, 5., 1e-4);
+        assert_close(towards.y, 5., 1e-4);
+
+        // Only the circle crossing ahead of the start of the half-line is kept
+        let ahead = line_circle_intersections(
+            point(0., 0.),
+            point(1., 0.),
+            LineExtent::HalfLine,
+            point(0., 0.),
+            5.,
+        );
+        assert_eq!(ahead.len(), 1);
+        assert_close(ahead[0].x, 5., 1e-4);
+    }
+
+    #[test]
+    fn tangent_line_touches_circle_once() {
+        let touching = line_circle_intersections(
+            point(-10., 5.),
+            point(10., 5.),
+            LineExtent::Line,
+            point(0., 0.),
+            5.,
+        );
+        assert_eq!(touching.len(), 1);
+        assert_close(touching[0].x, 0., 1e-2);
+        assert_close(touching[0].y, 5., 1e-4);
+
+        let crossing = line_circle_intersections(
+            point(-10., 0.),
+            point(10., 0.),
+            LineExtent::Line,
+            point(0., 0.),
+            5.,
+        );
+        assert_eq!(crossing.len(), 2);
+        let missing = line_circle_intersections(
+            point(-10., 6.),
+            point(10., 6.),
+            LineExtent::Line,
+            point(0., 0.),
+            5.,
+        );
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn circle_intersections_count() {
+        // Concentric circles never intersect, even with the same radius
+        assert!(circle_intersections(point(0., 0.), 5., point(0., 0.), 3.).is_empty());
+        assert!(circle_intersections(point(0., 0.), 5., point(0., 0.), 5.).is_empty());
+        // One circle inside the other
+        assert!(circle_intersections(point(0., 0.), 5., point(1., 0.), 2.).is_empty());
+        // Too far apart
+        assert!(circle_intersections(point(0., 0.), 5., point(20., 0.), 5.).is_empty());
+
+        let touching = circle_intersections(point(0., 0.), 5., point(10., 0.), 5.);
+        assert_eq!(touching.len(), 1);
+        assert_close(touching[0].x, 5., 1e-4);
+        assert_close(touching[0].y, 0., 1e-4);
+
+        let crossing = circle_intersections(point(0., 0.), 5., point(6., 0.), 5.);
+        assert_eq!(crossing.len(), 2);
+        for p in crossing {
+            assert_close(p.x, 3., 1e-4);
+            assert_close(p.y.abs(), 4., 1e-4);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use image::ImageError;
 use slint::{Image, Rgba8Pixel, SharedPixelBuffer};
 
 #[derive(Clone, Debug)]
@@ -8,15 +9,24 @@ pub struct BackgroundRenderer {
 }
 
 impl BackgroundRenderer {
-    pub fn new(background_file: &str) -> BackgroundRenderer {
+    /// Open the background image from disk
+    ///
+    /// # Returns
+    ///
+    /// The renderer, or an error if the file cannot be read or decoded
+    pub fn new(background_file: &str) -> Result<BackgroundRenderer, ImageError> {
         // open background image from disk and add to pixel buffer
-        let image_data = slint::Image::load_from_path(std::path::Path::new(background_file)).unwrap();
+        let image_data = image::open(background_file)?.to_rgba8();
 
-        BackgroundRenderer {
-            image_height: image_data.size().height,
-            image_width: image_data.size().height,
-            bg_pixel_buffer :image_data.to_rgba8_premultiplied().unwrap(),
-        }
+        Ok(BackgroundRenderer {
+            image_height: image_data.height(),
+            image_width: image_data.width(),
+            bg_pixel_buffer: SharedPixelBuffer::clone_from_slice(
+                image_data.as_raw(),
+                image_data.width(),
+                image_data.height(),
+            ),
+        })
     }
 
     /// Generate the background image
@@ -28,6 +38,6 @@ impl BackgroundRenderer {
         log::debug!("Entering render image");
         let pixel_buffer = self.bg_pixel_buffer.clone();
         log::debug!("Pixel buffer cloned");
-        Some(Image::from_rgba8_premultiplied(pixel_buffer))
+        Some(Image::from_rgba8(pixel_buffer))
     }
 }
@@ -0,0 +1,151 @@
+//! Parametric constructions
+//!
+//! A drawable derived from other drawables, e.g. a line parallel to another one, records
+//! the construction it was built from. When one of its parents moves, the drawable is
+//! built again from the new position of its parents, and when one of its parents is
+//! deleted, the drawable is deleted as well.
+
+use crate::math::Point;
+
+/// Position a construction goes through
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Anchor {
+    /// Position clicked on the map, away from any point
+    Fixed(Point),
+    /// A point drawable, followed when it moves
+    Drawable(i32),
+}
+
+impl Anchor {
+    /// Identifier of the point drawable the anchor follows, if any
+    pub fn parent(&self) -> Option<i32> {
+        match self {
+            Anchor::Fixed(_) => None,
+            Anchor::Drawable(id) => Some(*id),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Anchor::Fixed(point) => format!("({:.0}, {:.0})", point.x, point.y),
+            Anchor::Drawable(id) => format!("#{}", id),
+        }
+    }
+}
+
+/// How a drawable was built from other drawables
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Construction {
+    /// Line parallel to a line, going through an anchor
+    Parallel { line: i32, through: Anchor },
+    /// Line perpendicular to a line, going through an anchor
+    Perpendicular { line: i32, through: Anchor },
+    /// Perpendicular bisector of two anchors
+    Median { point1: Anchor, point2: Anchor },
+    /// Vertical line going through an anchor
+    Vertical { through: Anchor },
+    /// Horizontal line going through an anchor
+    Horizontal { through: Anchor },
+    /// One of the two tangents to a circle going through an anchor
+    Tangent {
+        circle: i32,
+        through: Anchor,
+        index: usize,
+    },
+    /// One of the two lines making an angle in degrees with a line, going through an anchor
+    FromAngle {
+        line: i32,
+        through: Anchor,
+        angle: f32,
+        index: usize,
+    },
+    /// One of the intersection points of two drawables, the one closest to its former
+    /// position once its parents move
+    Intersection {
+        first: i32,
+        second: i32,
+        index: usize,
+    },
+    /// Ellipse of two foci going through an anchor
+    EllipseFoci {
+        focus1: Anchor,
+        focus2: Anchor,
+        through: Anchor,
+    },
+}
+
+impl Construction {
+    /// Identifiers of the drawables the construction depends on
+    pub fn parents(&self) -> Vec<i32> {
+        match self {
+            Construction::Parallel { line, through }
+            | Construction::Perpendicular { line, through }
+            | Construction::FromAngle { line, through, .. } => {
+                std::iter::once(*line).chain(through.parent()).collect()
+            }
+            Construction::Tangent {
+                circle, through, ..
+            } => std::iter::once(*circle).chain(through.parent()).collect(),
+            Construction::Median { point1, point2 } => {
+                point1.parent().into_iter().chain(point2.parent()).collect()
+            }
+            Construction::Vertical { through } | Construction::Horizontal { through } => {
+                through.parent().into_iter().collect()
+            }
+            Construction::Intersection { first, second, .. } => vec![*first, *second],
+            Construction::EllipseFoci {
+                focus1,
+                focus2,
+                through,
+            } => focus1
+                .parent()
+                .into_iter()
+                .chain(focus2.parent())
+                .chain(through.parent())
+                .collect(),
+        }
+    }
+
+    /// Short description of the construction, displayed in the object list
+    pub fn describe(&self) -> String {
+        match self {
+            Construction::Parallel { line, through } => {
+                format!("parallel to #{} through {}", line, through.describe())
+            }
+            Construction::Perpendicular { line, through } => {
+                format!("perpendicular to #{} through {}", line, through.describe())
+            }
+            Construction::Median { point1, point2 } => format!(
+                "median of {} and {}",
+                point1.describe(),
+                point2.describe()
+            ),
+            Construction::Vertical { through } => format!("vertical through {}", through.describe()),
+            Construction::Horizontal { through } => {
+                format!("horizontal through {}", through.describe())
+            }
+            Construction::Tangent {
+                circle, through, ..
+            } => format!("tangent to #{} through {}", circle, through.describe()),
+            Construction::FromAngle {
+                line,
+                through,
+                angle,
+                ..
+            } => format!("{}° from #{} through {}", angle, line, through.describe()),
+            Construction::Intersection { first, second, .. } => {
+                format!("intersection of #{} and #{}", first, second)
+            }
+            Construction::EllipseFoci {
+                focus1,
+                focus2,
+                through,
+            } => format!(
+                "foci {} and {} through {}",
+                focus1.describe(),
+                focus2.describe(),
+                through.describe()
+            ),
+        }
+    }
+}
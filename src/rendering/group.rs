@@ -0,0 +1,39 @@
+//! Annotation groups
+//!
+//! Drawables may belong to a named group, e.g. one per hypothesis built on the map. A
+//! group can be hidden, which removes its drawables from the map, or locked, which
+//! protects its drawables against edits and deletion.
+
+/// A named group of drawables
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Group {
+    pub id: i32,
+    pub name: String,
+    /// Whether the drawables of the group are shown on the map
+    pub visible: bool,
+    /// Whether the drawables of the group are protected against edits
+    pub locked: bool,
+}
+
+impl Group {
+    /// Label of the group in the group list
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of drawables in the group
+    /// * `current` - Whether new drawables are added to the group
+    pub fn label(&self, count: usize, current: bool) -> String {
+        let mut label = format!("{} ({} objects", self.name, count);
+        if !self.visible {
+            label.push_str(", hidden");
+        }
+        if self.locked {
+            label.push_str(", locked");
+        }
+        label.push(')');
+        if current {
+            label = format!("* {}", label);
+        }
+        label
+    }
+}
@@ -1,3 +1,4 @@
+use image::error::{ImageError, ParameterError, ParameterErrorKind};
 use slint::{Image, Rgba8Pixel, SharedPixelBuffer, SharedString};
 
 use crate::{utils::IdGenerator, LayerDrawable};
@@ -9,11 +10,21 @@ pub struct LayerRenderer {
 }
 
 impl LayerDrawable {
-    pub fn new(id: i32, file: &str, x: i32, y: i32, transparency: f32, m_per_px: f32) -> LayerDrawable {
+    pub fn new(
+        id: i32,
+        file: &str,
+        x: i32,
+        y: i32,
+        transparency: f32,
+        m_per_px: f32,
+    ) -> Result<LayerDrawable, ImageError> {
         let path = std::path::Path::new(file);
-        let filename = path.file_name().unwrap().to_str().unwrap();
-        let data = LayerDrawable::redraw(file, transparency);
-        LayerDrawable {
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_else(|| path.to_string_lossy());
+        let data = LayerDrawable::redraw(file, transparency)?;
+        Ok(LayerDrawable {
             id,
             x: x as f32,
             y: y as f32,
@@ -21,12 +32,17 @@ impl LayerDrawable {
             data,
             transparency,
             file: SharedString::from(file),
-            name: SharedString::from(filename),
-        }
+            name: SharedString::from(filename.as_ref()),
+        })
     }
 
-    pub fn redraw(file: &str, transparency: f32) -> Image {
-        let image = image::open(file).unwrap();
+    /// Load the layer image from disk and apply its transparency
+    ///
+    /// # Returns
+    ///
+    /// The layer image, or an error if the file cannot be read or decoded
+    pub fn redraw(file: &str, transparency: f32) -> Result<Image, ImageError> {
+        let image = image::open(file)?;
         let image = image.to_rgba8();
         let image_width = image.width();
         let image_height = image.height();
@@ -34,7 +50,7 @@ impl LayerDrawable {
 
         let map_pixmap =
             tiny_skia::PixmapMut::from_bytes(image_data.as_mut(), image_width, image_height)
-                .unwrap();
+                .ok_or_else(empty_image_error)?;
 
         let mut pixel_buffer = SharedPixelBuffer::<Rgba8Pixel>::new(image_width, image_height);
 
@@ -43,7 +59,7 @@ impl LayerDrawable {
             image_width,
             image_height,
         )
-        .unwrap();
+        .ok_or_else(empty_image_error)?;
 
         let paint = tiny_skia::PixmapPaint {
             opacity: transparency,
@@ -51,10 +67,17 @@ impl LayerDrawable {
             quality: tiny_skia::FilterQuality::Nearest,
         };
         pixmap.draw_pixmap(0, 0, map_pixmap.as_ref(), &paint, Default::default(), None);
-        Image::from_rgba8_premultiplied(pixel_buffer)
+        Ok(Image::from_rgba8_premultiplied(pixel_buffer))
     }
 }
 
+/// Error returned for images without any pixel
+fn empty_image_error() -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(
+        ParameterErrorKind::DimensionMismatch,
+    ))
+}
+
 impl LayerRenderer {
     pub fn new() -> LayerRenderer {
         LayerRenderer {
@@ -74,7 +97,18 @@ impl LayerRenderer {
     /// * `x` - The x-coordinate of the layer
     /// * `y` - The y-coordinate of the layer
     /// * `transparency` - The transparency of the layer
-    pub fn add_layer(&mut self, file: &str, x: i32, y: i32, transparency: f32, m_per_px: f32) {
+    ///
+    /// # Returns
+    ///
+    /// An error if the image file cannot be loaded, in which case no layer is added
+    pub fn add_layer(
+        &mut self,
+        file: &str,
+        x: i32,
+        y: i32,
+        transparency: f32,
+        m_per_px: f32,
+    ) -> Result<(), ImageError> {
         let layer = LayerDrawable::new(
             self.entity_id_generator.get_id(),
             file,
@@ -82,7 +116,66 @@ impl LayerRenderer {
             y,
             transparency,
             m_per_px,
-        );
+        )?;
         self.layers.push(layer);
+        Ok(())
+    }
+    /// Removes a layer from the map by its identifier
+    ///
+    /// # Returns
+    ///
+    /// The removed layer, if it exists
+    pub fn remove_layer(&mut self, id: i32) -> Option<LayerDrawable> {
+        let index = self.layers.iter().position(|layer| layer.id == id)?;
+        Some(self.layers.remove(index))
+    }
+
+    /// Insert an existing layer back on the map, keeping its identifier and position in the stack
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The layer to be inserted
+    pub fn insert_layer(&mut self, layer: LayerDrawable) {
+        self.entity_id_generator.reserve(layer.id);
+        let index = self
+            .layers
+            .iter()
+            .position(|l| l.id > layer.id)
+            .unwrap_or(self.layers.len());
+        self.layers.insert(index, layer);
+    }
+
+    /// Update the position, transparency and scale of a layer, e.g. after an edit in the UI
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The edited layer, identified by its identifier
+    pub fn update_layer(&mut self, layer: &LayerDrawable) {
+        if let Some(existing) = self.layers.iter_mut().find(|l| l.id == layer.id) {
+            existing.x = layer.x;
+            existing.y = layer.y;
+            existing.transparency = layer.transparency;
+            existing.m_per_px = layer.m_per_px;
+        }
+    }
+
+    /// Change the scale of a layer
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the layer
+    /// * `m_per_px` - The new number of meters per pixel of the layer image
+    pub fn set_scale(&mut self, id: i32, m_per_px: f32) {
+        if let Some(layer) = self.layers.iter_mut().find(|layer| layer.id == id) {
+            layer.m_per_px = m_per_px;
+        }
+    }
+
+    /// Replace all layers with the given ones, keeping their identifiers
+    pub fn replace_layers(&mut self, layers: &[LayerDrawable]) {
+        self.layers = vec![];
+        for layer in layers {
+            self.insert_layer(layer.clone());
+        }
     }
 }
@@ -1,3 +1,8 @@
 pub mod background;
+pub mod construction;
+pub mod group;
 pub mod layer;
 pub mod overlay;
+pub mod style;
+pub mod text;
+pub mod tiles;
@@ -1,12 +1,24 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
 use slint::{Image, Rgba8Pixel, SharedPixelBuffer};
 
+use super::construction::{Anchor, Construction};
+use super::group::Group;
+use super::style::Style;
+use super::text;
+use super::tiles::TiledSurface;
 use crate::io::ProjectDrawable;
 use crate::math::{
-    distance, distance_to_half_line, distance_to_segment, find_line_extreme_coordinates,
-    perpendicular_distance, Point,
+    arc_points, bearing, bearing_in_sweep, circle_intersections, distance, distance_to_half_line,
+    distance_to_line_extent, distance_to_segment, ellipse_contains, ellipse_from_foci,
+    ellipse_points, find_line_extreme_coordinates, get_lines_from_angles,
+    line_circle_intersections, line_intersection, median_line, parallel_line,
+    perpendicular_distance, perpendicular_line, point_at_bearing, polygon_contains,
+    project_on_circle, project_on_line_extent, sweep, tangent_lines_to_circle, LineExtent, Point,
 };
 use crate::utils::IdGenerator;
-use crate::OverlayDrawable;
+use crate::{OverlayDrawable, OverlayTile};
 use log::debug;
 
 /// trait for circle
@@ -34,10 +46,17 @@ pub enum DrawableType {
     HalfLine,
     Line,
     Circle,
+    Polyline,
+    Polygon,
+    Label,
+    Arc,
+    Sector,
+    Annulus,
+    Ellipse,
 }
 
 /// Represents a color with red, green, and blue components
-#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -45,44 +64,649 @@ pub struct Color {
 }
 
 /// Represents a drawable object that can be rendered on the map
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Drawable {
     pub id: i32,
     pub object_type: DrawableType,
+    /// Position of a label, centered on its text
     pub point1: Point,
+    /// Target of the leader line of a label, equal to `point1` if the label has no leader
     pub point2: Point,
+    /// Vertices of a polyline or polygon, `point1` and `point2` being its first and last
+    /// vertices
+    pub points: Vec<Point>,
+    /// Whether the inside of a polygon is filled
+    pub fill: bool,
+    /// Text of a label, possibly on several lines
+    pub text: String,
+    /// Font size of a label, in pixels
+    pub font_size: f32,
+    /// Inner radius of an annulus, in pixels
+    pub inner_radius: f32,
+    /// Bearing an arc, sector or annulus starts at, in degrees clockwise from the top of
+    /// the map
+    pub start_angle: f32,
+    /// Bearing an arc, sector or annulus ends at, swept clockwise from `start_angle`, a
+    /// full turn when both are equal
+    pub end_angle: f32,
+    /// Length of the semi-axis of an ellipse perpendicular to the one from `point1`, its
+    /// center, to `point2`
+    pub semi_axis: f32,
+    /// Name displayed in the object list, empty if the drawable is not named
+    pub name: String,
+    /// Free-text note, possibly on several lines
+    pub note: String,
+    /// Tags the drawable is searched by, without duplicates
+    pub tags: Vec<String>,
+    /// Group the drawable belongs to, `None` if it is not grouped
+    pub group: Option<i32>,
+    /// Construction the drawable is built from, `None` for a drawable placed by hand
+    pub construction: Option<Construction>,
     pub color: Color,
     pub width: f32,
-    pub already_drawn: bool,
+    pub style: Style,
     pub listview_id: i32,
 }
 
+/// Distance under which a point is considered to lie on another drawable, in pixels
+const ON_DRAWABLE_TOLERANCE: f32 = 0.5;
+
+/// Distance under which a click is anchored to an existing point, in pixels
+const ANCHOR_TOLERANCE: f32 = 8.;
+
+/// Number of edges of the polygon an ellipse is approximated by to compute intersections
+const ELLIPSE_VERTICES: usize = 360;
+
+/// Elementary shape a drawable is made of, used to compute intersections
+#[derive(Clone, Copy, Debug)]
+enum Shape {
+    Point(Point),
+    Line(Point, Point, LineExtent),
+    Circle(Point, f32),
+    /// Arc of circle from its center, radius, start bearing and clockwise sweep
+    Arc(Point, f32, f32, f32),
+}
+
+/// Kind of geometry a click snaps to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapKind {
+    Point,
+    Endpoint,
+    Midpoint,
+    Center,
+    Intersection,
+    /// Closest point of the drawn part of a line or circle
+    Nearest,
+}
+
+impl SnapKind {
+    /// Name of the snap, displayed next to the cursor position
+    pub fn describe(&self) -> &'static str {
+        match self {
+            SnapKind::Point => "point",
+            SnapKind::Endpoint => "endpoint",
+            SnapKind::Midpoint => "midpoint",
+            SnapKind::Center => "center",
+            SnapKind::Intersection => "intersection",
+            SnapKind::Nearest => "on object",
+        }
+    }
+}
+
+/// Position a click snapped to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snap {
+    pub point: Point,
+    pub kind: SnapKind,
+}
+
+impl Shape {
+    /// Closest point of the drawn part of the shape
+    fn nearest(&self, point: Point) -> Point {
+        match *self {
+            Shape::Point(p) => p,
+            Shape::Line(p1, p2, extent) => project_on_line_extent(point, p1, p2, extent),
+            Shape::Circle(center, radius) => project_on_circle(point, center, radius),
+            Shape::Arc(center, radius, start, sweep) => {
+                if bearing_in_sweep(bearing(center, point), start, sweep) {
+                    return project_on_circle(point, center, radius);
+                }
+                let start_point = point_at_bearing(center, radius, start);
+                let end_point = point_at_bearing(center, radius, start + sweep);
+                if distance(point, start_point) <= distance(point, end_point) {
+                    start_point
+                } else {
+                    end_point
+                }
+            }
+        }
+    }
+
+    /// Circle an arc is part of, other shapes being kept as they are
+    fn full_circle(&self) -> Shape {
+        match *self {
+            Shape::Arc(center, radius, _, _) => Shape::Circle(center, radius),
+            shape => shape,
+        }
+    }
+
+    /// Whether a point of the circle an arc is part of lies on the arc itself
+    fn passes_through(&self, point: Point) -> bool {
+        match *self {
+            Shape::Arc(center, _, start, sweep) => {
+                bearing_in_sweep(bearing(center, point), start, sweep)
+            }
+            _ => true,
+        }
+    }
+
+    /// Distance from a point to the drawn part of the shape
+    fn distance(&self, point: Point) -> f32 {
+        match *self {
+            Shape::Point(p) => distance(point, p),
+            Shape::Line(p1, p2, extent) => distance_to_line_extent(point, p1, p2, extent),
+            Shape::Circle(center, radius) => (distance(point, center) - radius).abs(),
+            Shape::Arc(..) => distance(point, self.nearest(point)),
+        }
+    }
+
+    /// Intersection points of two shapes
+    fn intersections(&self, other: &Shape) -> Vec<Point> {
+        match (*self, *other) {
+            (Shape::Point(point), shape) | (shape, Shape::Point(point)) => {
+                if shape.distance(point) <= ON_DRAWABLE_TOLERANCE {
+                    vec![point]
+                } else {
+                    vec![]
+                }
+            }
+            (Shape::Arc(..), _) | (_, Shape::Arc(..)) => self
+                .full_circle()
+                .intersections(&other.full_circle())
+                .into_iter()
+                .filter(|point| self.passes_through(*point) && other.passes_through(*point))
+                .collect(),
+            (Shape::Line(p1, p2, extent1), Shape::Line(p3, p4, extent2)) => {
+                line_intersection(p1, p2, extent1, p3, p4, extent2)
+                    .into_iter()
+                    .collect()
+            }
+            (Shape::Line(p1, p2, extent), Shape::Circle(center, radius))
+            | (Shape::Circle(center, radius), Shape::Line(p1, p2, extent)) => {
+                line_circle_intersections(p1, p2, extent, center, radius)
+            }
+            (Shape::Circle(center1, radius1), Shape::Circle(center2, radius2)) => {
+                circle_intersections(center1, radius1, center2, radius2)
+            }
+        }
+    }
+}
+
+impl Drawable {
+    /// Label of the drawable in the object list, with its tags and the first line of its
+    /// note
+    pub fn label(&self) -> String {
+        let mut label = if self.object_type == DrawableType::Label && self.name.is_empty() {
+            format!("{} - {} (Label)", self.id, self.text.replace('\n', " "))
+        } else if self.name.is_empty() {
+            format!("{} - {:?}", self.id, self.object_type)
+        } else {
+            format!("{} - {} ({:?})", self.id, self.name, self.object_type)
+        };
+        if !self.tags.is_empty() {
+            label = format!("{} [{}]", label, self.tags.join(", "));
+        }
+        if let Some(construction) = &self.construction {
+            label = format!("{}, {}", label, construction.describe());
+        }
+        let note = self.note.lines().next().unwrap_or_default().trim();
+        if note.is_empty() {
+            label
+        } else {
+            format!("{}: {}", label, note)
+        }
+    }
+
+    /// Check whether the drawable matches a search query
+    ///
+    /// The query is split on whitespace, and each term must be found, ignoring case, in
+    /// the name, the text of a label, one of the tags or the type of the drawable. A term
+    /// starting with `#` only matches a whole tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The search query, matching every drawable when empty
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let name = self.name.to_lowercase();
+        let text = self.text.to_lowercase();
+        let object_type = format!("{:?}", self.object_type).to_lowercase();
+        let tags = self
+            .tags
+            .iter()
+            .map(|tag| tag.to_lowercase())
+            .collect::<Vec<String>>();
+        query
+            .split_whitespace()
+            .all(|term| match term.strip_prefix('#') {
+                Some(tag) => tags.iter().any(|t| t == tag),
+                None => {
+                    name.contains(term)
+                        || text.contains(term)
+                        || object_type.contains(term)
+                        || tags.iter().any(|t| t.contains(term))
+                }
+            })
+    }
+
+    /// Elementary shapes of the drawable, a polyline being made of segments
+    fn shapes(&self) -> Vec<Shape> {
+        match self.object_type {
+            DrawableType::Point => vec![Shape::Point(self.point1)],
+            DrawableType::Segment => {
+                vec![Shape::Line(self.point1, self.point2, LineExtent::Segment)]
+            }
+            DrawableType::HalfLine => {
+                vec![Shape::Line(self.point1, self.point2, LineExtent::HalfLine)]
+            }
+            DrawableType::Line => vec![Shape::Line(self.point1, self.point2, LineExtent::Line)],
+            DrawableType::Circle => vec![Shape::Circle(self.center(), self.radius())],
+            DrawableType::Polyline | DrawableType::Polygon => self
+                .edges()
+                .into_iter()
+                .map(|(p1, p2)| Shape::Line(p1, p2, LineExtent::Segment))
+                .collect(),
+            DrawableType::Label if self.has_leader() => vec![
+                Shape::Point(self.point1),
+                Shape::Line(self.point1, self.point2, LineExtent::Segment),
+            ],
+            DrawableType::Label => vec![Shape::Point(self.point1)],
+            DrawableType::Arc => vec![self.arc(self.radius())],
+            DrawableType::Sector | DrawableType::Annulus => {
+                let inner_radius = if self.object_type == DrawableType::Annulus {
+                    self.inner_radius
+                } else {
+                    0.
+                };
+                let mut shapes = vec![self.arc(self.radius())];
+                if inner_radius > 0. {
+                    shapes.push(self.arc(inner_radius));
+                }
+                if !self.is_full_turn() {
+                    for bearing in [self.start_angle, self.end_angle] {
+                        shapes.push(Shape::Line(
+                            point_at_bearing(self.center(), inner_radius, bearing),
+                            point_at_bearing(self.center(), self.radius(), bearing),
+                            LineExtent::Segment,
+                        ));
+                    }
+                }
+                shapes
+            }
+            DrawableType::Ellipse => {
+                let points = self.ellipse_points(ELLIPSE_VERTICES);
+                points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(p1, p2)| Shape::Line(*p1, *p2, LineExtent::Segment))
+                    .collect()
+            }
+        }
+    }
+
+    /// Vertices of the polygon approximating an ellipse, starting at `point2`
+    fn ellipse_points(&self, count: usize) -> Vec<Point> {
+        ellipse_points(self.point1, self.point2, self.semi_axis, count)
+    }
+
+    /// Arc of the drawable at a given radius, between its start and end bearings
+    fn arc(&self, radius: f32) -> Shape {
+        Shape::Arc(self.center(), radius, self.start_angle, self.sweep())
+    }
+
+    /// Clockwise sweep of an arc, sector or annulus, in degrees
+    pub fn sweep(&self) -> f32 {
+        sweep(self.start_angle, self.end_angle)
+    }
+
+    /// Whether an arc, sector or annulus goes all the way round its center
+    fn is_full_turn(&self) -> bool {
+        self.sweep() >= 360.
+    }
+
+    /// Point halfway along the arc of the drawable at a given radius
+    fn arc_middle(&self, radius: f32) -> Point {
+        point_at_bearing(self.center(), radius, self.start_angle + self.sweep() / 2.)
+    }
+
+    /// Whether a point is inside a sector, annulus or ellipse
+    fn contains(&self, point: Point) -> bool {
+        if self.object_type == DrawableType::Ellipse {
+            return ellipse_contains(point, self.point1, self.point2, self.semi_axis);
+        }
+        let inner_radius = if self.object_type == DrawableType::Annulus {
+            self.inner_radius
+        } else {
+            0.
+        };
+        let d = distance(self.center(), point);
+        d >= inner_radius
+            && d <= self.radius()
+            && bearing_in_sweep(
+                bearing(self.center(), point),
+                self.start_angle,
+                self.sweep(),
+            )
+    }
+
+    /// Outline of an arc, sector or annulus as polylines, to be closed for sectors and
+    /// annuli
+    fn outline(&self) -> Vec<Vec<Point>> {
+        let center = self.center();
+        let outer = arc_points(center, self.radius(), self.start_angle, self.sweep());
+        match self.object_type {
+            DrawableType::Sector if !self.is_full_turn() => {
+                vec![std::iter::once(center).chain(outer).collect()]
+            }
+            DrawableType::Annulus => {
+                let mut inner =
+                    arc_points(center, self.inner_radius, self.start_angle, self.sweep());
+                inner.reverse();
+                if self.is_full_turn() {
+                    vec![outer, inner]
+                } else {
+                    vec![outer.into_iter().chain(inner).collect()]
+                }
+            }
+            _ => vec![outer],
+        }
+    }
+
+    /// Whether a label is joined to its target by a leader line
+    pub fn has_leader(&self) -> bool {
+        self.object_type == DrawableType::Label && self.point1 != self.point2
+    }
+
+    /// Edges of a polyline or polygon, a polygon being closed by its last edge
+    fn edges(&self) -> Vec<(Point, Point)> {
+        let mut edges = self
+            .points
+            .windows(2)
+            .map(|edge| (edge[0], edge[1]))
+            .collect::<Vec<_>>();
+        if let (DrawableType::Polygon, Some(first), Some(last)) =
+            (self.object_type, self.points.first(), self.points.last())
+        {
+            edges.push((*last, *first));
+        }
+        edges
+    }
+
+    /// Distance from a point to the drawn part of the drawable
+    ///
+    /// The text box of a label is drawn as a whole, points inside it are at distance 0.
+    pub fn distance(&self, point: Point) -> f32 {
+        let distance = self
+            .shapes()
+            .iter()
+            .map(|shape| shape.distance(point))
+            .fold(f32::MAX, f32::min);
+        if self.object_type != DrawableType::Label {
+            return distance;
+        }
+        let (x, y, width, height) = label_box(self);
+        let dx = (x - point.x).max(point.x - (x + width)).max(0.);
+        let dy = (y - point.y).max(point.y - (y + height)).max(0.);
+        distance.min(dx.hypot(dy))
+    }
+
+    /// Remarkable points of the drawable a click can snap to
+    fn snap_points(&self) -> Vec<Snap> {
+        let snap = |point: Point, kind: SnapKind| Snap { point, kind };
+        let middle = |p1: Point, p2: Point| Point {
+            x: (p1.x + p2.x) / 2.,
+            y: (p1.y + p2.y) / 2.,
+        };
+        match self.object_type {
+            DrawableType::Point => vec![snap(self.point1, SnapKind::Point)],
+            DrawableType::Segment => vec![
+                snap(self.point1, SnapKind::Endpoint),
+                snap(self.point2, SnapKind::Endpoint),
+                snap(middle(self.point1, self.point2), SnapKind::Midpoint),
+            ],
+            // The other point of a half line is where it leaves the map
+            DrawableType::HalfLine => vec![snap(self.point1, SnapKind::Endpoint)],
+            DrawableType::Line => vec![],
+            DrawableType::Circle => vec![snap(self.center(), SnapKind::Center)],
+            DrawableType::Polyline | DrawableType::Polygon => self
+                .points
+                .iter()
+                .map(|point| snap(*point, SnapKind::Endpoint))
+                .chain(
+                    self.edges()
+                        .into_iter()
+                        .map(|(p1, p2)| snap(middle(p1, p2), SnapKind::Midpoint)),
+                )
+                .collect(),
+            DrawableType::Label if self.has_leader() => {
+                vec![snap(self.point2, SnapKind::Endpoint)]
+            }
+            DrawableType::Label => vec![],
+            DrawableType::Arc | DrawableType::Sector | DrawableType::Annulus => {
+                let mut snaps = vec![snap(self.center(), SnapKind::Center)];
+                let mut radii = vec![self.radius()];
+                if self.object_type == DrawableType::Annulus && self.inner_radius > 0. {
+                    radii.push(self.inner_radius);
+                }
+                for radius in radii {
+                    snaps.push(snap(self.arc_middle(radius), SnapKind::Midpoint));
+                    if !self.is_full_turn() {
+                        for bearing in [self.start_angle, self.end_angle] {
+                            let end = point_at_bearing(self.center(), radius, bearing);
+                            snaps.push(snap(end, SnapKind::Endpoint));
+                        }
+                    }
+                }
+                snaps
+            }
+            // The four vertices come first and last on the axes
+            DrawableType::Ellipse => std::iter::once(snap(self.center(), SnapKind::Center))
+                .chain(
+                    self.ellipse_points(4)
+                        .into_iter()
+                        .map(|point| snap(point, SnapKind::Endpoint)),
+                )
+                .collect(),
+        }
+    }
+
+    /// Points of the drawable which can be dragged to reshape it
+    ///
+    /// Constructed drawables have no handles, their geometry being given by their parents.
+    pub fn handles(&self) -> Vec<Point> {
+        if self.construction.is_some() {
+            return vec![];
+        }
+        match self.object_type {
+            DrawableType::Point => vec![self.point1],
+            DrawableType::Segment
+            | DrawableType::HalfLine
+            | DrawableType::Line
+            | DrawableType::Circle => vec![self.point1, self.point2],
+            DrawableType::Polyline | DrawableType::Polygon => self.points.clone(),
+            DrawableType::Label if self.has_leader() => vec![self.point1, self.point2],
+            DrawableType::Label => vec![self.point1],
+            DrawableType::Arc | DrawableType::Sector => {
+                vec![self.center(), self.arc_middle(self.radius())]
+            }
+            DrawableType::Annulus => vec![
+                self.center(),
+                self.arc_middle(self.radius()),
+                self.arc_middle(self.inner_radius),
+            ],
+            DrawableType::Ellipse => {
+                vec![self.center(), self.point2, self.ellipse_points(4)[1]]
+            }
+        }
+    }
+
+    /// Copy of the drawable with one of its handles moved
+    ///
+    /// Moving the center of a circle keeps its radius, moving its edge changes it. Moving
+    /// the edges of an arc, sector or annulus changes their radii and keeps their bearings.
+    /// Moving the center of an ellipse moves it as a whole, moving the end of one of its
+    /// semi-axes changes its length. A label without leader line keeps none when moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the handle, as returned by [`Drawable::handles`]
+    /// * `position` - The new position of the handle
+    pub fn with_handle_moved(&self, index: usize, position: Point) -> Drawable {
+        let mut drawable = self.clone();
+        match (self.object_type, index) {
+            (DrawableType::Point, _) => {
+                drawable.point1 = position;
+                drawable.point2 = position;
+            }
+            (
+                DrawableType::Circle
+                | DrawableType::Arc
+                | DrawableType::Sector
+                | DrawableType::Annulus,
+                0,
+            ) => {
+                drawable.point1 = position;
+                drawable.point2 = Point {
+                    x: position.x + self.radius(),
+                    y: position.y,
+                };
+            }
+            (DrawableType::Polyline | DrawableType::Polygon, _) => {
+                if let Some(point) = drawable.points.get_mut(index) {
+                    *point = position;
+                }
+                drawable.point1 = drawable.points.first().copied().unwrap_or(position);
+                drawable.point2 = drawable.points.last().copied().unwrap_or(position);
+            }
+            (DrawableType::Arc | DrawableType::Sector | DrawableType::Annulus, 1) => {
+                let radius = distance(self.center(), position);
+                drawable.point2 = Point {
+                    x: self.point1.x + radius,
+                    y: self.point1.y,
+                };
+                drawable.inner_radius = self.inner_radius.min(radius);
+            }
+            (DrawableType::Ellipse, 0) => {
+                return self.translated(position.x - self.point1.x, position.y - self.point1.y);
+            }
+            (DrawableType::Ellipse, 1) => drawable.point2 = position,
+            (DrawableType::Ellipse, _) => {
+                drawable.semi_axis = perpendicular_distance(position, self.point1, self.point2);
+            }
+            (DrawableType::Annulus, _) => {
+                drawable.inner_radius = distance(self.center(), position).min(self.radius());
+            }
+            (DrawableType::Label, 0) if !self.has_leader() => {
+                drawable.point1 = position;
+                drawable.point2 = position;
+            }
+            (_, 0) => drawable.point1 = position,
+            _ => drawable.point2 = position,
+        }
+        drawable
+    }
+
+    /// Copy of the drawable moved as a whole
+    ///
+    /// # Arguments
+    ///
+    /// * `dx` - The horizontal offset
+    /// * `dy` - The vertical offset
+    pub fn translated(&self, dx: f32, dy: f32) -> Drawable {
+        let translate = |point: Point| Point {
+            x: point.x + dx,
+            y: point.y + dy,
+        };
+        Drawable {
+            point1: translate(self.point1),
+            point2: translate(self.point2),
+            points: self.points.iter().copied().map(translate).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Compute the points where two drawables meet
+    ///
+    /// Segments and half-lines only intersect on their drawn part, and a point intersects
+    /// a drawable it lies on.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The other drawable
+    ///
+    /// # Returns
+    ///
+    /// The intersection points, without duplicates
+    pub fn intersections(&self, other: &Drawable) -> Vec<Point> {
+        let mut points: Vec<Point> = vec![];
+        for shape in self.shapes() {
+            for other_shape in other.shapes() {
+                for point in shape.intersections(&other_shape) {
+                    // Consecutive polyline edges share their vertices
+                    if points
+                        .iter()
+                        .all(|p| distance(*p, point) > ON_DRAWABLE_TOLERANCE)
+                    {
+                        points.push(point);
+                    }
+                }
+            }
+        }
+        points
+    }
+}
+
 pub struct OverlayRenderer {
     pub drawables: Vec<Drawable>,
-    pub drawable_images: Vec<OverlayDrawable>,
+    pub groups: Vec<Group>,
+    /// Tiles the visible drawables are composited into
+    surface: TiledSurface,
+    /// Drawables not matching the search query, rendered translucent
+    dimmed: HashSet<i32>,
+    /// Factor applied to the stroke widths when rendering, e.g. for them to keep their
+    /// width on screen whatever the zoom
+    width_scale: f32,
     entity_id_generator: IdGenerator,
+    group_id_generator: IdGenerator,
+    /// Group new drawables are added to, if any
+    current_group: Option<i32>,
     image_height: u32,
     image_width: u32,
-    is_overlay_discarded: bool,
     stroke_width: f32,
     color: Color,
+    style: Style,
 }
 
 impl OverlayRenderer {
     pub fn new(image_width: u32, image_height: u32) -> OverlayRenderer {
         OverlayRenderer {
             drawables: Vec::new(),
-            drawable_images: Vec::new(),
+            groups: Vec::new(),
+            surface: TiledSurface::new(image_width, image_height),
+            dimmed: HashSet::new(),
+            width_scale: 1.,
             entity_id_generator: IdGenerator::new(),
+            group_id_generator: IdGenerator::new(),
+            current_group: None,
             image_height,
             image_width,
-            is_overlay_discarded: true,
             stroke_width: 4.,
             color: Color {
                 r: 42,
                 g: 0,
                 b: 150,
             },
+            style: Style::default(),
         }
     }
 
@@ -90,40 +714,67 @@ impl OverlayRenderer {
         self.image_height = image_height;
         self.image_width = image_width;
         self.drawables = Vec::new();
-        self.drawable_images = Vec::new();
+        self.groups = Vec::new();
+        self.surface = TiledSurface::new(image_width, image_height);
+        self.dimmed = HashSet::new();
         self.entity_id_generator = IdGenerator::new();
-        self.is_overlay_discarded = true;
+        self.group_id_generator = IdGenerator::new();
+        self.current_group = None;
     }
 
-    pub fn restore_drawables(&mut self, drawables: Vec<ProjectDrawable>) { 
+    /// Retrieve the size of the map the overlay is drawn on, as width and height
+    pub fn image_size(&self) -> (u32, u32) {
+        (self.image_width, self.image_height)
+    }
 
+    /// Restore the drawables of a project, keeping their identifiers so that constructions
+    /// still refer to their parents
+    pub fn restore_drawables(&mut self, drawables: Vec<ProjectDrawable>) {
         self.drawables = Vec::new();
-        self.drawable_images = Vec::new();
+        self.surface.invalidate_all();
 
         for d in drawables {
             // set width and color
             self.set_width(d.width);
             self.set_color(d.color.r, d.color.g, d.color.b);
 
-            match d.object_type {
-                DrawableType::Point => {
-                    self.add_point(d.point1);
-                }
-                DrawableType::Segment => {
-                    self.add_segment(d.point1, d.point2);
-                }
-                DrawableType::HalfLine => {
-                    self.add_half_line(d.point1, d.point2);
-                }
-                DrawableType::Line => {
-                    self.add_line(d.point1, d.point2);
-                }
-                DrawableType::Circle => {
-                    self.add_circle(d.point1, distance(d.point1, d.point2));
-                }
-            }
+            self.insert_drawable(Drawable {
+                id: d.id,
+                object_type: d.object_type,
+                point1: d.point1,
+                point2: d.point2,
+                points: d.points,
+                fill: d.fill,
+                text: d.text,
+                font_size: d.font_size,
+                inner_radius: d.inner_radius,
+                start_angle: d.start_angle,
+                end_angle: d.end_angle,
+                semi_axis: d.semi_axis,
+                name: d.name,
+                note: d.note,
+                tags: d.tags,
+                group: d.group,
+                construction: d.construction,
+                color: d.color,
+                width: d.width,
+                style: d.style,
+                listview_id: 0,
+            });
+        }
+    }
+
+    /// Replace all drawables with the given ones, keeping their identifiers
+    ///
+    /// # Arguments
+    ///
+    /// * `drawables` - The drawables to be restored
+    pub fn replace_drawables(&mut self, drawables: &[Drawable]) {
+        self.drawables = Vec::new();
+        self.surface.invalidate_all();
+        for d in drawables {
+            self.insert_drawable(d.clone());
         }
-        self.is_overlay_discarded = true;
     }
 
     /// Set the width of the lines to be drawn
@@ -140,8 +791,9 @@ impl OverlayRenderer {
         };
     }
 
-    pub fn discard_overlay(&mut self) {
-        self.is_overlay_discarded = true;
+    /// Set the style of the drawables to be drawn
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
     }
 
     /// Add half line to the list of drawables
@@ -149,20 +801,36 @@ impl OverlayRenderer {
     /// * `point1` - The first point of the half line
     /// * `point2` - The second point of the half line
     pub fn add_half_line(&mut self, point1: Point, point2: Point) {
-        let (point3, point4) = find_line_extreme_coordinates(
+        let Some(point2) = self.half_line_across_map(point1, point2) else {
+            log::warn!("Half line does not cross the map, it is not drawn");
+            return;
+        };
+        self.push_drawable(DrawableType::HalfLine, point1, point2);
+    }
+
+    /// Extend a half line from its origin through a point up to the border of the map
+    ///
+    /// # Returns
+    ///
+    /// The point where the half line leaves the map
+    fn half_line_across_map(&self, point1: Point, point2: Point) -> Option<Point> {
+        let (point3, point4) = self.line_across_map(point1, point2)?;
+        // TODO : don't rely on distance but rather on the direction of the line compared to point2 position
+        let d1 = distance(point2, point3);
+        let d2 = distance(point2, point4);
+        Some(if d1 < d2 { point3 } else { point4 })
+    }
+
+    /// Extend a line through two points up to the borders of the map
+    fn line_across_map(&self, point1: Point, point2: Point) -> Option<(Point, Point)> {
+        find_line_extreme_coordinates(
             point1,
             point2,
             0.,
             self.image_width as f32,
             0.,
             self.image_height as f32,
-        );
-        // TODO : don't rely on distance but rather on the direction of the line compared to point2 position
-        let d1 = distance(point2, point3);
-        let d2 = distance(point2, point4);
-        let point2 = if d1 < d2 { point3 } else { point4 };
-        self.add_segment(point1, point2);
-        self.drawables.last_mut().unwrap().object_type = DrawableType::HalfLine;
+        )
     }
 
     /// Add line to the list of drawables
@@ -170,16 +838,11 @@ impl OverlayRenderer {
     /// * `point1` - The first point of the line
     /// * `point2` - The second point of the line
     pub fn add_line(&mut self, point1: Point, point2: Point) {
-        let (point1, point2) = find_line_extreme_coordinates(
-            point1,
-            point2,
-            0.,
-            self.image_width as f32,
-            0.,
-            self.image_height as f32,
-        );
-        self.add_segment(point1, point2);
-        self.drawables.last_mut().unwrap().object_type = DrawableType::Line;
+        let Some((point1, point2)) = self.line_across_map(point1, point2) else {
+            log::warn!("Line does not cross the map, it is not drawn");
+            return;
+        };
+        self.push_drawable(DrawableType::Line, point1, point2);
     }
 
     /// Add segment to the list of drawables
@@ -187,184 +850,1034 @@ impl OverlayRenderer {
     /// * `point1` - The first point of the segment
     /// * `point2` - The second point of the segment
     pub fn add_segment(&mut self, point1: Point, point2: Point) {
-        let size_x = ((point1.x - point2.x).abs() as u32).max(self.stroke_width as u32);
-        let size_y = ((point1.y - point2.y).abs() as u32).max(self.stroke_width as u32);
-        let corner_x = (point1.x.min(point2.x) - (self.stroke_width / 2.) ).max(0.);
-        let corner_y = (point1.y.min(point2.y) - (self.stroke_width / 2.) ).max(0.);
-
-        let local_point1 = Point {
-            x: point1.x - corner_x + (self.stroke_width / 2.) ,
-            y: point1.y - corner_y + (self.stroke_width / 2.),
+        self.push_drawable(DrawableType::Segment, point1, point2);
+    }
+
+    /// Add circle to the list of drawables
+    /// # Arguments
+    /// * `center` - The center of the circle
+    /// * `radius` - The radius of the circle
+    pub fn add_circle(&mut self, center: Point, radius: f32) {
+        self.push_drawable(
+            DrawableType::Circle,
+            center,
+            Point {
+                x: center.x + radius,
+                y: center.y,
+            },
+        );
+    }
+
+    /// Add point to the list of drawables
+    /// # Arguments
+    /// * `point` - The point to be added
+    pub fn add_point(&mut self, point: Point) {
+        self.push_drawable(DrawableType::Point, point, point);
+    }
+
+    /// Add polyline to the list of drawables
+    /// # Arguments
+    /// * `points` - The vertices of the polyline, at least two
+    /// * `name` - The name of the polyline, may be empty
+    pub fn add_polyline(&mut self, points: Vec<Point>, name: &str) {
+        let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+            log::warn!("Polyline without vertices, it is not drawn");
+            return;
         };
-        let local_point2 = Point {
-            x: point2.x - corner_x,
-            y: point2.y - corner_y,
+        if points.len() < 2 {
+            log::warn!("Polyline with a single vertex, it is not drawn");
+            return;
+        }
+        self.push(Drawable {
+            object_type: DrawableType::Polyline,
+            point1: first,
+            point2: last,
+            points,
+            name: String::from(name),
+            ..Default::default()
+        });
+    }
+
+    /// Add polygon to the list of drawables
+    /// # Arguments
+    /// * `points` - The vertices of the polygon, at least three, the last one being
+    ///   implicitly joined to the first one
+    /// * `fill` - Whether the inside of the polygon is filled
+    pub fn add_polygon(&mut self, points: Vec<Point>, fill: bool) {
+        let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+            log::warn!("Polygon without vertices, it is not drawn");
+            return;
         };
+        if points.len() < 3 {
+            log::warn!("Polygon with less than three vertices, it is not drawn");
+            return;
+        }
+        self.push(Drawable {
+            object_type: DrawableType::Polygon,
+            point1: first,
+            point2: last,
+            points,
+            fill,
+            ..Default::default()
+        });
+    }
+
+    /// Add arc of circle to the list of drawables
+    /// # Arguments
+    /// * `center` - The center of the arc
+    /// * `radius` - The radius of the arc
+    /// * `start_angle` - The bearing the arc starts at, in degrees clockwise from the top
+    /// * `end_angle` - The bearing the arc ends at, clockwise from the start one
+    pub fn add_arc(&mut self, center: Point, radius: f32, start_angle: f32, end_angle: f32) {
+        self.push(Drawable {
+            object_type: DrawableType::Arc,
+            point1: center,
+            point2: Point {
+                x: center.x + radius,
+                y: center.y,
+            },
+            start_angle,
+            end_angle,
+            ..Default::default()
+        });
+    }
+
+    /// Add circular sector to the list of drawables
+    /// # Arguments
+    /// * `center` - The center of the sector
+    /// * `radius` - The radius of the sector
+    /// * `start_angle` - The bearing the sector starts at, in degrees clockwise from the top
+    /// * `end_angle` - The bearing the sector ends at, clockwise from the start one
+    /// * `fill` - Whether the inside of the sector is filled
+    pub fn add_sector(
+        &mut self,
+        center: Point,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        fill: bool,
+    ) {
+        self.push(Drawable {
+            object_type: DrawableType::Sector,
+            point1: center,
+            point2: Point {
+                x: center.x + radius,
+                y: center.y,
+            },
+            start_angle,
+            end_angle,
+            fill,
+            ..Default::default()
+        });
+    }
+
+    /// Add annulus to the list of drawables
+    /// # Arguments
+    /// * `center` - The center of the annulus
+    /// * `inner_radius` - The radius of the inner edge
+    /// * `radius` - The radius of the outer edge
+    /// * `start_angle` - The bearing the annulus starts at, in degrees clockwise from the top
+    /// * `end_angle` - The bearing the annulus ends at, all the way round when equal to the
+    ///   start one
+    /// * `fill` - Whether the inside of the annulus is filled
+    pub fn add_annulus(
+        &mut self,
+        center: Point,
+        inner_radius: f32,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        fill: bool,
+    ) {
+        let (inner_radius, radius) = (inner_radius.min(radius), inner_radius.max(radius));
+        self.push(Drawable {
+            object_type: DrawableType::Annulus,
+            point1: center,
+            point2: Point {
+                x: center.x + radius,
+                y: center.y,
+            },
+            inner_radius,
+            start_angle,
+            end_angle,
+            fill,
+            ..Default::default()
+        });
+    }
+
+    /// Add ellipse to the list of drawables
+    /// # Arguments
+    /// * `center` - The center of the ellipse
+    /// * `axis_end` - The end of one of its semi-axes, giving its rotation
+    /// * `semi_axis` - The length of the other semi-axis
+    /// * `fill` - Whether the inside of the ellipse is filled
+    pub fn add_ellipse(&mut self, center: Point, axis_end: Point, semi_axis: f32, fill: bool) {
+        self.push(Drawable {
+            object_type: DrawableType::Ellipse,
+            point1: center,
+            point2: axis_end,
+            semi_axis,
+            fill,
+            ..Default::default()
+        });
+    }
 
-        let mut pixel_buffer = SharedPixelBuffer::<Rgba8Pixel>::new(size_x, size_y);
-        let mut pixmap =
-            tiny_skia::PixmapMut::from_bytes(pixel_buffer.make_mut_bytes(), size_x, size_y)
-                .unwrap();
+    /// Add label to the list of drawables
+    /// # Arguments
+    /// * `position` - The center of the text
+    /// * `target` - The point the leader line of the label goes to, `None` for no leader
+    /// * `text` - The text of the label, possibly on several lines
+    /// * `font_size` - The font size, in pixels
+    pub fn add_label(
+        &mut self,
+        position: Point,
+        target: Option<Point>,
+        text: &str,
+        font_size: f32,
+    ) {
+        if text.trim().is_empty() {
+            log::warn!("Label without text, it is not drawn");
+            return;
+        }
+        self.push(Drawable {
+            object_type: DrawableType::Label,
+            point1: position,
+            point2: target.unwrap_or(position),
+            text: String::from(text),
+            font_size,
+            ..Default::default()
+        });
+    }
+
+    /// Rasterize a drawable which is not on the map yet, with the current color and width
+    ///
+    /// Used to show a polyline or polygon while its vertices are being clicked.
+    pub fn preview(&self, object_type: DrawableType, points: &[Point]) -> OverlayDrawable {
+        rasterize(&Drawable {
+            object_type,
+            point1: points.first().copied().unwrap_or_default(),
+            point2: points.last().copied().unwrap_or_default(),
+            points: points.to_vec(),
+            color: self.color,
+            width: self.stroke_width * self.width_scale,
+            style: self.style,
+            ..Default::default()
+        })
+    }
 
-        let mut paint = tiny_skia::Paint::default();
-        paint.set_color_rgba8(self.color.r, self.color.g, self.color.b, 255);
-        paint.anti_alias = true;
+    /// Create a new drawable with the current color and width, and add it to the map
+    ///
+    /// # Arguments
+    ///
+    /// * `object_type` - The type of the drawable
+    /// * `point1` - The first defining point of the drawable
+    /// * `point2` - The second defining point of the drawable
+    fn push_drawable(&mut self, object_type: DrawableType, point1: Point, point2: Point) {
+        self.push(Drawable {
+            object_type,
+            point1,
+            point2,
+            ..Default::default()
+        });
+    }
 
-        let stroke = tiny_skia::Stroke {
+    /// Give a new identifier, the current color, width and style and the current group to a
+    /// drawable, and add it to the map
+    fn push(&mut self, drawable: Drawable) {
+        let d = Drawable {
+            id: self.entity_id_generator.get_id(),
+            color: self.color,
             width: self.stroke_width,
+            style: self.style,
+            group: self.current_group,
+            ..drawable
+        };
+        debug!("Adding {:?}", d);
+        self.surface.invalidate(self.drawable_area(&d));
+        self.drawables.push(d);
+    }
+
+    /// Snap a position to the geometry of the drawables
+    ///
+    /// Remarkable points (points, endpoints, midpoints, centers and intersections) are
+    /// preferred, the closest point of a nearby object being used otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The position to be snapped
+    /// * `tolerance` - The maximum distance to the snapped position, in pixels
+    ///
+    /// # Returns
+    ///
+    /// The snapped position, or `None` if there is no geometry within the tolerance
+    pub fn snap(&self, point: Point, tolerance: f32) -> Option<Snap> {
+        let nearby = self
+            .visible_drawables()
+            .filter(|d| d.distance(point) <= tolerance)
+            .collect::<Vec<&Drawable>>();
+
+        let mut remarkable = self
+            .visible_drawables()
+            .flat_map(|d| d.snap_points())
+            .collect::<Vec<Snap>>();
+        for (i, first) in nearby.iter().enumerate() {
+            for second in nearby.iter().skip(i + 1) {
+                remarkable.extend(first.intersections(second).into_iter().map(|point| Snap {
+                    point,
+                    kind: SnapKind::Intersection,
+                }));
+            }
+        }
+        let closest = |snaps: Vec<Snap>| {
+            snaps
+                .into_iter()
+                .map(|snap| (distance(snap.point, point), snap))
+                .filter(|(distance, _)| *distance <= tolerance)
+                .min_by(|(d1, _), (d2, _)| d1.total_cmp(d2))
+                .map(|(_, snap)| snap)
+        };
+
+        closest(remarkable).or_else(|| {
+            closest(
+                nearby
+                    .iter()
+                    .flat_map(|d| d.shapes())
+                    .map(|shape| Snap {
+                        point: shape.nearest(point),
+                        kind: SnapKind::Nearest,
+                    })
+                    .collect(),
+            )
+        })
+    }
+
+    /// Find a drawable by its identifier
+    pub fn drawable(&self, id: i32) -> Option<&Drawable> {
+        self.drawables.iter().find(|d| d.id == id)
+    }
+
+    /// Anchor for a click on the map, the closest point drawable if there is one nearby
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The clicked position
+    pub fn anchor_at(&self, point: Point) -> Anchor {
+        match self.closest_point(point) {
+            Some(closest) if distance(closest.point1, point) <= ANCHOR_TOLERANCE => {
+                Anchor::Drawable(closest.id)
+            }
+            _ => Anchor::Fixed(point),
+        }
+    }
+
+    /// Current position of an anchor, `None` if its point does not exist anymore
+    fn anchor_position(&self, anchor: Anchor) -> Option<Point> {
+        match anchor {
+            Anchor::Fixed(point) => Some(point),
+            Anchor::Drawable(id) => self
+                .drawable(id)
+                .filter(|d| d.object_type == DrawableType::Point)
+                .map(|d| d.point1),
+        }
+    }
+
+    /// Compute the geometry of a construction from the current position of its parents
+    ///
+    /// # Arguments
+    ///
+    /// * `construction` - The construction of the drawable
+    /// * `previous` - The former position of the drawable when it is built again, the
+    ///   intersection point closest to it being kept instead of the one at the recorded index
+    ///
+    /// # Returns
+    ///
+    /// A drawable holding the type and geometry of the constructed drawable, along with the
+    /// updated construction of an intersection point built again, or `None` if a parent is
+    /// missing or if the construction has no solution, e.g. a tangent through a point inside
+    /// a circle
+    fn build(&self, construction: &Construction, previous: Option<Point>) -> Option<Drawable> {
+        let line = |id: i32| {
+            self.drawable(id).filter(|d| {
+                matches!(
+                    d.object_type,
+                    DrawableType::Line | DrawableType::Segment | DrawableType::HalfLine
+                )
+            })
+        };
+        let (point1, point2) = match *construction {
+            Construction::Parallel { line: id, through } => {
+                let line = line(id)?;
+                parallel_line(self.anchor_position(through)?, line.point1, line.point2)
+            }
+            Construction::Perpendicular { line: id, through } => {
+                let line = line(id)?;
+                perpendicular_line(self.anchor_position(through)?, line.point1, line.point2)
+            }
+            Construction::Median { point1, point2 } => median_line(
+                self.anchor_position(point1)?,
+                self.anchor_position(point2)?,
+            ),
+            Construction::Vertical { through } => {
+                let point = self.anchor_position(through)?;
+                (point, Point { x: point.x, y: point.y + 1. })
+            }
+            Construction::Horizontal { through } => {
+                let point = self.anchor_position(through)?;
+                (point, Point { x: point.x + 1., y: point.y })
+            }
+            Construction::Tangent {
+                circle,
+                through,
+                index,
+            } => {
+                let circle = self
+                    .drawable(circle)
+                    .filter(|d| d.object_type == DrawableType::Circle)?;
+                let (first, second) = tangent_lines_to_circle(
+                    self.anchor_position(through)?,
+                    circle.center(),
+                    circle.radius(),
+                )?;
+                if index == 0 {
+                    first
+                } else {
+                    second
+                }
+            }
+            Construction::FromAngle {
+                line: id,
+                through,
+                angle,
+                index,
+            } => {
+                let line = line(id)?;
+                let through = self.anchor_position(through)?;
+                let (point1, point2) = parallel_line(through, line.point1, line.point2);
+                let (first, second) = get_lines_from_angles(point1, point2, through, angle);
+                if index == 0 {
+                    first
+                } else {
+                    second
+                }
+            }
+            Construction::Intersection {
+                first,
+                second,
+                index,
+            } => {
+                let intersections = self
+                    .drawable(first)?
+                    .intersections(self.drawable(second)?);
+                // The order of the intersection points changes as the parents move
+                let index = match previous {
+                    Some(previous) => intersections
+                        .iter()
+                        .enumerate()
+                        .min_by(|(_, a), (_, b)| {
+                            distance(**a, previous).total_cmp(&distance(**b, previous))
+                        })
+                        .map(|(index, _)| index)?,
+                    None => index,
+                };
+                let point = *intersections.get(index)?;
+                return Some(Drawable {
+                    object_type: DrawableType::Point,
+                    point1: point,
+                    point2: point,
+                    construction: previous.map(|_| Construction::Intersection {
+                        first,
+                        second,
+                        index,
+                    }),
+                    ..Default::default()
+                });
+            }
+            Construction::EllipseFoci {
+                focus1,
+                focus2,
+                through,
+            } => {
+                let (center, axis_end, semi_axis) = ellipse_from_foci(
+                    self.anchor_position(focus1)?,
+                    self.anchor_position(focus2)?,
+                    self.anchor_position(through)?,
+                )?;
+                return Some(Drawable {
+                    object_type: DrawableType::Ellipse,
+                    point1: center,
+                    point2: axis_end,
+                    semi_axis,
+                    ..Default::default()
+                });
+            }
+        };
+        let (point1, point2) = self.line_across_map(point1, point2)?;
+        Some(Drawable {
+            object_type: DrawableType::Line,
+            point1,
+            point2,
             ..Default::default()
+        })
+    }
+
+    /// Build a drawable from a construction and add it to the map
+    ///
+    /// # Arguments
+    ///
+    /// * `construction` - The construction of the drawable
+    ///
+    /// # Returns
+    ///
+    /// `false` if the construction has no solution on the map, in which case nothing is added
+    pub fn add_construction(&mut self, construction: Construction) -> bool {
+        let Some(built) = self.build(&construction, None) else {
+            log::warn!("{} cannot be built, it is not drawn", construction.describe());
+            return false;
         };
-        let mut pb = tiny_skia::PathBuilder::new();
-        pb.move_to(local_point1.x as f32, local_point1.y as f32);
-        pb.line_to(local_point2.x as f32, local_point2.y as f32);
-        let path = pb.finish().unwrap();
-        pixmap.stroke_path(&path, &paint, &stroke, Default::default(), None);
+        self.push(Drawable {
+            construction: Some(construction),
+            ..built
+        });
+        true
+    }
+
+    /// Find the drawables depending on the given ones, directly or through other drawables
+    ///
+    /// # Returns
+    ///
+    /// The dependent drawables, parents coming before their children
+    pub fn dependents(&self, ids: &[i32]) -> Vec<Drawable> {
+        let mut ancestors = ids.to_vec();
+        let mut dependents = vec![];
+        // Children are always created after their parents, hence have greater identifiers
+        for drawable in self.drawables.iter() {
+            let Some(construction) = &drawable.construction else {
+                continue;
+            };
+            if construction
+                .parents()
+                .iter()
+                .any(|parent| ancestors.contains(parent))
+            {
+                ancestors.push(drawable.id);
+                dependents.push(drawable.clone());
+            }
+        }
+        dependents
+    }
+
+    /// Replace a drawable with another state of it, e.g. when undoing a move
+    ///
+    /// # Arguments
+    ///
+    /// * `drawable` - The new state of the drawable, identified by its identifier
+    pub fn replace_drawable(&mut self, drawable: Drawable) {
+        if let Some(index) = self.drawables.iter().position(|d| d.id == drawable.id) {
+            let area = self.drawable_area(&self.drawables[index]);
+            self.surface.invalidate(area);
+            self.surface.invalidate(self.drawable_area(&drawable));
+            self.drawables[index] = drawable;
+        }
+    }
+
+    /// Move a point and build again the drawables depending on it
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the point
+    /// * `position` - The new position of the point
+    ///
+    /// # Returns
+    ///
+    /// The moved point and the rebuilt drawables, before and after the move, or `None` if
+    /// there is no such point
+    pub fn move_point(&mut self, id: i32, position: Point) -> Option<(Vec<Drawable>, Vec<Drawable>)> {
+        let point = self
+            .drawable(id)
+            .filter(|d| d.object_type == DrawableType::Point)?
+            .clone();
+        self.edit_drawable(Drawable {
+            point1: position,
+            point2: position,
+            ..point
+        })
+    }
+
+    /// Change an existing drawable and build again the drawables depending on it
+    ///
+    /// Lines and half lines are extended up to the borders of the map. The geometry of a
+    /// constructed drawable is given by its construction, only its name and style can be
+    /// changed. Dependent drawables whose construction has no solution anymore keep their
+    /// former position.
+    ///
+    /// # Arguments
+    ///
+    /// * `drawable` - The new state of the drawable, identified by its identifier
+    ///
+    /// # Returns
+    ///
+    /// The edited drawable and the rebuilt drawables, before and after the edit, or `None`
+    /// if there is no such drawable or if the new line does not cross the map
+    pub fn edit_drawable(&mut self, drawable: Drawable) -> Option<(Vec<Drawable>, Vec<Drawable>)> {
+        let current = self.drawable(drawable.id)?.clone();
+        let (point1, point2) = if current.construction.is_some() {
+            (current.point1, current.point2)
+        } else {
+            match current.object_type {
+                DrawableType::Line => self.line_across_map(drawable.point1, drawable.point2)?,
+                DrawableType::HalfLine => (
+                    drawable.point1,
+                    self.half_line_across_map(drawable.point1, drawable.point2)?,
+                ),
+                _ => (drawable.point1, drawable.point2),
+            }
+        };
+        let mut before = vec![current.clone()];
+        self.replace_drawable(Drawable {
+            object_type: current.object_type,
+            point1,
+            point2,
+            construction: current.construction,
+            ..drawable
+        });
+
+        for dependent in self.dependents(&[drawable.id]) {
+            let Some(construction) = &dependent.construction else {
+                continue;
+            };
+            match self.build(construction, Some(dependent.point1)) {
+                Some(built) => {
+                    before.push(dependent.clone());
+                    let construction = built.construction.or(dependent.construction.clone());
+                    self.replace_drawable(Drawable {
+                        object_type: built.object_type,
+                        point1: built.point1,
+                        point2: built.point2,
+                        semi_axis: built.semi_axis,
+                        construction,
+                        ..dependent
+                    });
+                }
+                None => log::warn!("{} cannot be built anymore", construction.describe()),
+            }
+        }
+
+        let after = before
+            .iter()
+            .filter_map(|d| self.drawable(d.id).cloned())
+            .collect();
+        Some((before, after))
+    }
+
+    /// Insert an existing drawable back on the map, keeping its identifier
+    ///
+    /// Drawables are kept ordered by identifier, so that a drawable restored
+    /// after a deletion takes back its former place in the list.
+    ///
+    /// # Arguments
+    ///
+    /// * `drawable` - The drawable to be inserted
+    pub fn insert_drawable(&mut self, drawable: Drawable) {
+        self.entity_id_generator.reserve(drawable.id);
+        let index = self
+            .drawables
+            .iter()
+            .position(|d| d.id > drawable.id)
+            .unwrap_or(self.drawables.len());
+        self.surface.invalidate(self.drawable_area(&drawable));
+        self.drawables.insert(index, drawable);
+    }
+
+    /// Change the color, width and style of an existing drawable
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the drawable
+    /// * `color` - The new color
+    /// * `width` - The new width
+    /// * `style` - The new style
+    ///
+    /// # Returns
+    ///
+    /// The drawable as it was before the change, if it exists
+    pub fn set_drawable_style(
+        &mut self,
+        id: i32,
+        color: Color,
+        width: f32,
+        style: Style,
+    ) -> Option<Drawable> {
+        let index = self.drawables.iter().position(|d| d.id == id)?;
+        let previous = self.drawables[index].clone();
+        self.drawables[index].color = color;
+        self.drawables[index].width = width;
+        self.drawables[index].style = style;
+        self.surface.invalidate(self.drawable_area(&previous));
+        let area = self.drawable_area(&self.drawables[index]);
+        self.surface.invalidate(area);
+        Some(previous)
+    }
+
+    /// Removes a drawable object from the map by its identifier.
+    ///
+    /// # Returns
+    ///
+    /// The removed drawable, if it exists
+    pub fn remove_drawable(&mut self, id: i32) -> Option<Drawable> {
+        let index = self.drawables.iter().position(|d| d.id == id)?;
+        let area = self.drawable_area(&self.drawables[index]);
+        self.surface.invalidate(area);
+        Some(self.drawables.remove(index))
+    }
+
+    /// Remove drawables along with the drawables built from them, directly or not
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The identifiers of the drawables
+    ///
+    /// # Returns
+    ///
+    /// The removed drawables, the given ones coming first, then their dependents with
+    /// parents before their children
+    pub fn remove_with_dependents(&mut self, ids: &[i32]) -> Vec<Drawable> {
+        let dependents = self.dependents(ids);
+        ids.iter()
+            .copied()
+            .chain(dependents.iter().map(|d| d.id))
+            .filter_map(|id| self.remove_drawable(id))
+            .collect()
+    }
+
+    /// Drawables shown on the map, i.e. not in a hidden group
+    pub fn visible_drawables(&self) -> impl Iterator<Item = &Drawable> {
+        self.drawables.iter().filter(|d| self.is_visible(d))
+    }
+
+    /// Check whether a drawable is shown on the map, i.e. is not in a hidden group
+    pub fn is_visible(&self, drawable: &Drawable) -> bool {
+        drawable
+            .group
+            .and_then(|id| self.group(id))
+            .is_none_or(|group| group.visible)
+    }
+
+    /// Check whether a drawable is protected against edits, i.e. is in a locked group
+    pub fn is_locked(&self, drawable: &Drawable) -> bool {
+        drawable
+            .group
+            .and_then(|id| self.group(id))
+            .is_some_and(|group| group.locked)
+    }
+
+    /// Check whether editing a drawable would rebuild drawables protected against edits
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the drawable to be edited
+    pub fn has_locked_dependents(&self, id: i32) -> bool {
+        self.dependents(&[id]).iter().any(|d| self.is_locked(d))
+    }
 
-        let id = self.entity_id_generator.get_id();
+    /// Find a group by its identifier
+    pub fn group(&self, id: i32) -> Option<&Group> {
+        self.groups.iter().find(|g| g.id == id)
+    }
 
-        let d = Drawable {
-            id,
-            object_type: DrawableType::Segment,
-            point1,
-            point2,
-            color: self.color,
-            width: self.stroke_width,
-            ..Default::default()
-        };
-        self.drawables.push(d);
+    /// Group new drawables are added to, `None` if they are not grouped
+    pub fn current_group(&self) -> Option<i32> {
+        self.current_group
+    }
 
-        self.drawable_images.push(OverlayDrawable {
-            id,
-            data: Image::from_rgba8_premultiplied(pixel_buffer),
-            x: corner_x as f32,
-            y: corner_y as f32,
+    /// Set the group new drawables are added to
+    ///
+    /// New drawables are not grouped if the group does not exist, is hidden or is locked.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the group, `None` for new drawables not to be grouped
+    pub fn set_current_group(&mut self, id: Option<i32>) {
+        self.current_group = id.filter(|id| {
+            self.group(*id)
+                .is_some_and(|group| group.visible && !group.locked)
         });
-        debug!("Adding segment  {:?}", d);
-        debug!("Buffer size : {}x{}", size_x, size_y);
-        debug!("x, y : {}, {}", corner_x, corner_y);
     }
 
-    /// Add circle to the list of drawables
+    /// Create a new visible and unlocked group
+    ///
     /// # Arguments
-    /// * `center` - The center of the circle
-    /// * `radius` - The radius of the circle
-    pub fn add_circle(&mut self, center: Point, radius: f32) {
-        let size = ((radius + self.stroke_width) * 2.) as u32;
-        let mut pixel_buffer = SharedPixelBuffer::<Rgba8Pixel>::new(size, size);
-
-        let mut pixmap =
-            tiny_skia::PixmapMut::from_bytes(pixel_buffer.make_mut_bytes(), size, size).unwrap();
+    ///
+    /// * `name` - The name of the group
+    ///
+    /// # Returns
+    ///
+    /// The new group
+    pub fn add_group(&mut self, name: &str) -> Group {
+        let group = Group {
+            id: self.group_id_generator.get_id(),
+            name: String::from(name),
+            visible: true,
+            locked: false,
+        };
+        self.groups.push(group.clone());
+        group
+    }
 
-        let path = tiny_skia::PathBuilder::from_circle(
-            radius + self.stroke_width,
-            radius + self.stroke_width,
-            radius,
-        )
-        .unwrap();
-        let mut paint = tiny_skia::Paint::default();
-        paint.set_color_rgba8(self.color.r, self.color.g, self.color.b, 255);
-        paint.anti_alias = true;
+    /// Insert an existing group back, keeping its identifier and its place in the list
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The group to be inserted
+    pub fn insert_group(&mut self, group: Group) {
+        self.group_id_generator.reserve(group.id);
+        let index = self
+            .groups
+            .iter()
+            .position(|g| g.id > group.id)
+            .unwrap_or(self.groups.len());
+        let id = group.id;
+        self.groups.insert(index, group);
+        self.invalidate_group(id);
+    }
 
-        let stroke = tiny_skia::Stroke {
-            width: self.stroke_width,
-            ..Default::default()
-        };
-        pixmap.stroke_path(&path, &paint, &stroke, Default::default(), None);
+    /// Replace a group with another state of it, e.g. once renamed, hidden or locked
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The new state of the group, identified by its identifier
+    pub fn replace_group(&mut self, group: Group) {
+        if let Some(existing) = self.groups.iter_mut().find(|g| g.id == group.id) {
+            let shown_or_hidden = existing.visible != group.visible;
+            let id = group.id;
+            *existing = group;
+            if shown_or_hidden {
+                self.invalidate_group(id);
+            }
+        }
+        // New drawables are not added to a group once it is hidden or locked
+        self.set_current_group(self.current_group);
+    }
 
-        let id = self.entity_id_generator.get_id();
+    /// Remove a group, its drawables being expected to have been removed or moved
+    ///
+    /// # Returns
+    ///
+    /// The removed group, if it exists
+    pub fn remove_group(&mut self, id: i32) -> Option<Group> {
+        let index = self.groups.iter().position(|g| g.id == id)?;
+        if self.current_group == Some(id) {
+            self.current_group = None;
+        }
+        self.invalidate_group(id);
+        Some(self.groups.remove(index))
+    }
 
-        let d = Drawable {
-            id,
-            object_type: DrawableType::Circle,
-            point1: center,
-            point2: Point {
-                x: center.x + radius,
-                y: center.y,
-            },
-            color: self.color,
-            width: self.stroke_width,
-            ..Default::default()
-        };
-        debug!("Adding circle  {:?}", d);
-        debug!("Buffer size : {}", size);
-        debug!(
-            "x, y : {:.2}, {:.2}",
-            center.x  - radius - self.stroke_width,
-            center.y  - radius - self.stroke_width
-        );
-        self.drawables.push(d);
+    /// Replace all groups with the given ones, keeping their identifiers
+    ///
+    /// # Arguments
+    ///
+    /// * `groups` - The groups to be restored
+    pub fn replace_groups(&mut self, groups: &[Group]) {
+        self.groups = Vec::new();
+        self.current_group = None;
+        for group in groups {
+            self.insert_group(group.clone());
+        }
+        self.surface.invalidate_all();
+    }
 
-        self.drawable_images.push(OverlayDrawable {
-            id,
-            data: Image::from_rgba8_premultiplied(pixel_buffer),
-            x: center.x as f32 - radius - self.stroke_width,
-            y: center.y as f32 - radius - self.stroke_width,
-        });
+    /// Mark the tiles under the drawables of a group as dirty, e.g. once it is hidden
+    fn invalidate_group(&mut self, id: i32) {
+        for d in self.drawables.iter().filter(|d| d.group == Some(id)) {
+            self.surface.invalidate(self.drawable_area(d));
+        }
     }
 
-    /// Add point to the list of drawables
+    /// Drawables belonging to a group
+    ///
     /// # Arguments
-    /// * `point` - The point to be added
-    pub fn add_point(&mut self, point: Point) {
-        let size = (self.stroke_width * 2.) as u32;
-        let mut pixel_buffer = SharedPixelBuffer::<Rgba8Pixel>::new(size, size);
+    ///
+    /// * `id` - The identifier of the group
+    pub fn group_drawables(&self, id: i32) -> Vec<Drawable> {
+        self.drawables
+            .iter()
+            .filter(|d| d.group == Some(id))
+            .cloned()
+            .collect()
+    }
 
-        let mut pixmap =
-            tiny_skia::PixmapMut::from_bytes(pixel_buffer.make_mut_bytes(), size, size).unwrap();
+    /// Retrieve the list of drawables
+    pub fn get_drawables(&self) -> Vec<Drawable> {
+        self.drawables.clone()
+    }
 
-        let path =
-            tiny_skia::PathBuilder::from_circle(point.x as f32, point.y as f32, self.stroke_width)
-                .unwrap();
-        let mut paint = tiny_skia::Paint::default();
-        paint.set_color_rgba8(self.color.r, self.color.g, self.color.b, 255);
-        paint.anti_alias = true;
+    /// Set the drawables rendered translucent, e.g. as they do not match a search query
+    ///
+    /// Only the tiles under the drawables which are dimmed or no longer dimmed are
+    /// invalidated.
+    ///
+    /// # Arguments
+    ///
+    /// * `dimmed` - The identifiers of the dimmed drawables
+    pub fn set_dimmed(&mut self, dimmed: HashSet<i32>) {
+        for id in self.dimmed.symmetric_difference(&dimmed) {
+            if let Some(d) = self.drawables.iter().find(|d| d.id == *id) {
+                self.surface.invalidate(self.drawable_area(d));
+            }
+        }
+        self.dimmed = dimmed;
+    }
 
-        let stroke = tiny_skia::Stroke {
-            width: self.stroke_width,
-            ..Default::default()
-        };
-        pixmap.stroke_path(&path, &paint, &stroke, Default::default(), None);
+    /// Area of the map a drawable is rendered on, with the stroke widths of the view
+    fn drawable_area(&self, drawable: &Drawable) -> Option<tiny_skia::Rect> {
+        bounds(&with_width_scale(drawable, self.width_scale))
+    }
 
-        let id = self.entity_id_generator.get_id();
+    /// Set the part of the map in view and the resolution it is displayed with, for the
+    /// overlay to be rendered at that resolution
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The part of the map in view, in map pixels
+    /// * `scale` - The number of device pixels per map pixel
+    /// * `width_scale` - The factor applied to the stroke widths, e.g. for them to keep
+    ///   their width on screen whatever the zoom
+    pub fn set_view(&mut self, view: tiny_skia::Rect, scale: f32, width_scale: f32) {
+        if width_scale != self.width_scale {
+            self.width_scale = width_scale;
+            self.surface.invalidate_all();
+        }
+        self.surface.set_view(view, scale);
+    }
 
-        let d = Drawable {
-            id,
-            object_type: DrawableType::Circle,
-            point1: point,
-            point2: point,
-            color: self.color,
-            width: self.stroke_width,
-            ..Default::default()
-        };
-        self.drawables.push(d);
+    /// Indices of the tiles of the overlay surface in view, row by row
+    pub fn visible_tiles(&self) -> Vec<usize> {
+        self.surface.tiles()
+    }
 
-        self.drawable_images.push(OverlayDrawable {
-            id,
-            data: Image::from_rgba8_premultiplied(pixel_buffer),
-            x: point.x as f32 - self.stroke_width,
-            y: point.y as f32 - self.stroke_width,
-        });
-        debug!("Adding point  {:?}", d);
-        debug!("Buffer size : {}", size);
-        debug!(
-            "x, y : {:.2}, {:.2}",
-            point.x as f32 - self.stroke_width,
-            point.y as f32 - self.stroke_width
-        );
+    /// Tile of the overlay surface positioned on the map, as last rendered
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the tile, row by row
+    ///
+    /// # Returns
+    ///
+    /// The tile, its position and size being in map pixels
+    pub fn tile(&self, index: usize) -> OverlayTile {
+        let scale = self.surface.scale();
+        let area = self.surface.area(index);
+        let map_units = |value: Option<i32>| value.unwrap_or_default() as f32 / scale;
+        OverlayTile {
+            id: index as i32,
+            x: map_units(area.map(|area| area.x())),
+            y: map_units(area.map(|area| area.y())),
+            width: map_units(area.map(|area| area.width() as i32)),
+            height: map_units(area.map(|area| area.height() as i32)),
+            data: self.surface.tile(index),
+        }
     }
 
-    /// Removes a drawable object from the map by its identifier.
-    pub fn remove_drawable(&mut self, id: i32) {
-        self.drawables.retain(|d| d.id != id);
-        self.is_overlay_discarded = true;
+    /// Render again the tiles invalidated since the last call
+    ///
+    /// # Returns
+    ///
+    /// The indices of the rendered tiles
+    pub fn render_tiles(&mut self) -> Vec<usize> {
+        let dirty = self.surface.take_dirty();
+        if dirty.is_empty() {
+            return dirty;
+        }
+        let scale = self.surface.scale();
+        let tiles = dirty
+            .iter()
+            .map(|&index| {
+                let area = self.surface.area(index);
+                (area, area.and_then(|area| tile_rect(area, scale)))
+            })
+            .collect::<Vec<_>>();
+        // Only the drawables crossing a dirty tile are needed
+        let drawables = self
+            .visible_drawables()
+            .filter_map(|d| {
+                let d = with_width_scale(d, self.width_scale);
+                let area = bounds(&d)?;
+                let crosses = tiles
+                    .iter()
+                    .any(|(_, rect)| rect.is_some_and(|rect| area.intersect(&rect).is_some()));
+                crosses.then_some((d, area))
+            })
+            .collect::<Vec<_>>();
+        let images = tiles
+            .iter()
+            .map(|(area, rect)| match (area, rect) {
+                (Some(area), Some(rect)) => self.render_tile(*area, *rect, scale, &drawables),
+                _ => Image::default(),
+            })
+            .collect::<Vec<Image>>();
+        for (&index, image) in dirty.iter().zip(images) {
+            self.surface.set_tile(index, image);
+        }
+        debug!("{} overlay tiles rendered", dirty.len());
+        dirty
     }
 
-    /// Retrieve the list of drawables
-    pub fn get_drawables(&self) -> Vec<Drawable> {
-        self.drawables.clone()
+    /// Composite the drawables crossing a tile, in drawing order
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - The area of the tile, in device pixels
+    /// * `rect` - The part of the map under the tile, see [`tile_rect`]
+    /// * `scale` - The number of device pixels per map pixel
+    /// * `drawables` - The visible drawables with the stroke widths of the view, along with
+    ///   their area on the map
+    ///
+    /// # Returns
+    ///
+    /// The image of the tile, empty if no drawable crosses it
+    fn render_tile(
+        &self,
+        area: tiny_skia::IntRect,
+        rect: tiny_skia::Rect,
+        scale: f32,
+        drawables: &[(Cow<Drawable>, tiny_skia::Rect)],
+    ) -> Image {
+        let mut crossing = drawables
+            .iter()
+            .filter(|(_, bounds)| bounds.intersect(&rect).is_some())
+            .peekable();
+        if crossing.peek().is_none() {
+            return Image::default();
+        }
+
+        let mut pixel_buffer = SharedPixelBuffer::<Rgba8Pixel>::new(area.width(), area.height());
+        let Some(mut pixmap) = tiny_skia::PixmapMut::from_bytes(
+            pixel_buffer.make_mut_bytes(),
+            area.width(),
+            area.height(),
+        ) else {
+            return Image::default();
+        };
+        let transform = tiny_skia::Transform::from_row(
+            scale,
+            0.,
+            0.,
+            scale,
+            -area.x() as f32,
+            -area.y() as f32,
+        );
+        for (d, _) in crossing {
+            let opacity = if self.dimmed.contains(&d.id) {
+                DIMMED_OPACITY
+            } else {
+                1.
+            };
+            render(d, &mut pixmap, transform, opacity);
+        }
+        Image::from_rgba8_premultiplied(pixel_buffer)
     }
 
-    /// Set listview id for a drawable
+    /// Set listview id for a drawable, negative if it is not listed
     pub fn set_listview_id(&mut self, id: i32, listview_id: i32) {
         for draw in self.drawables.iter_mut() {
             if draw.id == id {
@@ -373,6 +1886,18 @@ impl OverlayRenderer {
         }
     }
 
+    /// Find the drawable shown at a row of the object list
+    ///
+    /// # Arguments
+    ///
+    /// * `listview_id` - The row in the object list, negative if no row is selected
+    pub fn listed_drawable(&self, listview_id: i32) -> Option<&Drawable> {
+        if listview_id < 0 {
+            return None;
+        }
+        self.drawables.iter().find(|d| d.listview_id == listview_id)
+    }
+
     /// Find closest circle to a specific point
     ///
     /// # Arguments
@@ -387,7 +1912,7 @@ impl OverlayRenderer {
         let mut min_distance = f32::MAX;
         let mut closest_circle = None;
 
-        for drawable in self.drawables.iter() {
+        for drawable in self.visible_drawables() {
             if drawable.object_type != DrawableType::Circle {
                 continue;
             }
@@ -419,11 +1944,11 @@ impl OverlayRenderer {
     ///
     /// The closest point to the specific point
     ///
-    pub fn _closest_point(&self, point: Point) -> Option<Drawable> {
+    pub fn closest_point(&self, point: Point) -> Option<Drawable> {
         let mut min_distance = f32::MAX;
         let mut closest_point = None;
 
-        for drawable in self.drawables.iter() {
+        for drawable in self.visible_drawables() {
             if drawable.object_type != DrawableType::Point {
                 continue;
             }
@@ -455,7 +1980,7 @@ impl OverlayRenderer {
         let mut min_distance = f32::MAX;
         let mut closest_object = None;
 
-        for drawable in self.drawables.iter() {
+        for drawable in self.visible_drawables() {
             let distance = match drawable.object_type {
                 DrawableType::Circle => {
                     let radius = distance(drawable.point1, drawable.point2);
@@ -473,6 +1998,32 @@ impl OverlayRenderer {
                 DrawableType::HalfLine => {
                     distance_to_half_line(point, drawable.point1, drawable.point2)
                 }
+                DrawableType::Polyline => drawable
+                    .points
+                    .windows(2)
+                    .map(|edge| distance_to_segment(point, edge[0], edge[1]))
+                    .fold(f32::MAX, f32::min),
+                // A click inside a filled polygon selects it
+                DrawableType::Polygon
+                    if drawable.fill && polygon_contains(point, &drawable.points) =>
+                {
+                    0.
+                }
+                DrawableType::Polygon => drawable
+                    .edges()
+                    .into_iter()
+                    .map(|(p1, p2)| distance_to_segment(point, p1, p2))
+                    .fold(f32::MAX, f32::min),
+                DrawableType::Label | DrawableType::Arc => drawable.distance(point),
+                // A click inside a filled sector, annulus or ellipse selects it
+                DrawableType::Sector | DrawableType::Annulus | DrawableType::Ellipse
+                    if drawable.fill && drawable.contains(point) =>
+                {
+                    0.
+                }
+                DrawableType::Sector | DrawableType::Annulus | DrawableType::Ellipse => {
+                    drawable.distance(point)
+                }
             };
 
             log::debug!("Id: {}, Distance: {}", drawable.id, distance);
@@ -500,7 +2051,7 @@ impl OverlayRenderer {
         let mut min_distance = f32::MAX;
         let mut closest_line = None;
 
-        for drawable in self.drawables.iter() {
+        for drawable in self.visible_drawables() {
             if drawable.object_type != DrawableType::Line
                 && drawable.object_type != DrawableType::Segment
                 && drawable.object_type != DrawableType::HalfLine
@@ -520,3 +2071,785 @@ impl OverlayRenderer {
         closest_line
     }
 }
+
+/// Opacity of the drawables not matching the search query of the object list
+const DIMMED_OPACITY: f32 = 0.2;
+
+/// Area of the map a drawable is rendered on, stroke, arrowheads and label background
+/// included
+///
+/// # Returns
+///
+/// The area, with a margin of one pixel for antialiasing, or `None` if the drawable
+/// covers no area
+fn bounds(drawable: &Drawable) -> Option<tiny_skia::Rect> {
+    let stroke_width = drawable.width;
+    let (min_x, min_y, max_x, max_y) = match drawable.object_type {
+        DrawableType::Point => {
+            let point = drawable.point1;
+            (
+                point.x - stroke_width,
+                point.y - stroke_width,
+                point.x + stroke_width,
+                point.y + stroke_width,
+            )
+        }
+        DrawableType::Segment | DrawableType::HalfLine | DrawableType::Line => {
+            let (start_arrow, end_arrow, arrow_length) = arrows(drawable);
+            let margin = if start_arrow || end_arrow {
+                arrow_length
+            } else {
+                stroke_width
+            };
+            let (point1, point2) = (drawable.point1, drawable.point2);
+            (
+                point1.x.min(point2.x) - margin,
+                point1.y.min(point2.y) - margin,
+                point1.x.max(point2.x) + margin,
+                point1.y.max(point2.y) + margin,
+            )
+        }
+        DrawableType::Circle => {
+            let center = drawable.center();
+            let half = drawable.radius() + stroke_width;
+            (
+                center.x - half,
+                center.y - half,
+                center.x + half,
+                center.y + half,
+            )
+        }
+        DrawableType::Polyline | DrawableType::Polygon => {
+            extent(std::slice::from_ref(&drawable.points), stroke_width)
+        }
+        DrawableType::Arc | DrawableType::Sector | DrawableType::Annulus => {
+            extent(&drawable.outline(), stroke_width)
+        }
+        DrawableType::Label => {
+            let (box_x, box_y, box_width, box_height) = label_box(drawable);
+            let (mut min_x, mut min_y) = (box_x, box_y);
+            let (mut max_x, mut max_y) = (box_x + box_width, box_y + box_height);
+            if drawable.has_leader() {
+                let dot_radius = leader_dot_radius(drawable);
+                min_x = min_x.min(drawable.point2.x - dot_radius);
+                min_y = min_y.min(drawable.point2.y - dot_radius);
+                max_x = max_x.max(drawable.point2.x + dot_radius);
+                max_y = max_y.max(drawable.point2.y + dot_radius);
+            }
+            (min_x, min_y, max_x, max_y)
+        }
+        DrawableType::Ellipse => {
+            let center = drawable.center();
+            let (half_x, half_y) = ellipse_half_size(drawable);
+            (
+                center.x - half_x,
+                center.y - half_y,
+                center.x + half_x,
+                center.y + half_y,
+            )
+        }
+    };
+    tiny_skia::Rect::from_ltrb(min_x - 1., min_y - 1., max_x + 1., max_y + 1.)
+}
+
+/// Part of the map under a tile, with a margin of a device pixel for antialiasing
+fn tile_rect(area: tiny_skia::IntRect, scale: f32) -> Option<tiny_skia::Rect> {
+    let margin = 1. / scale;
+    tiny_skia::Rect::from_ltrb(
+        area.left() as f32 / scale - margin,
+        area.top() as f32 / scale - margin,
+        area.right() as f32 / scale + margin,
+        area.bottom() as f32 / scale + margin,
+    )
+}
+
+/// A drawable with its stroke width multiplied, borrowed unchanged if the factor is one
+fn with_width_scale(drawable: &Drawable, width_scale: f32) -> Cow<'_, Drawable> {
+    if width_scale == 1. {
+        Cow::Borrowed(drawable)
+    } else {
+        Cow::Owned(Drawable {
+            width: drawable.width * width_scale,
+            ..drawable.clone()
+        })
+    }
+}
+
+/// Extent of polylines, as left, top, right and bottom, widened by the stroke width
+fn extent(paths: &[Vec<Point>], stroke_width: f32) -> (f32, f32, f32, f32) {
+    let points = || paths.iter().flatten();
+    (
+        points().map(|p| p.x).fold(f32::MAX, f32::min) - stroke_width,
+        points().map(|p| p.y).fold(f32::MAX, f32::min) - stroke_width,
+        points().map(|p| p.x).fold(f32::MIN, f32::max) + stroke_width,
+        points().map(|p| p.y).fold(f32::MIN, f32::max) + stroke_width,
+    )
+}
+
+/// Rasterize a drawable into an image positioned on the map, covering its area only
+///
+/// Used for previews, the drawables on the map being rendered into the tiles of the
+/// overlay surface.
+///
+/// # Arguments
+///
+/// * `drawable` - The drawable to be rasterized
+///
+/// # Returns
+///
+/// The overlay image of the drawable
+fn rasterize(drawable: &Drawable) -> OverlayDrawable {
+    let Some(area) = bounds(drawable).and_then(|bounds| bounds.round_out()) else {
+        return empty_drawable(drawable);
+    };
+    let mut pixel_buffer = SharedPixelBuffer::<Rgba8Pixel>::new(area.width(), area.height());
+    let Some(mut pixmap) = tiny_skia::PixmapMut::from_bytes(
+        pixel_buffer.make_mut_bytes(),
+        area.width(),
+        area.height(),
+    ) else {
+        return empty_drawable(drawable);
+    };
+    let transform = tiny_skia::Transform::from_translate(-area.x() as f32, -area.y() as f32);
+    render(drawable, &mut pixmap, transform, 1.);
+
+    debug!("Buffer size : {}x{}", area.width(), area.height());
+
+    OverlayDrawable {
+        id: drawable.id,
+        data: Image::from_rgba8_premultiplied(pixel_buffer),
+        x: area.x() as f32,
+        y: area.y() as f32,
+    }
+}
+
+/// Empty image for a drawable too small to be rasterized, e.g. a polyline without vertices
+fn empty_drawable(drawable: &Drawable) -> OverlayDrawable {
+    debug!("Drawable {} cannot be rasterized", drawable.id);
+    OverlayDrawable {
+        id: drawable.id,
+        data: Image::default(),
+        x: drawable.point1.x,
+        y: drawable.point1.y,
+    }
+}
+
+/// Composite a drawable onto a pixmap covering a part of the map
+///
+/// A translucent drawable is drawn opaque on a scratch pixmap first, so that its
+/// overlapping parts, e.g. the stroke and the arrowheads of a segment, do not add up.
+///
+/// # Arguments
+///
+/// * `drawable` - The drawable to be rendered
+/// * `pixmap` - The pixmap
+/// * `transform` - The transform from map coordinates to pixmap coordinates
+/// * `opacity` - The opacity the drawable is composited with
+fn render(
+    drawable: &Drawable,
+    pixmap: &mut tiny_skia::PixmapMut,
+    transform: tiny_skia::Transform,
+    opacity: f32,
+) {
+    let opacity = match drawable.object_type {
+        DrawableType::Segment | DrawableType::HalfLine | DrawableType::Line => {
+            opacity * drawable.style.alpha as f32 / 255.
+        }
+        _ => opacity,
+    };
+    if opacity >= 1. {
+        draw(drawable, pixmap, transform);
+        return;
+    }
+    let Some(mut layer) = tiny_skia::Pixmap::new(pixmap.width(), pixmap.height()) else {
+        return;
+    };
+    draw(drawable, &mut layer.as_mut(), transform);
+    pixmap.draw_pixmap(
+        0,
+        0,
+        layer.as_ref(),
+        &tiny_skia::PixmapPaint {
+            opacity,
+            ..Default::default()
+        },
+        tiny_skia::Transform::identity(),
+        None,
+    );
+}
+
+/// Draw a drawable onto a pixmap covering a part of the map
+fn draw(drawable: &Drawable, pixmap: &mut tiny_skia::PixmapMut, transform: tiny_skia::Transform) {
+    match drawable.object_type {
+        DrawableType::Point => draw_point(drawable, pixmap, transform),
+        DrawableType::Segment | DrawableType::HalfLine | DrawableType::Line => {
+            draw_segment(drawable, pixmap, transform)
+        }
+        DrawableType::Circle => draw_circle(drawable, pixmap, transform),
+        DrawableType::Polyline | DrawableType::Polygon => draw_path(
+            drawable,
+            std::slice::from_ref(&drawable.points),
+            drawable.object_type == DrawableType::Polygon,
+            pixmap,
+            transform,
+        ),
+        DrawableType::Label => draw_label(drawable, pixmap, transform),
+        DrawableType::Arc | DrawableType::Sector | DrawableType::Annulus => draw_path(
+            drawable,
+            &drawable.outline(),
+            drawable.object_type != DrawableType::Arc,
+            pixmap,
+            transform,
+        ),
+        DrawableType::Ellipse => draw_ellipse(drawable, pixmap, transform),
+    }
+}
+
+/// Length of the arrowheads, relative to the stroke width
+const ARROW_LENGTH: f32 = 4.;
+
+/// Minimum length of the arrowheads, in pixels
+const MIN_ARROW_LENGTH: f32 = 10.;
+
+/// Arrowheads of a segment, half line or line
+///
+/// # Returns
+///
+/// Whether there is an arrowhead on the first and on the second point, and their length
+fn arrows(drawable: &Drawable) -> (bool, bool, f32) {
+    // Both ends of a line are on the borders of the map, and so is the end of a half line,
+    // which still shows its direction
+    let style = &drawable.style;
+    (
+        style.start_arrow && drawable.object_type != DrawableType::Line,
+        style.end_arrow && drawable.object_type != DrawableType::Line,
+        (ARROW_LENGTH * drawable.width).max(MIN_ARROW_LENGTH),
+    )
+}
+
+/// Build the triangle of an arrowhead
+///
+/// # Arguments
+///
+/// * `tip` - The point the arrow points to
+/// * `from` - Another point of the line, giving the direction of the arrow
+/// * `length` - The length of the arrowhead
+fn arrowhead(tip: Point, from: Point, length: f32) -> Option<tiny_skia::Path> {
+    let d = distance(tip, from);
+    if d == 0. {
+        return None;
+    }
+    let (ux, uy) = ((tip.x - from.x) / d, (tip.y - from.y) / d);
+    let base = Point {
+        x: tip.x - ux * length,
+        y: tip.y - uy * length,
+    };
+    let half_width = length / 2.;
+    let mut pb = tiny_skia::PathBuilder::new();
+    pb.move_to(tip.x, tip.y);
+    pb.line_to(base.x - uy * half_width, base.y + ux * half_width);
+    pb.line_to(base.x + uy * half_width, base.y - ux * half_width);
+    pb.close();
+    pb.finish()
+}
+
+/// Draw a segment, half line or line between its two points, with the arrowheads of its
+/// style for a segment or half line
+///
+/// The stroke and the arrowheads are drawn opaque, the opacity of the style being applied
+/// when the segment is composited.
+fn draw_segment(
+    drawable: &Drawable,
+    pixmap: &mut tiny_skia::PixmapMut,
+    transform: tiny_skia::Transform,
+) {
+    let point1 = drawable.point1;
+    let point2 = drawable.point2;
+    let style = &drawable.style;
+    let (start_arrow, end_arrow, arrow_length) = arrows(drawable);
+
+    // The stroke stops inside the arrowheads, so that its caps do not stick out of their tips
+    let (mut start, mut end) = (point1, point2);
+    let length = distance(point1, point2);
+    if length > 0. {
+        let back = arrow_length / 2. / length;
+        let toward = |from: Point, to: Point| Point {
+            x: from.x + (to.x - from.x) * back,
+            y: from.y + (to.y - from.y) * back,
+        };
+        if start_arrow {
+            start = toward(point1, point2);
+        }
+        if end_arrow {
+            end = toward(point2, point1);
+        }
+    }
+
+    let mut pb = tiny_skia::PathBuilder::new();
+    pb.move_to(start.x, start.y);
+    pb.line_to(end.x, end.y);
+    let Some(path) = pb.finish() else {
+        return;
+    };
+    let paint = Style {
+        alpha: 255,
+        ..*style
+    }
+    .stroke_paint(drawable.color);
+    pixmap.stroke_path(
+        &path,
+        &paint,
+        &style.stroke(drawable.width),
+        transform,
+        None,
+    );
+
+    let arrows = [(start_arrow, point1, point2), (end_arrow, point2, point1)];
+    for (_, tip, from) in arrows.into_iter().filter(|(shown, _, _)| *shown) {
+        if let Some(arrow) = arrowhead(tip, from, arrow_length) {
+            pixmap.fill_path(
+                &arrow,
+                &paint,
+                tiny_skia::FillRule::Winding,
+                transform,
+                None,
+            );
+        }
+    }
+}
+
+/// Draw polylines, or closed rings optionally filled with the even-odd rule
+///
+/// # Arguments
+///
+/// * `drawable` - The drawable, giving the color, width and fill
+/// * `paths` - The vertices of each polyline or ring
+/// * `closed` - Whether the paths are closed rings
+/// * `pixmap` - The pixmap covering a part of the map
+/// * `transform` - The transform from map coordinates to pixmap coordinates
+fn draw_path(
+    drawable: &Drawable,
+    paths: &[Vec<Point>],
+    closed: bool,
+    pixmap: &mut tiny_skia::PixmapMut,
+    transform: tiny_skia::Transform,
+) {
+    let mut pb = tiny_skia::PathBuilder::new();
+    for path in paths {
+        for (i, point) in path.iter().enumerate() {
+            if i == 0 {
+                pb.move_to(point.x, point.y);
+            } else {
+                pb.line_to(point.x, point.y);
+            }
+        }
+        if closed {
+            pb.close();
+        }
+    }
+    let Some(path) = pb.finish() else {
+        return;
+    };
+
+    if closed && drawable.fill {
+        pixmap.fill_path(
+            &path,
+            &drawable.style.fill_paint(drawable.color),
+            tiny_skia::FillRule::EvenOdd,
+            transform,
+            None,
+        );
+    }
+
+    pixmap.stroke_path(
+        &path,
+        &drawable.style.stroke_paint(drawable.color),
+        &drawable.style.stroke(drawable.width),
+        transform,
+        None,
+    );
+}
+
+/// Draw a circle from its center and edge point
+fn draw_circle(
+    drawable: &Drawable,
+    pixmap: &mut tiny_skia::PixmapMut,
+    transform: tiny_skia::Transform,
+) {
+    let center = drawable.center();
+    let Some(path) = tiny_skia::PathBuilder::from_circle(center.x, center.y, drawable.radius())
+    else {
+        return;
+    };
+
+    if drawable.fill {
+        pixmap.fill_path(
+            &path,
+            &drawable.style.fill_paint(drawable.color),
+            tiny_skia::FillRule::Winding,
+            transform,
+            None,
+        );
+    }
+
+    pixmap.stroke_path(
+        &path,
+        &drawable.style.stroke_paint(drawable.color),
+        &drawable.style.stroke(drawable.width),
+        transform,
+        None,
+    );
+}
+
+/// Draw a point as a small disc centered on its position
+fn draw_point(
+    drawable: &Drawable,
+    pixmap: &mut tiny_skia::PixmapMut,
+    transform: tiny_skia::Transform,
+) {
+    let point = drawable.point1;
+    let stroke_width = drawable.width;
+    let Some(path) = tiny_skia::PathBuilder::from_circle(point.x, point.y, stroke_width / 2.)
+    else {
+        return;
+    };
+
+    // A point is a disc, which is neither dashed nor capped
+    let stroke = tiny_skia::Stroke {
+        width: stroke_width,
+        ..Default::default()
+    };
+    pixmap.stroke_path(
+        &path,
+        &drawable.style.stroke_paint(drawable.color),
+        &stroke,
+        transform,
+        None,
+    );
+}
+
+/// Space between the text of a label and the edge of its background, in pixels
+const LABEL_PADDING: f32 = 3.;
+
+/// Opacity of the background of the labels, keeping them readable on any map
+const LABEL_BACKGROUND_ALPHA: u8 = 200;
+
+/// Background box of a label, centered on its first point
+///
+/// # Returns
+///
+/// The position of the top left corner of the box, its width and its height
+fn label_box(drawable: &Drawable) -> (f32, f32, f32, f32) {
+    let (text_width, text_height) = text::measure(&drawable.text, drawable.font_size);
+    let box_width = text_width + 2. * LABEL_PADDING;
+    let box_height = text_height + 2. * LABEL_PADDING;
+    (
+        drawable.point1.x - box_width / 2.,
+        drawable.point1.y - box_height / 2.,
+        box_width,
+        box_height,
+    )
+}
+
+/// Radius of the dot ending the leader line of a label on its target
+fn leader_dot_radius(drawable: &Drawable) -> f32 {
+    drawable.width.max(1.) * 1.5
+}
+
+/// Draw a label as its text on a light background, with its leader line if any
+///
+/// The transform is expected to be a uniform scaling followed by a translation, which the
+/// text is drawn with.
+fn draw_label(
+    drawable: &Drawable,
+    pixmap: &mut tiny_skia::PixmapMut,
+    transform: tiny_skia::Transform,
+) {
+    let (box_x, box_y, box_width, box_height) = label_box(drawable);
+
+    if drawable.has_leader() {
+        let mut pb = tiny_skia::PathBuilder::new();
+        pb.move_to(drawable.point1.x, drawable.point1.y);
+        pb.line_to(drawable.point2.x, drawable.point2.y);
+        if let Some(path) = pb.finish() {
+            pixmap.stroke_path(
+                &path,
+                &drawable.style.stroke_paint(drawable.color),
+                &drawable.style.stroke(drawable.width),
+                transform,
+                None,
+            );
+        }
+        if let Some(dot) = tiny_skia::PathBuilder::from_circle(
+            drawable.point2.x,
+            drawable.point2.y,
+            leader_dot_radius(drawable),
+        ) {
+            pixmap.fill_path(
+                &dot,
+                &drawable.style.stroke_paint(drawable.color),
+                tiny_skia::FillRule::Winding,
+                transform,
+                None,
+            );
+        }
+    }
+
+    // The background hides the leader line under the text
+    if let Some(rect) = tiny_skia::Rect::from_xywh(box_x, box_y, box_width, box_height) {
+        let mut background = tiny_skia::Paint::default();
+        background.set_color_rgba8(255, 255, 255, LABEL_BACKGROUND_ALPHA);
+        pixmap.fill_rect(rect, &background, transform, None);
+    }
+
+    let scale = transform.sx;
+    text::draw(
+        pixmap,
+        &drawable.text,
+        drawable.font_size * scale,
+        drawable.color,
+        (box_x + LABEL_PADDING) * scale + transform.tx,
+        (box_y + LABEL_PADDING) * scale + transform.ty,
+    );
+}
+
+/// Half the size of the bounding box of a rotated ellipse, stroke included
+fn ellipse_half_size(drawable: &Drawable) -> (f32, f32) {
+    let center = drawable.center();
+    let a = drawable.radius();
+    let b = drawable.semi_axis;
+    let rotation = (drawable.point2.y - center.y).atan2(drawable.point2.x - center.x);
+    let (sin, cos) = rotation.sin_cos();
+    (
+        ((a * cos).powi(2) + (b * sin).powi(2)).sqrt() + drawable.width,
+        ((a * sin).powi(2) + (b * cos).powi(2)).sqrt() + drawable.width,
+    )
+}
+
+/// Draw an ellipse as a rotated oval, optionally filled
+fn draw_ellipse(
+    drawable: &Drawable,
+    pixmap: &mut tiny_skia::PixmapMut,
+    transform: tiny_skia::Transform,
+) {
+    let center = drawable.center();
+    let a = drawable.radius();
+    let b = drawable.semi_axis;
+    let rotation = (drawable.point2.y - center.y).atan2(drawable.point2.x - center.x);
+
+    let Some(oval) = tiny_skia::Rect::from_xywh(center.x - a, center.y - b, 2. * a, 2. * b)
+        .and_then(tiny_skia::PathBuilder::from_oval)
+        .and_then(|path| {
+            path.transform(tiny_skia::Transform::from_rotate_at(
+                rotation.to_degrees(),
+                center.x,
+                center.y,
+            ))
+        })
+    else {
+        return;
+    };
+
+    if drawable.fill {
+        pixmap.fill_path(
+            &oval,
+            &drawable.style.fill_paint(drawable.color),
+            tiny_skia::FillRule::Winding,
+            transform,
+            None,
+        );
+    }
+
+    pixmap.stroke_path(
+        &oval,
+        &drawable.style.stroke_paint(drawable.color),
+        &drawable.style.stroke(drawable.width),
+        transform,
+        None,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    /// Identifier of the last drawable added
+    fn last_id(overlay: &OverlayRenderer) -> i32 {
+        overlay.drawables.last().unwrap().id
+    }
+
+    #[test]
+    fn intersection_keeps_closest_point() {
+        let mut overlay = OverlayRenderer::new(400, 400);
+        overlay.add_circle(Point { x: 100., y: 100. }, 50.);
+        let fixed = last_id(&overlay);
+        overlay.add_circle(Point { x: 160., y: 100. }, 50.);
+        let moving = last_id(&overlay);
+        assert!(overlay.add_construction(Construction::Intersection {
+            first: fixed,
+            second: moving,
+            index: 0,
+        }));
+        let point = overlay.drawables.last().unwrap().clone();
+        assert_close(point.point1.x, 130., 1e-3);
+        assert!(point.point1.y > 100.);
+
+        // Moving the second circle to the other side swaps the two intersection points
+        let circle = overlay.drawable(moving).unwrap().clone();
+        overlay
+            .edit_drawable(Drawable {
+                point1: Point { x: 40., y: 100. },
+                point2: Point { x: 90., y: 100. },
+                ..circle
+            })
+            .unwrap();
+        let rebuilt = overlay.drawable(point.id).unwrap();
+        assert_close(rebuilt.point1.x, 70., 1e-3);
+        assert!(rebuilt.point1.y > 100.);
+        assert_eq!(
+            rebuilt.construction,
+            Some(Construction::Intersection {
+                first: fixed,
+                second: moving,
+                index: 1,
+            })
+        );
+    }
+
+    /// Cross product of the directions of two drawables, zero if they are parallel
+    fn cross(a: &Drawable, b: &Drawable) -> f32 {
+        let (ax, ay) = (a.point2.x - a.point1.x, a.point2.y - a.point1.y);
+        let (bx, by) = (b.point2.x - b.point1.x, b.point2.y - b.point1.y);
+        (ax * by - ay * bx) / (ax.hypot(ay) * bx.hypot(by))
+    }
+
+    /// Overlay with a point, a line, a line parallel to it through the point, a line
+    /// perpendicular to the parallel through the point, an unrelated point and the
+    /// intersection of the parallel and the perpendicular
+    fn chain() -> (OverlayRenderer, [i32; 6]) {
+        let mut overlay = OverlayRenderer::new(400, 400);
+        overlay.add_point(Point { x: 200., y: 200. });
+        let point = last_id(&overlay);
+        overlay.add_line(Point { x: 0., y: 100. }, Point { x: 400., y: 150. });
+        let line = last_id(&overlay);
+        assert!(overlay.add_construction(Construction::Parallel {
+            line,
+            through: Anchor::Drawable(point),
+        }));
+        let parallel = last_id(&overlay);
+        assert!(overlay.add_construction(Construction::Perpendicular {
+            line: parallel,
+            through: Anchor::Drawable(point),
+        }));
+        let perpendicular = last_id(&overlay);
+        overlay.add_point(Point { x: 50., y: 50. });
+        let unrelated = last_id(&overlay);
+        assert!(overlay.add_construction(Construction::Intersection {
+            first: parallel,
+            second: perpendicular,
+            index: 0,
+        }));
+        let intersection = last_id(&overlay);
+        (
+            overlay,
+            [
+                point,
+                line,
+                parallel,
+                perpendicular,
+                unrelated,
+                intersection,
+            ],
+        )
+    }
+
+    #[test]
+    fn dependents_come_after_their_parents() {
+        let (mut overlay, [point, line, parallel, perpendicular, _, intersection]) = chain();
+        let ids = |dependents: Vec<Drawable>| dependents.iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(overlay.dependents(&[line])),
+            vec![parallel, perpendicular, intersection]
+        );
+        assert_eq!(
+            ids(overlay.dependents(&[point])),
+            vec![parallel, perpendicular, intersection]
+        );
+        assert_eq!(
+            ids(overlay.dependents(&[perpendicular])),
+            vec![intersection]
+        );
+
+        // Drawables restored in any order take back their place in the list
+        let removed = overlay.remove_with_dependents(&[line]);
+        for drawable in removed.into_iter().rev() {
+            overlay.insert_drawable(drawable);
+        }
+        assert!(overlay.drawables.windows(2).all(|w| w[0].id < w[1].id));
+        assert_eq!(
+            ids(overlay.dependents(&[line])),
+            vec![parallel, perpendicular, intersection]
+        );
+    }
+
+    #[test]
+    fn edit_rebuilds_chain() {
+        let (mut overlay, [point, line, parallel, perpendicular, unrelated, intersection]) =
+            chain();
+        let edited = overlay.drawable(line).unwrap().clone();
+        let (before, after) = overlay
+            .edit_drawable(Drawable {
+                point1: Point { x: 100., y: 0. },
+                point2: Point { x: 300., y: 400. },
+                ..edited
+            })
+            .unwrap();
+        let ids = [line, parallel, perpendicular, intersection];
+        assert_eq!(before.iter().map(|d| d.id).collect::<Vec<_>>(), ids);
+        assert_eq!(after.iter().map(|d| d.id).collect::<Vec<_>>(), ids);
+        assert!(overlay.drawable(unrelated).is_some());
+
+        let line = overlay.drawable(line).unwrap();
+        let parallel = overlay.drawable(parallel).unwrap();
+        let perpendicular = overlay.drawable(perpendicular).unwrap();
+        assert_close(cross(line, parallel), 0., 1e-4);
+        assert_close(cross(parallel, perpendicular).abs(), 1., 1e-4);
+
+        // Moving the point carries the whole chain along
+        overlay
+            .move_point(point, Point { x: 150., y: 250. })
+            .unwrap();
+        let intersection = overlay.drawable(intersection).unwrap();
+        assert_close(intersection.point1.x, 150., 1e-2);
+        assert_close(intersection.point1.y, 250., 1e-2);
+    }
+
+    #[test]
+    fn delete_cascades_to_dependents() {
+        let (mut overlay, [point, line, parallel, perpendicular, unrelated, intersection]) =
+            chain();
+        let removed = overlay.remove_with_dependents(&[perpendicular]);
+        assert_eq!(
+            removed.iter().map(|d| d.id).collect::<Vec<_>>(),
+            vec![perpendicular, intersection]
+        );
+        let removed = overlay.remove_with_dependents(&[point]);
+        assert_eq!(
+            removed.iter().map(|d| d.id).collect::<Vec<_>>(),
+            vec![point, parallel]
+        );
+        assert_eq!(
+            overlay.drawables.iter().map(|d| d.id).collect::<Vec<_>>(),
+            vec![line, unrelated]
+        );
+    }
+}
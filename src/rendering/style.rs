@@ -0,0 +1,165 @@
+//! Stroke and fill styles of the drawables
+//!
+//! Each drawable carries its own style on top of its color and width: opacity, dash
+//! pattern, line caps and joins, fill color of closed shapes and arrowheads of segments
+//! and half-lines.
+
+use super::overlay::Color;
+
+/// Opacity of the inside of filled shapes, for a fully opaque style
+const FILL_ALPHA: u8 = 64;
+
+/// Dash pattern of a stroke
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum DashPattern {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+    DashDot,
+}
+
+impl DashPattern {
+    /// All the dash patterns, in the order they are offered in the UI
+    pub const ALL: [DashPattern; 4] = [
+        DashPattern::Solid,
+        DashPattern::Dashed,
+        DashPattern::Dotted,
+        DashPattern::DashDot,
+    ];
+
+    /// Lengths of the dashes and gaps, proportional to the stroke width
+    ///
+    /// # Returns
+    ///
+    /// The intervals, or `None` for a solid stroke
+    fn intervals(&self, width: f32) -> Option<Vec<f32>> {
+        let width = width.max(1.);
+        match self {
+            DashPattern::Solid => None,
+            DashPattern::Dashed => Some(vec![4. * width, 2. * width]),
+            DashPattern::Dotted => Some(vec![width, 2. * width]),
+            DashPattern::DashDot => Some(vec![4. * width, 2. * width, width, 2. * width]),
+        }
+    }
+}
+
+/// Shape of the ends of a stroke
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum LineCap {
+    #[default]
+    Butt,
+    Round,
+    Square,
+}
+
+impl LineCap {
+    /// All the line caps, in the order they are offered in the UI
+    pub const ALL: [LineCap; 3] = [LineCap::Butt, LineCap::Round, LineCap::Square];
+}
+
+impl From<LineCap> for tiny_skia::LineCap {
+    fn from(cap: LineCap) -> tiny_skia::LineCap {
+        match cap {
+            LineCap::Butt => tiny_skia::LineCap::Butt,
+            LineCap::Round => tiny_skia::LineCap::Round,
+            LineCap::Square => tiny_skia::LineCap::Square,
+        }
+    }
+}
+
+/// Shape of the corners of a stroke
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum LineJoin {
+    Miter,
+    #[default]
+    Round,
+    Bevel,
+}
+
+impl LineJoin {
+    /// All the line joins, in the order they are offered in the UI
+    pub const ALL: [LineJoin; 3] = [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel];
+}
+
+impl From<LineJoin> for tiny_skia::LineJoin {
+    fn from(join: LineJoin) -> tiny_skia::LineJoin {
+        match join {
+            LineJoin::Miter => tiny_skia::LineJoin::Miter,
+            LineJoin::Round => tiny_skia::LineJoin::Round,
+            LineJoin::Bevel => tiny_skia::LineJoin::Bevel,
+        }
+    }
+}
+
+/// Style a drawable is rendered with, besides its color and width
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Style {
+    /// Opacity of the stroke, the fill being as much more transparent
+    pub alpha: u8,
+    pub dash: DashPattern,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Color of the inside of filled shapes, the stroke color if `None`
+    pub fill_color: Option<Color>,
+    /// Arrowhead on the first point of a segment or half-line
+    pub start_arrow: bool,
+    /// Arrowhead on the second point of a segment or half-line
+    pub end_arrow: bool,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            alpha: 255,
+            dash: DashPattern::default(),
+            cap: LineCap::default(),
+            join: LineJoin::default(),
+            fill_color: None,
+            start_arrow: false,
+            end_arrow: false,
+        }
+    }
+}
+
+impl Style {
+    /// Build the tiny-skia stroke of the style
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the stroke, which the dash pattern is proportional to
+    pub fn stroke(&self, width: f32) -> tiny_skia::Stroke {
+        tiny_skia::Stroke {
+            width,
+            line_cap: self.cap.into(),
+            line_join: self.join.into(),
+            dash: self
+                .dash
+                .intervals(width)
+                .and_then(|intervals| tiny_skia::StrokeDash::new(intervals, 0.)),
+            ..Default::default()
+        }
+    }
+
+    /// Build the paint of the stroke, arrowheads included
+    pub fn stroke_paint(&self, color: Color) -> tiny_skia::Paint<'static> {
+        let mut paint = tiny_skia::Paint::default();
+        paint.set_color_rgba8(color.r, color.g, color.b, self.alpha);
+        paint.anti_alias = true;
+        paint
+    }
+
+    /// Build the translucent paint of the inside of filled shapes
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The stroke color, used when the style has no fill color
+    pub fn fill_paint(&self, color: Color) -> tiny_skia::Paint<'static> {
+        let color = self.fill_color.unwrap_or(color);
+        let alpha = (FILL_ALPHA as u32 * self.alpha as u32 / 255) as u8;
+        let mut paint = tiny_skia::Paint::default();
+        paint.set_color_rgba8(color.r, color.g, color.b, alpha);
+        paint.anti_alias = true;
+        paint
+    }
+}
@@ -0,0 +1,120 @@
+//! Text rendering with an embedded font
+//!
+//! Labels are rasterized with DejaVu Sans, embedded in the executable so that annotated
+//! maps look the same whatever the fonts installed on the system.
+
+use std::sync::OnceLock;
+
+use ab_glyph::{point, Font, FontRef, GlyphId, PxScale, ScaleFont};
+use tiny_skia::{PixmapMut, PremultipliedColorU8};
+
+use super::overlay::Color;
+
+/// The embedded font file
+static FONT_DATA: &[u8] = include_bytes!("../../ui/fonts/DejaVuSans.ttf");
+
+/// The embedded font, parsed once
+fn font() -> &'static FontRef<'static> {
+    static FONT: OnceLock<FontRef<'static>> = OnceLock::new();
+    FONT.get_or_init(|| FontRef::try_from_slice(FONT_DATA).expect("embedded font is valid"))
+}
+
+/// Width of a single line of text, kerning included
+fn line_width<F: Font, SF: ScaleFont<F>>(font: &SF, line: &str) -> f32 {
+    let mut width = 0.;
+    let mut previous: Option<GlyphId> = None;
+    for c in line.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Size of a text once rendered, as width and height in pixels
+///
+/// # Arguments
+///
+/// * `text` - The text, possibly on several lines
+/// * `size` - The font size, in pixels
+pub fn measure(text: &str, size: f32) -> (f32, f32) {
+    let font = font().as_scaled(PxScale::from(size));
+    let width = text
+        .lines()
+        .map(|line| line_width(&font, line))
+        .fold(0., f32::max);
+    let lines = text.lines().count().max(1) as f32;
+    (
+        width,
+        lines * font.height() + (lines - 1.) * font.line_gap(),
+    )
+}
+
+/// Blend a pixel of a color over a pixmap
+fn blend(pixmap: &mut PixmapMut, x: i32, y: i32, color: Color, coverage: f32) {
+    let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return;
+    }
+    let alpha = coverage.clamp(0., 1.);
+    let pixel = &mut pixmap.pixels_mut()[(y * width + x) as usize];
+    let over = |source: u8, destination: u8| {
+        (source as f32 * alpha + destination as f32 * (1. - alpha)).round() as u8
+    };
+    let a = over(255, pixel.alpha());
+    // Premultiplied channels cannot exceed the alpha, whatever the rounding
+    let blended = PremultipliedColorU8::from_rgba(
+        over(color.r, pixel.red()).min(a),
+        over(color.g, pixel.green()).min(a),
+        over(color.b, pixel.blue()).min(a),
+        a,
+    );
+    if let Some(blended) = blended {
+        *pixel = blended;
+    }
+}
+
+/// Draw a text on a pixmap
+///
+/// # Arguments
+///
+/// * `pixmap` - The pixmap to draw on
+/// * `text` - The text, possibly on several lines
+/// * `size` - The font size, in pixels
+/// * `color` - The color of the text
+/// * `x` - The left of the text in the pixmap
+/// * `y` - The top of the text in the pixmap
+pub fn draw(pixmap: &mut PixmapMut, text: &str, size: f32, color: Color, x: f32, y: f32) {
+    let font = font().as_scaled(PxScale::from(size));
+    for (index, line) in text.lines().enumerate() {
+        let baseline = y + font.ascent() + index as f32 * (font.height() + font.line_gap());
+        let mut caret = x;
+        let mut previous: Option<GlyphId> = None;
+        for c in line.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(font.scale(), point(caret, baseline));
+            caret += font.h_advance(id);
+            previous = Some(id);
+
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                blend(
+                    pixmap,
+                    bounds.min.x as i32 + gx as i32,
+                    bounds.min.y as i32 + gy as i32,
+                    color,
+                    coverage,
+                );
+            });
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Tiled overlay surface
+//!
+//! The drawables are composited into a grid of fixed size tiles covering the map at the
+//! resolution it is displayed with, instead of one image per drawable. Only the tiles in
+//! view are kept. Adding, editing or removing a drawable invalidates the tiles under its
+//! area, and only these dirty tiles are rendered again.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+
+use slint::Image;
+use tiny_skia::{IntRect, Rect};
+
+/// Width and height of the tiles, in device pixels, the last row and column being smaller
+pub const TILE_SIZE: u32 = 512;
+
+/// Grid of tiles covering the map, with the tiles in view and the ones to be rendered again
+pub struct TiledSurface {
+    /// Width of the map, in map pixels
+    width: u32,
+    /// Height of the map, in map pixels
+    height: u32,
+    /// Device pixels per map pixel the tiles are rendered at
+    scale: f32,
+    columns: u32,
+    rows: u32,
+    /// Tiles in view, as last rendered
+    tiles: BTreeMap<usize, Image>,
+    dirty: BTreeSet<usize>,
+}
+
+impl TiledSurface {
+    /// Create a surface covering a map at its own resolution, all the tiles being in view
+    /// and dirty
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the map, in pixels
+    /// * `height` - The height of the map, in pixels
+    pub fn new(width: u32, height: u32) -> TiledSurface {
+        let mut surface = TiledSurface {
+            width,
+            height,
+            scale: 1.,
+            columns: width.div_ceil(TILE_SIZE),
+            rows: height.div_ceil(TILE_SIZE),
+            tiles: BTreeMap::new(),
+            dirty: BTreeSet::new(),
+        };
+        for index in 0..(surface.columns * surface.rows) as usize {
+            surface.tiles.insert(index, Image::default());
+            surface.dirty.insert(index);
+        }
+        surface
+    }
+
+    /// Device pixels per map pixel the tiles are rendered at
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Size of the map at the resolution of the tiles, as width and height in device pixels
+    fn device_size(&self) -> (u32, u32) {
+        (
+            (self.width as f32 * self.scale).ceil() as u32,
+            (self.height as f32 * self.scale).ceil() as u32,
+        )
+    }
+
+    /// Set the part of the map in view and the resolution it is displayed with
+    ///
+    /// All the tiles are dropped when the resolution changes. Otherwise the tiles out of view
+    /// are dropped, and the tiles coming into view are marked as dirty.
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The part of the map in view, in map pixels
+    /// * `scale` - The number of device pixels per map pixel
+    pub fn set_view(&mut self, view: Rect, scale: f32) {
+        if !scale.is_finite() || scale <= 0. {
+            return;
+        }
+        if scale != self.scale {
+            self.scale = scale;
+            let (width, height) = self.device_size();
+            self.columns = width.div_ceil(TILE_SIZE);
+            self.rows = height.div_ceil(TILE_SIZE);
+            self.tiles.clear();
+            self.dirty.clear();
+        }
+        let in_view = self.tiles_under(view);
+        self.tiles.retain(|index, _| in_view.contains(index));
+        self.dirty.retain(|index| in_view.contains(index));
+        for index in in_view {
+            if let Entry::Vacant(tile) = self.tiles.entry(index) {
+                tile.insert(Image::default());
+                self.dirty.insert(index);
+            }
+        }
+    }
+
+    /// Tiles under an area of the map, along with a margin of one device pixel
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - The area, in map pixels
+    fn tiles_under(&self, area: Rect) -> BTreeSet<usize> {
+        let mut tiles = BTreeSet::new();
+        if self.columns == 0 || self.rows == 0 {
+            return tiles;
+        }
+        let tile = |position: f32| ((position * self.scale - 1.).max(0.) / TILE_SIZE as f32) as u32;
+        let (left, top) = (tile(area.left()), tile(area.top()));
+        let end = |position: f32| ((position * self.scale + 1.).max(0.) / TILE_SIZE as f32) as u32;
+        let right = end(area.right()).min(self.columns - 1);
+        let bottom = end(area.bottom()).min(self.rows - 1);
+        for row in top..=bottom {
+            for column in left..=right {
+                tiles.insert((row * self.columns + column) as usize);
+            }
+        }
+        tiles
+    }
+
+    /// Indices of the tiles in view, row by row
+    pub fn tiles(&self) -> Vec<usize> {
+        self.tiles.keys().copied().collect()
+    }
+
+    /// Area covered by a tile, in device pixels
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the tile, row by row
+    pub fn area(&self, index: usize) -> Option<IntRect> {
+        let (width, height) = self.device_size();
+        let column = index as u32 % self.columns.max(1);
+        let row = index as u32 / self.columns.max(1);
+        let x = column * TILE_SIZE;
+        let y = row * TILE_SIZE;
+        IntRect::from_xywh(
+            x as i32,
+            y as i32,
+            TILE_SIZE.min(width.saturating_sub(x)),
+            TILE_SIZE.min(height.saturating_sub(y)),
+        )
+    }
+
+    /// Image of a tile, as last rendered, empty if the tile is out of view
+    pub fn tile(&self, index: usize) -> Image {
+        self.tiles.get(&index).cloned().unwrap_or_default()
+    }
+
+    /// Replace the image of a tile in view once rendered
+    pub fn set_tile(&mut self, index: usize, image: Image) {
+        if let Some(tile) = self.tiles.get_mut(&index) {
+            *tile = image;
+        }
+    }
+
+    /// Mark the tiles in view under an area of the map as dirty
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - The area, in map pixels, nothing being invalidated if `None`
+    pub fn invalidate(&mut self, area: Option<Rect>) {
+        let Some(area) = area else {
+            return;
+        };
+        for index in self.tiles_under(area) {
+            if self.tiles.contains_key(&index) {
+                self.dirty.insert(index);
+            }
+        }
+    }
+
+    /// Mark all the tiles in view as dirty, e.g. once all the drawables are replaced
+    pub fn invalidate_all(&mut self) {
+        self.dirty = self.tiles.keys().copied().collect();
+    }
+
+    /// Retrieve the dirty tiles, which are expected to be rendered again
+    ///
+    /// # Returns
+    ///
+    /// The indices of the dirty tiles, in increasing order
+    pub fn take_dirty(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.dirty).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalidate_marks_overlapping_tiles() {
+        let mut surface = TiledSurface::new(1500, 1100);
+        assert_eq!(surface.take_dirty(), (0..9).collect::<Vec<_>>());
+        surface.invalidate(None);
+        assert!(surface.take_dirty().is_empty());
+        surface.invalidate(Rect::from_ltrb(600., 600., 700., 700.));
+        assert_eq!(surface.take_dirty(), vec![4]);
+        // Across the border between two columns
+        surface.invalidate(Rect::from_ltrb(1000., 100., 1100., 200.));
+        assert_eq!(surface.take_dirty(), vec![1, 2]);
+    }
+
+    #[test]
+    fn view_change_exposes_new_tiles() {
+        let mut surface = TiledSurface::new(1500, 1100);
+        surface.set_view(Rect::from_ltrb(0., 0., 500., 500.).unwrap(), 1.);
+        assert_eq!(surface.tiles(), vec![0]);
+        assert_eq!(surface.take_dirty(), vec![0]);
+        // Only the tile coming into view is to be rendered
+        surface.set_view(Rect::from_ltrb(0., 0., 1000., 500.).unwrap(), 1.);
+        assert_eq!(surface.tiles(), vec![0, 1]);
+        assert_eq!(surface.take_dirty(), vec![1]);
+        // Tiles out of view are dropped
+        surface.set_view(Rect::from_ltrb(600., 0., 1000., 500.).unwrap(), 1.);
+        assert_eq!(surface.tiles(), vec![1]);
+        assert!(surface.take_dirty().is_empty());
+        // A new resolution renders everything in view again, on a finer grid
+        surface.set_view(Rect::from_ltrb(0., 0., 500., 500.).unwrap(), 2.);
+        assert_eq!(surface.tiles(), vec![0, 1, 6, 7]);
+        assert_eq!(surface.take_dirty(), vec![0, 1, 6, 7]);
+    }
+
+    #[test]
+    fn last_tiles_are_clipped_to_the_map() {
+        let surface = TiledSurface::new(1500, 1100);
+        assert_eq!(surface.area(2), IntRect::from_xywh(1024, 0, 476, 512));
+        assert_eq!(surface.area(8), IntRect::from_xywh(1024, 1024, 476, 76));
+    }
+}
@@ -64,4 +64,26 @@ impl IdGenerator {
         log::debug!("New id: {}", self.id);
         self.id
     }
+    /// Makes sure identifiers generated afterwards are greater than the given one
+    ///
+    /// Used when an entity is restored with its former identifier, so that no
+    /// other entity can be given the same identifier later on.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier already in use
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use utils::IdGenerator;
+    ///
+    /// let mut generator = IdGenerator::new();
+    /// generator.reserve(5);
+    ///
+    /// assert_eq!(generator.get_id(), 6);
+    /// ```
+    pub fn reserve(&mut self, id: i32) {
+        self.id = self.id.max(id);
+    }
 }
\ No newline at end of file